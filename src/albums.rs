@@ -0,0 +1,105 @@
+use crate::output::round3;
+use crate::playlist::{Entry, Track};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const ALBUM_PREFIX: &str = "#EXTALB:";
+
+/*
+Which album a track belongs to: its #EXTALB line, or the directory it's in,
+which is how most libraries keep an album together.
+*/
+pub fn key(entry: &Entry) -> String {
+    entry
+        .directives
+        .iter()
+        .find_map(|d| d.strip_prefix(ALBUM_PREFIX))
+        .map(|a| a.trim().to_string())
+        .unwrap_or_else(|| {
+            Path::new(&entry.path)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+}
+
+/// The analysed tracks of one album, for --album-report
+#[derive(Clone, Serialize)]
+pub struct Album {
+    pub album: String,
+    pub tracks: usize,
+    pub mean_loudness: f32,
+    /// Loudest track minus quietest, in LU
+    pub loudness_spread: f32,
+    pub mean_cross: f32,
+    /// How many standard deviations of the whole run the mean loudness is off its mean
+    pub deviation: f32,
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+/*
+Groups the analysed tracks by album, most anomalous first: the albums whose
+mean loudness is furthest from the run's, measured in the run's standard
+deviations of track loudness, since a bad mastering batch or rip setting moves
+a whole album at once. Silent tracks, which measure -inf, are left out of the
+loudness figures.
+*/
+pub fn albums(tracks: &[Track]) -> Vec<Album> {
+    let mut by_album: BTreeMap<String, Vec<(f32, f32)>> = BTreeMap::new();
+    for track in tracks {
+        if let Some(r) = track.analyzed().filter(|r| r.loudness.is_finite()) {
+            by_album
+                .entry(key(&track.entry))
+                .or_default()
+                .push((r.loudness, r.start_next));
+        }
+    }
+
+    let loudness: Vec<f32> = by_album.values().flatten().map(|(l, _)| *l).collect();
+    if loudness.is_empty() {
+        return Vec::new();
+    }
+    let run_mean = mean(&loudness);
+    let squares: Vec<f32> = loudness.iter().map(|l| (l - run_mean).powi(2)).collect();
+    let run_deviation = mean(&squares).sqrt();
+
+    let mut albums: Vec<Album> = by_album
+        .into_iter()
+        .map(|(album, values)| {
+            let (loudness, cross): (Vec<f32>, Vec<f32>) = values.into_iter().unzip();
+            let mean_loudness = mean(&loudness);
+            let max = loudness.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let min = loudness.iter().copied().fold(f32::INFINITY, f32::min);
+            Album {
+                album,
+                tracks: loudness.len(),
+                mean_loudness: round3(mean_loudness),
+                loudness_spread: round3(max - min),
+                mean_cross: round3(mean(&cross)),
+                deviation: if run_deviation > 0. {
+                    round3((mean_loudness - run_mean).abs() / run_deviation)
+                } else {
+                    0.
+                },
+            }
+        })
+        .collect();
+    albums.sort_by(|a, b| b.deviation.total_cmp(&a.deviation));
+    albums
+}
+
+/// One row per album, as a table
+pub fn render(albums: &[Album]) -> String {
+    let mut s = String::from("  tracks  loudness  spread   cross  deviation  album\n");
+    for a in albums {
+        s.push_str(&format!(
+            "  {:>6}  {:>8.1}  {:>6.1}  {:>6.2}  {:>9.2}  {}\n",
+            a.tracks, a.mean_loudness, a.loudness_spread, a.mean_cross, a.deviation, a.album
+        ));
+    }
+    s
+}
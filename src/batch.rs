@@ -0,0 +1,132 @@
+use crate::playlist;
+use crate::summary::{self, Finished};
+use crate::AnalyzeResult;
+use clap::ArgMatches;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/*
+What a directory given with --per-input-output is taken to hold playlists of:
+m3u, PLS and CUE sheets, compressed or not. A CSV needs its --path-column, so
+those are only processed when named. Our own outputs are left out, or every
+run would process the last one's again.
+*/
+fn is_playlist(path: &Path) -> bool {
+    let plain = playlist::without_gz(path);
+    let extension = plain
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let processed = plain
+        .file_stem()
+        .is_some_and(|s| s.to_string_lossy().contains("-processed"));
+    ["m3u", "m3u8", "pls", "cue"].contains(&extension.as_str()) && !processed
+}
+
+// the playlists in `dir`, and with `recursive` in the directories under it
+fn find(dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // not followed into symlinked directories, which can lead back up the tree
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            if recursive {
+                find(&path, recursive, found)?;
+            }
+        } else if path.is_file() && is_playlist(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/*
+The playlists to process: the files given, and the playlists in the
+directories given, sorted. With `recursive` also those further down.
+*/
+pub fn expand(paths: &[PathBuf], recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            inputs.push(path.clone());
+            continue;
+        }
+        let mut found = Vec::new();
+        find(path, recursive, &mut found)?;
+        found.sort();
+        inputs.extend(found);
+    }
+    Ok(inputs)
+}
+
+/*
+A file in several of the playlists is analysed once, by the first to get to
+it, whose result the others take; one that gets there while it's still being
+analysed waits for it. Keyed like the cache, by file and settings.
+*/
+type Analysed = Arc<OnceLock<Result<AnalyzeResult, String>>>;
+static ANALYSED: Mutex<BTreeMap<(String, String), Analysed>> = Mutex::new(BTreeMap::new());
+
+// how many entries took another input's result
+static SHARED: AtomicUsize = AtomicUsize::new(0);
+
+/// How many entries used the analysis of another input so far
+pub fn shared() -> usize {
+    SHARED.load(Ordering::Relaxed)
+}
+
+/// Analyses a track with `analyze` unless another input has, and whether it was another one
+pub fn analyze_once(
+    key: (String, String),
+    analyze: impl FnOnce() -> Result<AnalyzeResult, String>,
+) -> (Result<AnalyzeResult, String>, bool) {
+    let cell = ANALYSED.lock().unwrap().entry(key).or_default().clone();
+    let mut ours = false;
+    let result = cell.get_or_init(|| {
+        ours = true;
+        analyze()
+    });
+    if !ours {
+        SHARED.fetch_add(1, Ordering::Relaxed);
+    }
+    (result.clone(), !ours)
+}
+
+/*
+Runs `run` for every input, each on a thread of its own with a summary of its
+own, and returns how each one ended, in the order given. `run` ends with
+summary::exit, which on these threads unwinds back to here with the summary
+instead of ending the process. A panic ends only the input it happened in.
+*/
+pub fn run_all(
+    inputs: &[PathBuf],
+    matches: &ArgMatches,
+    run: impl Fn(&Path) + Sync,
+) -> Vec<Finished> {
+    thread::scope(|scope| {
+        let threads: Vec<_> = inputs
+            .iter()
+            .map(|path| {
+                let run = &run;
+                scope.spawn(move || {
+                    summary::start_input(path, matches);
+                    match panic::catch_unwind(AssertUnwindSafe(|| run(path))) {
+                        Err(payload) => match payload.downcast::<Finished>() {
+                            Ok(finished) => *finished,
+                            Err(_) => summary::finish(crate::EXIT_FATAL),
+                        },
+                        Ok(()) => summary::finish(0),
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    })
+}
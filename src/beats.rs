@@ -0,0 +1,63 @@
+use crate::jobs;
+use std::process::Command;
+
+// a beat further than this after the loudness cue belongs to something else
+const MAX_SNAP: f32 = 1.;
+
+/*
+Beat times of a file in seconds, from aubio's beat tracker. It's a heuristic:
+it locks on to a steady pulse, so rubato, tempo changes or a beatless intro give
+beats that are nowhere near what a DJ would count, or none at all.
+*/
+fn beats(path: &str) -> Result<Vec<f32>, String> {
+    let output = jobs::output(Command::new("aubio").arg("beat").arg("-i").arg(path))
+        .map_err(|e| format!("couldn't run aubio: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.trim().parse().ok())
+        .collect())
+}
+
+/// The first beat at or after `cue`, if there's one within MAX_SNAP seconds
+fn snap(beats: &[f32], cue: f32) -> Option<f32> {
+    beats
+        .iter()
+        .copied()
+        .find(|&b| b >= cue)
+        .filter(|&b| b - cue <= MAX_SNAP)
+}
+
+/*
+Moves the cue-in to the first beat after it, returning the new cue and how far
+it moved. aubio reads the whole file, so its beats are moved by `offset` into
+the window (or CUE track) the cue was found in. Without beats close enough, or
+if aubio fails, the loudness cue stays.
+*/
+pub fn align(path: &str, offset: f32, cue: f32, verbose: bool) -> (f32, Option<f32>) {
+    let beats: Vec<f32> = match beats(path) {
+        Ok(b) => b.iter().map(|b| b - offset).collect(),
+        Err(e) => {
+            eprintln!("Couldn't find the beats of {}: {}", path, e);
+            return (cue, None);
+        }
+    };
+
+    match snap(&beats, cue) {
+        Some(beat) => {
+            if verbose {
+                say!(
+                    "{}: cue-in moved from {:.3}s to the beat at {:.3}s",
+                    path,
+                    cue,
+                    beat
+                );
+            }
+            (beat, Some(beat - cue))
+        }
+        None => (cue, None),
+    }
+}
@@ -0,0 +1,208 @@
+use crate::jobs;
+use crate::playlist::{self, Entry};
+use crate::AnalyzeResult;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use unicode_normalization::UnicodeNormalization;
+
+/// How hard to check that a cached file is still the one that was analysed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Verify {
+    /// Compare the duration ffprobe reports
+    Duration,
+    /// Compare the duration, codec, sample rate and channel count
+    Stream,
+}
+
+/// What ffprobe says about a file, cheap next to a full analysis
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Probe {
+    duration: f32,
+    codec: String,
+    sample_rate: String,
+    channels: u64,
+}
+
+impl Probe {
+    fn matches(&self, other: &Probe, level: Verify) -> bool {
+        // ffprobe prints microseconds, anything past that is rounding
+        let same_duration = (self.duration - other.duration).abs() < 0.001;
+        match level {
+            Verify::Duration => same_duration,
+            Verify::Stream => same_duration && self == other,
+        }
+    }
+}
+
+pub fn probe(path: &str) -> Option<Probe> {
+    let output = jobs::output(
+        Command::new("ffprobe")
+            .arg("-v")
+            .arg("error")
+            .arg("-select_streams")
+            .arg("a:0")
+            .arg("-show_entries")
+            .arg("format=duration:stream=codec_name,sample_rate,channels")
+            .arg("-of")
+            .arg("json")
+            .arg(path),
+    )
+    .ok()?;
+
+    let probe: Value = serde_json::from_slice(&output.stdout).ok()?;
+    let text = |pointer: &str| {
+        probe
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    Some(Probe {
+        duration: text("/format/duration").parse().ok()?,
+        codec: text("/streams/0/codec_name"),
+        sample_rate: text("/streams/0/sample_rate"),
+        channels: probe
+            .pointer("/streams/0/channels")
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    fingerprint: String,
+    settings: String,
+    /// Only recorded with --reanalyze-on-mismatch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    probe: Option<Probe>,
+    result: AnalyzeResult,
+}
+
+/// Analysis results of earlier runs, by file
+pub struct Cache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+    /// Keys inserted since the file was read
+    added: Vec<String>,
+}
+
+// one save at a time, for the inputs of --per-input-output sharing a cache
+static SAVING: Mutex<()> = Mutex::new(());
+
+/*
+Tracks of a CUE sheet share their file, so the region is part of the key. The
+path is composed (NFC), so a cache written on macOS, where file names are
+decomposed, still finds the files of a Linux copy of the same tree.
+*/
+pub fn key(entry: &Entry) -> String {
+    let path: String = entry.path.nfc().collect();
+    match &entry.region {
+        Some(r) => format!("{}#{}-{:?}", path, r.start, r.end),
+        None => path,
+    }
+}
+
+impl Cache {
+    /// Reads the cache file. A missing file is an empty cache, it gets created
+    /// when the cache is saved.
+    pub fn load(path: &Path) -> io::Result<Cache> {
+        Ok(Cache {
+            path: path.to_path_buf(),
+            entries: Cache::read(path)?,
+            added: Vec::new(),
+        })
+    }
+
+    fn read(path: &Path) -> io::Result<HashMap<String, CacheEntry>> {
+        let entries = match fs::read_to_string(path) {
+            Ok(c) => serde_json::from_str(&c)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(entries)
+    }
+
+    /*
+    A cached result is used when the file's size and mtime are what they were,
+    and it was analysed with the same settings. That misses a file replaced by
+    one of the same size within the same second, which `verify` is there for:
+    ffprobe has a look at the file and has to agree with what it said last time.
+    Entries cached without a probe can't be verified, so they're analysed again.
+    */
+    pub fn get(
+        &self,
+        entry: &Entry,
+        settings: &str,
+        verify: Option<Verify>,
+    ) -> Option<&AnalyzeResult> {
+        let cached = self.entries.get(&key(entry))?;
+        if cached.settings != settings
+            || playlist::fingerprint(&entry.path).as_deref() != Some(&cached.fingerprint)
+        {
+            return None;
+        }
+
+        if let Some(level) = verify {
+            let matches = match (&cached.probe, probe(&entry.path)) {
+                (Some(old), Some(new)) => old.matches(&new, level),
+                _ => false,
+            };
+            if !matches {
+                say!(
+                    "{}: cached result doesn't match the file, analysing again",
+                    entry.path
+                );
+                return None;
+            }
+        }
+
+        Some(&cached.result)
+    }
+
+    pub fn insert(
+        &mut self,
+        entry: &Entry,
+        settings: &str,
+        result: &AnalyzeResult,
+        verify: Option<Verify>,
+    ) {
+        let fingerprint = match playlist::fingerprint(&entry.path) {
+            Some(f) => f,
+            None => return,
+        };
+        let probe = verify.and_then(|_| probe(&entry.path));
+        self.added.push(key(entry));
+        self.entries.insert(
+            key(entry),
+            CacheEntry {
+                fingerprint,
+                settings: settings.to_string(),
+                probe,
+                result: result.clone(),
+            },
+        );
+    }
+
+    /*
+    What was added goes into the file as it is now rather than as it was read,
+    so runs sharing the cache don't write over each other's results.
+    */
+    pub fn save(&self) -> io::Result<()> {
+        let _saving = SAVING.lock().unwrap();
+        let mut entries = Cache::read(&self.path).unwrap_or_default();
+        for key in &self.added {
+            entries.insert(key.clone(), self.entries[key].clone());
+        }
+        let json = serde_json::to_string(&entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, json)
+    }
+}
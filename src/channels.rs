@@ -0,0 +1,75 @@
+use crate::jobs;
+use std::process::Command;
+
+/*
+Per-channel levels come from a separate astats pass rather than the ebur128
+one: ebur128 only reports the summed programme loudness. astats prints a block
+per channel followed by an "Overall" block, and we keep the RMS level of each
+channel in dBFS. A dead channel comes out as -inf.
+*/
+pub fn measure_channel_levels(path: &str) -> Result<Vec<f32>, String> {
+    let output = jobs::output(
+        Command::new("ffmpeg")
+            .arg("-hide_banner")
+            .arg("-i")
+            .arg(path)
+            .arg("-vn")
+            .arg("-af")
+            .arg("astats")
+            .arg("-f")
+            .arg("null")
+            .arg("null"),
+    )
+    .map_err(|e| format!("couldn't run ffmpeg: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut levels = Vec::new();
+    let mut in_channel = false;
+
+    for line in stderr.lines() {
+        if !line.starts_with("[Parsed_astats") {
+            continue;
+        }
+        // strip the "[Parsed_astats_0 @ 0x...] " prefix
+        let field = match line.find("] ") {
+            Some(i) => &line[i + 2..],
+            None => continue,
+        };
+
+        if field.starts_with("Channel:") {
+            in_channel = true;
+        } else if field.starts_with("Overall") {
+            break;
+        } else if in_channel {
+            if let Some(value) = field.strip_prefix("RMS level dB:") {
+                let level = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| String::from("couldn't parse a channel level"))?;
+                levels.push(level);
+                in_channel = false;
+            }
+        }
+    }
+
+    if levels.is_empty() {
+        return Err(String::from("astats reported no channels"));
+    }
+
+    Ok(levels)
+}
+
+/// Difference in dB between the loudest and the quietest channel
+pub fn imbalance(levels: &[f32]) -> Option<f32> {
+    if levels.len() < 2 {
+        return None;
+    }
+    let max = levels.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let min = levels.iter().cloned().fold(f32::INFINITY, f32::min);
+    // all channels silent is balanced, if useless
+    if max == f32::NEG_INFINITY {
+        return Some(0.);
+    }
+    Some(max - min)
+}
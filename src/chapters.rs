@@ -0,0 +1,100 @@
+use rust_boundaries::Measurement;
+
+// Tunables of the chapter segmentation. Not a doc comment on purpose, clap would
+// use it as the about text of whatever flattens it.
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct ChapterSettings {
+    /// Chapters: LU below the loudness that counts as a quiet stretch
+    #[arg(long, default_value_t = 12.)]
+    pub chapter_level: f32,
+
+    /// Chapters: seconds a quiet stretch has to last to separate two chapters
+    #[arg(long, default_value_t = 2.)]
+    pub chapter_gap: f32,
+
+    /// Chapters: shortest chapter in seconds, shorter ones are merged into the one before
+    #[arg(long, default_value_t = 30.)]
+    pub chapter_min_length: f32,
+
+    /// How to write chapters
+    #[arg(long, value_enum, default_value_t = ChapterStyle::Webvtt)]
+    pub chapter_style: ChapterStyle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChapterStyle {
+    /// WebVTT cues, one per chapter
+    Webvtt,
+    /// ffmpeg's ;FFMETADATA1 [CHAPTER] sections
+    Ffmetadata,
+}
+
+// seconds of momentary loudness averaged together, so a drum break isn't a gap
+const SMOOTHING: f32 = 3.;
+
+/*
+Splits a track into chapters where it goes quiet for a while, the way a
+podcast pauses between segments or a mix breathes between tunes. The momentary
+loudness is smoothed first, then every stretch of at least `chapter_gap`
+seconds below `chapter_level` LU under the track's loudness ends a chapter, and
+the next one starts where the audio comes back. Chapters shorter than
+`chapter_min_length` are merged into the one before, so a short sting doesn't
+become a chapter of its own.
+
+Returns the start times, the first one always 0, each chapter lasting until
+the next one starts.
+*/
+pub fn segment(measure: &[Measurement], loudness: f32, settings: &ChapterSettings) -> Vec<f32> {
+    // blocks are 0.1s apart
+    let half = (SMOOTHING * 5.) as usize;
+    let smoothed: Vec<f32> = (0..measure.len())
+        .map(|i| {
+            let window = &measure[i.saturating_sub(half)..(i + half + 1).min(measure.len())];
+            window
+                .iter()
+                .map(|&Measurement { momentary: m, .. }| m)
+                .sum::<f32>()
+                / window.len() as f32
+        })
+        .collect();
+
+    let threshold = loudness - settings.chapter_level;
+    let mut starts = vec![0.];
+    let mut i = 0;
+    while i < measure.len() {
+        if smoothed[i] >= threshold {
+            i += 1;
+            continue;
+        }
+        let gap_start = i;
+        while i < measure.len() && smoothed[i] < threshold {
+            i += 1;
+        }
+        // a quiet start or end isn't between two chapters
+        if gap_start > 0
+            && i < measure.len()
+            && measure[i - 1].time - measure[gap_start].time >= settings.chapter_gap
+        {
+            // smoothing blurs where the audio comes back, the raw blocks don't
+            let back = (i.saturating_sub(half)..i)
+                .find(|&j| measure[j].momentary >= threshold)
+                .unwrap_or(i);
+            // same 400ms block correction as the cue
+            starts.push(f32::max(measure[back].time - 0.4, 0.));
+        }
+    }
+
+    // going backwards, the next chapter's start is final by the time it's needed
+    let end = measure.last().map_or(0., |&Measurement { time: t, .. }| t);
+    let mut kept: Vec<f32> = Vec::with_capacity(starts.len());
+    let mut next = end;
+    for (k, &start) in starts.iter().enumerate().rev() {
+        if k == 0 || next - start >= settings.chapter_min_length {
+            kept.push(start);
+            next = start;
+        }
+    }
+    kept.reverse();
+
+    kept
+}
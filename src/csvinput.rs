@@ -0,0 +1,102 @@
+use crate::playlist::{self, Entry};
+use std::io;
+use std::path::Path;
+
+/*
+Splits CSV into records of fields, each record with the line it starts on.
+Fields in double quotes can hold commas, line breaks and doubled "" quotes.
+*/
+pub fn records(contents: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = contents.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut fields)));
+                line += 1;
+                start = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((start, fields));
+    }
+    records
+}
+
+// rows of notes, breaks and the like have something that isn't a file name there
+fn looks_like_path(field: &str) -> bool {
+    !field.is_empty()
+        && (field.contains(['/', '\\'])
+            || Path::new(field)
+                .extension()
+                .is_some_and(|e| !e.is_empty() && !e.to_string_lossy().contains(' ')))
+}
+
+/*
+Reads the file paths of a CSV export, like the day's log from a traffic or
+playout system, from `column`: a column number counting from 1, or the name of
+a column in the header row. Rows whose field there is empty or doesn't look
+like a path (a note, a break) are left out; the second value is how many. Each
+entry's line is the row's line in the file.
+*/
+pub fn read_csv(path: &Path, column: &str) -> io::Result<(Vec<Entry>, usize)> {
+    let contents = playlist::read_input(path)?;
+    let mut records = records(&contents).into_iter();
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let index = match column.parse::<usize>() {
+        Ok(0) => return Err(invalid(String::from("columns are counted from 1"))),
+        Ok(n) => n - 1,
+        Err(_) => {
+            let (_, header) = records
+                .next()
+                .ok_or_else(|| invalid(String::from("no header row")))?;
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(column))
+                .ok_or_else(|| invalid(format!("no column named '{}' in the header", column)))?
+        }
+    };
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for (line, fields) in records {
+        // blank lines aren't rows
+        if fields.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        match fields.get(index).map(|f| f.trim()) {
+            Some(field) if looks_like_path(field) => entries.push(Entry {
+                path: field.to_string(),
+                directives: Vec::new(),
+                line,
+                region: None,
+            }),
+            _ => skipped += 1,
+        }
+    }
+
+    Ok((entries, skipped))
+}
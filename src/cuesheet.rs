@@ -0,0 +1,181 @@
+use crate::playlist::{self, Entry, Region};
+use std::io;
+use std::path::Path;
+
+// CUE times are MM:SS:FF with 75 frames (CD sectors) per second
+fn parse_msf(s: &str) -> Option<f32> {
+    let parts: Vec<u32> = s
+        .split(':')
+        .map(|x| x.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [m, s, f] if s < 60 && f < 75 => Some(m as f32 * 60. + s as f32 + f as f32 / 75.),
+        _ => None,
+    }
+}
+
+// splits a line on whitespace, keeping "quoted strings" together
+fn tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+struct CueTrack {
+    file: String,
+    line: usize,
+    raw: bool,
+    start: f32,
+    title: Option<String>,
+    performer: Option<String>,
+}
+
+/*
+Reads the audio tracks of a CUE sheet as playlist entries, each one the region
+of its file between its INDEX 01 and the next track's. The subset understood:
+
+- FILE "name" BINARY|MOTOROLA|WAVE|MP3|AIFF, relative to the sheet's directory.
+  BINARY is headerless CD audio (16-bit little endian stereo at 44.1kHz),
+  anything else has to be a file ffmpeg can open on its own. MOTOROLA, the big
+  endian variant, isn't supported.
+- TRACK nn AUDIO. Data tracks (MODE1/2352 and friends) are skipped.
+- INDEX 01 mm:ss:ff, where the track starts. Other indexes, PREGAP and POSTGAP
+  are ignored, so pregaps are heard at the end of the previous track.
+- TITLE and PERFORMER, for an #EXTINF line. A PERFORMER before the first TRACK
+  is used for tracks without one of their own.
+
+Everything else (REM, CATALOG, FLAGS, ISRC, ...) is skipped.
+*/
+pub fn read_cue(path: &Path) -> io::Result<Vec<Entry>> {
+    let contents = playlist::read_input(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut file: Option<(String, bool)> = None;
+    let mut disc_performer = None;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    // the TRACK being read, None before the first one or inside a data track
+    let mut current: Option<CueTrack> = None;
+    let mut in_track = false;
+
+    for (n, line) in contents.lines().enumerate() {
+        let tokens = tokens(line);
+        let keyword = match tokens.first() {
+            Some(k) => k.to_uppercase(),
+            None => continue,
+        };
+
+        match keyword.as_str() {
+            "FILE" => {
+                let (name, kind) = match &tokens[1..] {
+                    [name, kind] => (name, kind.to_uppercase()),
+                    _ => return Err(invalid(format!("line {}: malformed FILE", n + 1))),
+                };
+                if kind == "MOTOROLA" {
+                    return Err(invalid(format!(
+                        "line {}: big endian (MOTOROLA) images aren't supported",
+                        n + 1
+                    )));
+                }
+                let full = dir.join(name).to_string_lossy().to_string();
+                file = Some((full, kind == "BINARY"));
+            }
+            "TRACK" => {
+                if let Some(t) = current.take() {
+                    tracks.push(t);
+                }
+                in_track = true;
+                let audio = tokens
+                    .get(2)
+                    .is_some_and(|t| t.eq_ignore_ascii_case("AUDIO"));
+                if audio {
+                    let (file, raw) = file
+                        .clone()
+                        .ok_or_else(|| invalid(format!("line {}: TRACK before FILE", n + 1)))?;
+                    current = Some(CueTrack {
+                        file,
+                        line: n + 1,
+                        raw,
+                        start: f32::NAN,
+                        title: None,
+                        performer: None,
+                    });
+                }
+            }
+            "INDEX" if tokens.get(1).map(String::as_str) == Some("01") => {
+                if let Some(t) = &mut current {
+                    t.start = tokens
+                        .get(2)
+                        .and_then(|s| parse_msf(s))
+                        .ok_or_else(|| invalid(format!("line {}: malformed INDEX", n + 1)))?;
+                }
+            }
+            "TITLE" => {
+                if let Some(t) = &mut current {
+                    t.title = tokens.get(1).cloned();
+                }
+            }
+            "PERFORMER" => {
+                if let Some(t) = &mut current {
+                    t.performer = tokens.get(1).cloned();
+                } else if !in_track {
+                    disc_performer = tokens.get(1).cloned();
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(t) = current.take() {
+        tracks.push(t);
+    }
+
+    if let Some(t) = tracks.iter().find(|t| t.start.is_nan()) {
+        return Err(invalid(format!("a track of {} has no INDEX 01", t.file)));
+    }
+
+    let mut entries = Vec::with_capacity(tracks.len());
+    for (i, t) in tracks.iter().enumerate() {
+        // a track ends where the next one in the same file starts, the last one at the end of it
+        let end = tracks
+            .get(i + 1)
+            .filter(|next| next.file == t.file)
+            .map(|next| next.start);
+
+        let mut directives = Vec::new();
+        let performer = t.performer.as_ref().or(disc_performer.as_ref());
+        match (performer, &t.title) {
+            (Some(p), Some(title)) => directives.push(format!("#EXTINF:-1,{} - {}", p, title)),
+            (None, Some(title)) => directives.push(format!("#EXTINF:-1,{}", title)),
+            _ => {}
+        }
+
+        entries.push(Entry {
+            path: t.file.clone(),
+            directives,
+            line: t.line,
+            region: Some(Region {
+                start: t.start,
+                end,
+                raw: t.raw,
+            }),
+        });
+    }
+
+    Ok(entries)
+}
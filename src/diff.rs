@@ -0,0 +1,245 @@
+use crate::playlist::{Annotation, Track};
+use crate::runinfo;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// How a diff report is printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// Aligned human readable table
+    Table,
+    Json,
+}
+
+// How far apart two values can be before a difference is highlighted. Not a doc
+// comment on purpose, clap would use it as the about text of whatever flattens it.
+#[derive(clap::Args, Clone, Copy)]
+pub struct Tolerances {
+    /// Highlight cue-in differences above this many seconds
+    #[arg(long, default_value_t = 0.1)]
+    pub cue_tolerance: f32,
+
+    /// Highlight crossfade duration differences above this many seconds
+    #[arg(long, default_value_t = 0.1)]
+    pub cross_tolerance: f32,
+
+    /// Highlight duration differences above this many seconds
+    #[arg(long, default_value_t = 0.1)]
+    pub duration_tolerance: f32,
+
+    /// Highlight amplify differences above this many dB
+    #[arg(long, default_value_t = 0.2)]
+    pub amplify_tolerance: f32,
+}
+
+/// The values of one processed playlist entry that are worth comparing
+#[derive(Clone, Copy, Default)]
+pub struct Values {
+    pub cue_in: f32,
+    pub cross: f32,
+    pub duration: f32,
+    pub amplify: f32,
+}
+
+impl Values {
+    pub fn from_annotation(a: &Annotation) -> Values {
+        Values {
+            cue_in: a.get_f32("liq_cue_in").unwrap_or(0.),
+            cross: a.get_f32("liq_cross_duration").unwrap_or(0.),
+            duration: a.get_f32("duration").unwrap_or(0.),
+            amplify: a.get_db("liq_amplify").unwrap_or(0.),
+        }
+    }
+
+    // rounded like the annotate line so a fresh run compares fairly against a file
+    // tracks that weren't analysed have no values, they show up as missing from the new playlist
+    pub fn from_track(t: &Track) -> Option<Values> {
+        let round3 = |x: f32| (x * 1000.).round() / 1000.;
+        let r = t.analyzed()?;
+        Some(Values {
+            cue_in: round3(r.cue_point),
+            cross: round3(r.start_next),
+            duration: round3(r.duration),
+            amplify: round3(r.amplify()),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Delta {
+    pub path: String,
+    pub cue_in: f32,
+    pub cross: f32,
+    pub duration: f32,
+    pub amplify: f32,
+    /// Names of the fields whose delta is beyond its tolerance
+    pub exceeded: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct DiffReport {
+    /// The runs that wrote each playlist, from their header comments
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub old_runs: Vec<runinfo::Block>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub new_runs: Vec<runinfo::Block>,
+    pub changed: Vec<Delta>,
+    pub unchanged: usize,
+    pub only_in_old: Vec<String>,
+    pub only_in_new: Vec<String>,
+}
+
+impl DiffReport {
+    pub fn exceeded(&self) -> usize {
+        self.changed
+            .iter()
+            .filter(|d| !d.exceeded.is_empty())
+            .count()
+    }
+}
+
+/*
+Tracks are matched by path. A path can legitimately appear more than once in a
+playlist, so repeated paths are paired up in the order they appear.
+*/
+pub fn diff(old: &[(String, Values)], new: &[(String, Values)], tol: &Tolerances) -> DiffReport {
+    let mut old_by_path: HashMap<&str, VecDeque<&Values>> = HashMap::new();
+    for (path, values) in old {
+        old_by_path.entry(path).or_default().push_back(values);
+    }
+
+    let mut report = DiffReport {
+        old_runs: Vec::new(),
+        new_runs: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+        only_in_old: Vec::new(),
+        only_in_new: Vec::new(),
+    };
+
+    for (path, n) in new {
+        let o = match old_by_path
+            .get_mut(path.as_str())
+            .and_then(|q| q.pop_front())
+        {
+            Some(o) => o,
+            None => {
+                report.only_in_new.push(path.clone());
+                continue;
+            }
+        };
+
+        let delta = Delta {
+            path: path.clone(),
+            cue_in: n.cue_in - o.cue_in,
+            cross: n.cross - o.cross,
+            duration: n.duration - o.duration,
+            amplify: n.amplify - o.amplify,
+            exceeded: Vec::new(),
+        };
+
+        let mut exceeded = Vec::new();
+        for (name, d, t) in [
+            ("cue_in", delta.cue_in, tol.cue_tolerance),
+            ("cross", delta.cross, tol.cross_tolerance),
+            ("duration", delta.duration, tol.duration_tolerance),
+            ("amplify", delta.amplify, tol.amplify_tolerance),
+        ] {
+            if d.abs() > t {
+                exceeded.push(name);
+            }
+        }
+
+        // anything below the annotate line's precision is noise
+        if [delta.cue_in, delta.cross, delta.duration, delta.amplify]
+            .iter()
+            .all(|d| d.abs() < 0.0005)
+        {
+            report.unchanged += 1;
+        } else {
+            report.changed.push(Delta { exceeded, ..delta });
+        }
+    }
+
+    // whatever wasn't paired up is gone from the new playlist, keep the old order
+    for (path, _) in old {
+        if let Some(q) = old_by_path.get_mut(path.as_str()) {
+            if q.pop_front().is_some() {
+                report.only_in_old.push(path.clone());
+            }
+        }
+    }
+
+    report
+}
+
+pub fn render_table(report: &DiffReport) -> String {
+    let mut s = String::new();
+
+    // the settings each side was made with, which likely explain the changes
+    for (side, runs) in [("old", &report.old_runs), ("new", &report.new_runs)] {
+        for run in runs {
+            let values: Vec<String> = run.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            s.push_str(&format!("{} run: {}\n", side, values.join(", ")));
+        }
+    }
+    if !report.old_runs.is_empty() || !report.new_runs.is_empty() {
+        s.push('\n');
+    }
+
+    if !report.changed.is_empty() {
+        let width = report
+            .changed
+            .iter()
+            .map(|d| d.path.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+
+        s.push_str(&format!(
+            "{:<width$}  {:>9}   {:>9}   {:>9}   {:>9}\n",
+            "path", "cue_in", "cross", "duration", "amplify"
+        ));
+
+        for d in &report.changed {
+            // values past their tolerance get a trailing '!'
+            let cell = |name: &str, v: f32| {
+                let mark = if d.exceeded.contains(&name) { "!" } else { " " };
+                format!("{:>+9.3}{}", v, mark)
+            };
+            s.push_str(&format!(
+                "{:<width$}  {}  {}  {}  {}\n",
+                d.path,
+                cell("cue_in", d.cue_in),
+                cell("cross", d.cross),
+                cell("duration", d.duration),
+                cell("amplify", d.amplify),
+            ));
+        }
+        s.push('\n');
+    }
+
+    for path in &report.only_in_old {
+        s.push_str(&format!("only in old: {}\n", path));
+    }
+    for path in &report.only_in_new {
+        s.push_str(&format!("only in new: {}\n", path));
+    }
+
+    s.push_str(&format!(
+        "{} changed ({} beyond tolerance), {} unchanged, {} only in old, {} only in new\n",
+        report.changed.len(),
+        report.exceeded(),
+        report.unchanged,
+        report.only_in_old.len(),
+        report.only_in_new.len()
+    ));
+
+    s
+}
+
+pub fn render_json(report: &DiffReport) -> String {
+    let mut s = serde_json::to_string_pretty(report).unwrap();
+    s.push('\n');
+    s
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+/// One of the momentary loudness measurements ebur128 prints every 100ms
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    /// Seconds from the start of the input, the end of the 400ms block measured
+    pub time: f32,
+    /// Momentary loudness in LUFS
+    pub momentary: f32,
+}
+
+/// The values of the summary block ebur128 prints at the end, None for any it doesn't have
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Integrated loudness in LUFS
+    pub integrated: Option<f32>,
+    /// Loudness range in LU
+    pub lra: Option<f32>,
+    /// Sample or true peak in dBFS, whichever ebur128 was asked for
+    pub peak: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// A measurement line that didn't have a value where it should, with `strict`
+    Unparsed(String),
+    /// A value that's there but isn't a number, what it is
+    Value(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unparsed(line) => write!(f, "couldn't parse ebur128 line: {}", line),
+            ParseError::Value(what) => write!(f, "couldn't parse {} from the ffmpeg output", what),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/*
+The value that starts a piece of an ebur128 line, up to the next space, as in
+"M: -27.1 S:-30.2". Read whole rather than a fixed number of characters, so
+timestamps past 9999 seconds and values some builds print in scientific
+notation (-1.2e+01) come through as they are.
+*/
+fn first_token(s: &str) -> &str {
+    s.split_whitespace().next().unwrap_or("")
+}
+
+// a number up to the next space, what all the values so far are
+fn number(s: &str) -> Option<f32> {
+    first_token(s).parse().ok()
+}
+
+/// A value ebur128 prints after a label, and how it's read
+struct Field {
+    /// What's printed right before the value, as in "M:"
+    label: &'static str,
+    /// What the value is, for errors
+    what: &'static str,
+    parse: fn(&str) -> Option<f32>,
+}
+
+/*
+What's read from every measurement line, in this order. Adding a value, or
+reading one ffmpeg labels differently, is a line here and, for a new one, a
+field in Measurement, filled in at the end of `measurement`.
+*/
+const MEASUREMENT_FIELDS: [Field; 2] = [
+    Field {
+        label: "t:",
+        what: "a timestamp",
+        parse: number,
+    },
+    Field {
+        label: "M:",
+        what: "a momentary loudness",
+        parse: number,
+    },
+];
+
+// what's read from the summary block, in the order of Summary's fields
+const SUMMARY_FIELDS: [Field; 3] = [
+    Field {
+        label: "I:",
+        what: "the integrated loudness",
+        parse: number,
+    },
+    Field {
+        label: "LRA:",
+        what: "the loudness range",
+        parse: number,
+    },
+    Field {
+        label: "Peak:",
+        what: "the peak",
+        parse: number,
+    },
+];
+
+/*
+Reads a measurement line. Err(None) when a label isn't there, which skips the
+line, unless `strict`.
+*/
+fn measurement(line: &str, strict: bool) -> Result<Measurement, Option<ParseError>> {
+    let mut values = [0.; MEASUREMENT_FIELDS.len()];
+    for (field, value) in MEASUREMENT_FIELDS.iter().zip(&mut values) {
+        let Some(i) = line.find(field.label) else {
+            return Err(strict.then(|| ParseError::Unparsed(line.to_string())));
+        };
+        *value = (field.parse)(&line[i + field.label.len()..])
+            .ok_or(Some(ParseError::Value(field.what)))?;
+    }
+    let [time, momentary] = values;
+    Ok(Measurement { time, momentary })
+}
+
+// finds a value in the summary block by its label, the last one printed
+fn summary_value(lines: &[&str], field: &Field) -> Option<f32> {
+    let line = lines
+        .iter()
+        .rev()
+        .find(|l| l.trim_start().starts_with(field.label))?;
+    (field.parse)(&line.trim_start()[field.label.len()..])
+}
+
+/*
+Reads what ffmpeg's ebur128 filter printed to stderr, as run with
+`-af ebur128 -f null -`: every momentary measurement in order, and the
+summary at the end.
+
+Lines that don't look like measurements are skipped, which is what keeps it
+going when ffmpeg adds something to its output. With `strict` every ebur128
+line skipped that way is an error instead, to notice when a new ffmpeg prints
+its measurements differently. A measurement whose value isn't a number is an
+error either way.
+*/
+pub fn parse_ebur128_measurements(
+    stderr: &str,
+    strict: bool,
+) -> Result<(Vec<Measurement>, Summary), ParseError> {
+    let lines: Vec<&str> = stderr.lines().collect();
+    let mut measurements = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if !line.starts_with("[Parsed_ebur128") {
+            continue;
+        }
+        // the summary block is read on its own below
+        if i + 13 > lines.len() || line.trim_end().ends_with("Summary:") {
+            if strict && line.contains("M:") {
+                return Err(ParseError::Unparsed(line.to_string()));
+            }
+            continue;
+        }
+        match measurement(line, strict) {
+            Ok(m) => measurements.push(m),
+            Err(Some(e)) => return Err(e),
+            Err(None) => continue,
+        }
+    }
+
+    let [integrated, lra, peak] = SUMMARY_FIELDS.each_ref().map(|f| summary_value(&lines, f));
+    let summary = Summary {
+        integrated,
+        lra,
+        peak,
+    };
+    Ok((measurements, summary))
+}
+
+/*
+The time of the first measurement louder than `threshold`, or with `rev` the
+last one, going from the end. 0 if none is.
+*/
+pub fn first_time_threshold(measure: &[Measurement], threshold: f32, rev: bool) -> f32 {
+    let louder = |m: &&Measurement| m.momentary > threshold;
+    let found = if rev {
+        measure.iter().rev().find(louder)
+    } else {
+        measure.iter().find(louder)
+    };
+    found.map_or(0., |m| m.time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // measurement lines followed by the summary block ffmpeg ends with
+    fn stderr(lines: &[&str]) -> String {
+        let summary = [
+            "[Parsed_ebur128_0 @ 0x5555] Summary:",
+            "",
+            "  Integrated loudness:",
+            "    I:         -1.8e+01 LUFS",
+            "    Threshold: -28.5 LUFS",
+            "",
+            "  Loudness range:",
+            "    LRA:         5.0 LU",
+            "    Threshold: -38.5 LUFS",
+            "    LRA low:   -22.7 LUFS",
+            "    LRA high:  -17.5 LUFS",
+            "",
+            "  Sample peak:",
+            "    Peak:       -1.5 dBFS",
+        ];
+        lines
+            .iter()
+            .chain(&summary)
+            .fold(String::new(), |mut s, l| {
+                s.push_str(l);
+                s.push('\n');
+                s
+            })
+    }
+
+    #[test]
+    fn scientific_notation() {
+        let stderr = stderr(&[
+            "[Parsed_ebur128_0 @ 0x5555] t: 0.1        TARGET:-23 LUFS    M:-1.2e+01 S:-1.2e+01     I:-1.8e+01 LUFS       LRA:   5.0 LU  ",
+            "[Parsed_ebur128_0 @ 0x5555] t: 1.2e+04    TARGET:-23 LUFS    M:-7.05E1 S: -70.5     I: -18.0 LUFS       LRA:   5.0 LU  ",
+            "[Parsed_ebur128_0 @ 0x5555] t: 12000.1    TARGET:-23 LUFS    M: -1.207e2 S:-120.7     I: -18.0 LUFS       LRA:   5.0 LU  ",
+        ]);
+        let (measurements, summary) = parse_ebur128_measurements(&stderr, true).unwrap();
+        assert_eq!(
+            measurements,
+            [
+                Measurement {
+                    time: 0.1,
+                    momentary: -12.
+                },
+                Measurement {
+                    time: 12000.,
+                    momentary: -70.5
+                },
+                Measurement {
+                    time: 12000.1,
+                    momentary: -120.7
+                },
+            ]
+        );
+        assert_eq!(summary.integrated, Some(-18.));
+        assert_eq!(summary.lra, Some(5.));
+        assert_eq!(summary.peak, Some(-1.5));
+    }
+
+    #[test]
+    fn not_a_number() {
+        let stderr = stderr(&[
+            "[Parsed_ebur128_0 @ 0x5555] t: 0.1        TARGET:-23 LUFS    M:-1.2e+ S:-12.0     I: -18.0 LUFS       LRA:   5.0 LU  ",
+        ]);
+        assert_eq!(
+            parse_ebur128_measurements(&stderr, false),
+            Err(ParseError::Value("a momentary loudness"))
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::playlist::Track;
+use std::ops::Range;
+
+fn is_short(track: &Track, min_duration: f32) -> bool {
+    track
+        .analyzed()
+        .is_some_and(|r| !r.cross_disabled && r.duration - r.cue_point < min_duration)
+}
+
+/*
+Finds runs of two or more neighbouring tracks heard for less than
+`min_duration` seconds, like a cluster of station IDs and stingers, and plays
+them back to back: every element but the last gets no crossfade (and no
+fade-out), so each one starts where the one before ends. The crossfade into the
+run and out of its last element stay as analysed.
+
+Tracks without results or with --no-cross end a run, we don't know how long
+the first are and the second leave the crossfade to the playout. Returns the
+runs as index ranges into `tracks`.
+*/
+pub fn play_through(tracks: &mut [Track], min_duration: f32) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+
+    while i < tracks.len() {
+        let start = i;
+        while i < tracks.len() && is_short(&tracks[i], min_duration) {
+            i += 1;
+        }
+        if i - start >= 2 {
+            runs.push(start..i);
+        }
+        i = i.max(start + 1);
+    }
+
+    for run in &runs {
+        let last = run.end - 1;
+        for (j, track) in tracks[run.clone()].iter_mut().enumerate() {
+            let r = track.analyzed_mut().unwrap();
+            r.grouped = true;
+            if run.start + j != last {
+                r.start_next = 0.;
+                r.fade_out = r.fade_out.map(|_| 0.);
+            }
+        }
+    }
+
+    runs
+}
@@ -0,0 +1,66 @@
+use crate::playlist::Track;
+
+// the most --overlap-headroom takes off a track's liq_amplify
+const MAX_REDUCTION: f32 = 3.;
+
+/// A crossfade predicted to go over --overlap-ceiling
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    /// Predicted loudness of the overlap in LUFS, after normalisation
+    pub level: f32,
+    /// dB taken off the incoming track's liq_amplify, with --overlap-headroom
+    pub reduction: f32,
+}
+
+// loudness of two signals playing together, assuming they're uncorrelated
+fn power_sum(a: f32, b: f32) -> f32 {
+    10. * (10f32.powf(a / 10.) + 10f32.powf(b / 10.)).log10()
+}
+
+/*
+Predicts how loud each crossfade plays once both tracks are normalised: the
+outgoing track at the level of its crossfade region, the incoming one at the
+level of its first seconds, added up as power. Only tracks next to each other
+in the playlist and both analysed are looked at, anything in between would
+change what's heard.
+
+Transitions over `ceiling` are returned. With `reduce`, the incoming track's
+gain is lowered by the excess, up to MAX_REDUCTION dB. That lowers the whole
+track, so it's meant for small corrections.
+*/
+pub fn check(tracks: &mut [Track], ceiling: f32, reduce: bool) -> Vec<Transition> {
+    let mut over = Vec::new();
+
+    for i in 1..tracks.len() {
+        let (before, after) = tracks.split_at_mut(i);
+        let (prev, next) = match (before[i - 1].analyzed(), after[0].analyzed_mut()) {
+            (Some(p), Some(n)) => (p, n),
+            _ => continue,
+        };
+        let (outro, intro) = match (prev.outro_level, next.intro_level) {
+            (Some(o), Some(n)) if prev.start_next > 0. => (o, n),
+            _ => continue,
+        };
+
+        let level = power_sum(prev.target + outro, next.target + intro);
+        if level <= ceiling {
+            continue;
+        }
+
+        let reduction = if reduce {
+            (level - ceiling).min(MAX_REDUCTION)
+        } else {
+            0.
+        };
+        next.headroom_reduction = reduction;
+        over.push(Transition {
+            from: prev.path.clone(),
+            to: next.path.clone(),
+            level,
+            reduction,
+        });
+    }
+
+    over
+}
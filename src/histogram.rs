@@ -0,0 +1,43 @@
+// widest bar, in characters
+const BAR_WIDTH: usize = 40;
+// at most this many rows, the bins widen to fit
+const MAX_BINS: usize = 20;
+
+/*
+A text histogram of integrated loudness, one row per bin of whole LU from the
+quietest track to the loudest, so a rotation that falls into two clusters shows
+up as two humps. Bins are 1 LU wide unless the spread needs more than MAX_BINS
+rows. Each row has the bin's lower edge, a bar scaled to the fullest bin and
+the count.
+*/
+pub fn render(loudness: &[f32]) -> String {
+    // silent tracks measure -inf, they have no bin
+    let finite: Vec<f32> = loudness.iter().copied().filter(|l| l.is_finite()).collect();
+    if finite.is_empty() {
+        return String::new();
+    }
+    let min = finite.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = finite.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+    let low = min.floor() as i32;
+    let spread = (max.floor() as i32 - low + 1) as usize;
+    let width = spread.div_ceil(MAX_BINS);
+    let mut counts = vec![0usize; spread.div_ceil(width)];
+    for l in &finite {
+        counts[(l.floor() as i32 - low) as usize / width] += 1;
+    }
+
+    let fullest = counts.iter().copied().max().unwrap_or(1);
+    let mut s = String::new();
+    for (i, &count) in counts.iter().enumerate() {
+        let bar = (count * BAR_WIDTH).div_ceil(fullest);
+        s.push_str(&format!(
+            "  {:>4} LUFS  {:<width$}  {}\n",
+            low + (i * width) as i32,
+            "#".repeat(bar),
+            count,
+            width = BAR_WIDTH
+        ));
+    }
+    s
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore file looked for next to the playlist
+pub const IGNORE_FILE_NAME: &str = ".boundariesignore";
+
+struct Rule {
+    pattern: Vec<char>,
+    /// `!pattern`, re-includes what earlier rules ignored
+    negated: bool,
+    /// Patterns without a slash only look at the file name
+    basename_only: bool,
+}
+
+/// The rules of a .boundariesignore file
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+    /// Directory the ignore file lives in, paths with a slash are relative to it
+    base: PathBuf,
+}
+
+/*
+Reads the ignore file in `dir`, if there is one. The syntax follows .gitignore:
+blank lines and lines starting with '#' are skipped, '!' negates, a trailing
+'/' matches everything inside a directory, and a leading '/' anchors the
+pattern to the ignore file's directory.
+*/
+pub fn load(dir: &Path) -> io::Result<Option<IgnoreRules>> {
+    let path = dir.join(IGNORE_FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut rules = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(l) => (true, l),
+            None => (false, line),
+        };
+
+        let mut pattern = line.to_string();
+        if let Some(dir) = pattern.strip_suffix('/') {
+            // a directory: anything below it, wherever it is unless anchored
+            pattern = if dir.contains('/') {
+                format!("{}/**", dir)
+            } else {
+                format!("**/{}/**", dir)
+            };
+        }
+        let basename_only = !pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/').chars().collect();
+
+        rules.push(Rule {
+            pattern,
+            negated,
+            basename_only,
+        });
+    }
+
+    // absolute, so absolute entries can be compared against it
+    let base = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+    Ok(Some(IgnoreRules { rules, base }))
+}
+
+impl IgnoreRules {
+    /// Whether a playlist entry should be passed through. Like .gitignore,
+    /// the last matching rule decides.
+    pub fn is_ignored(&self, entry: &str) -> bool {
+        let entry = entry.replace('\\', "/");
+        let base = self.base.to_string_lossy().replace('\\', "/");
+
+        // absolute entries below the ignore file's directory are matched relative to it
+        let relative: Vec<char> = match entry.strip_prefix(&format!("{}/", base)) {
+            Some(r) if !base.is_empty() => r.chars().collect(),
+            _ => entry.chars().collect(),
+        };
+        let name: Vec<char> = entry.rsplit('/').next().unwrap_or("").chars().collect();
+
+        let mut ignored = false;
+        for rule in &self.rules {
+            let text = if rule.basename_only { &name } else { &relative };
+            if glob_match(&rule.pattern, text) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+// '*' and '?' stop at slashes, '**' doesn't, "[...]" is a character class
+fn glob_match(p: &[char], t: &[char]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some('*') if p.get(1) == Some(&'*') => {
+            let rest = &p[2..];
+            // "**/x" also matches "x" with no directory in front
+            (0..=t.len()).any(|i| glob_match(rest, &t[i..]))
+                || (rest.first() == Some(&'/') && glob_match(&rest[1..], t))
+        }
+        Some('*') => {
+            let mut i = 0;
+            loop {
+                if glob_match(&p[1..], &t[i..]) {
+                    return true;
+                }
+                if i == t.len() || t[i] == '/' {
+                    return false;
+                }
+                i += 1;
+            }
+        }
+        Some('?') => !t.is_empty() && t[0] != '/' && glob_match(&p[1..], &t[1..]),
+        Some('[') => match p.iter().skip(2).position(|&c| c == ']') {
+            Some(end) => {
+                let class = &p[1..end + 2];
+                !t.is_empty() && class_match(class, t[0]) && glob_match(&p[end + 3..], &t[1..])
+            }
+            // no closing bracket, it's just a character
+            None => t.first() == Some(&'[') && glob_match(&p[1..], &t[1..]),
+        },
+        Some('\\') if p.len() > 1 => t.first() == Some(&p[1]) && glob_match(&p[2..], &t[1..]),
+        Some(c) => t.first() == Some(c) && glob_match(&p[1..], &t[1..]),
+    }
+}
+
+fn class_match(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
@@ -0,0 +1,70 @@
+use crate::playlist::{Entry, Outcome};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/*
+Version of the --save-analysis format. Bumped whenever a file written by an
+earlier version can't be read the same way any more. Fields that are only
+added, with a default when they're missing, don't need a new version.
+*/
+const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Deserialize)]
+struct StoredTrack {
+    entry: Entry,
+    outcome: Outcome,
+}
+
+/// What's read back of a file `save` wrote, once its version checks out
+#[derive(Deserialize)]
+struct Analysis {
+    tracks: Vec<StoredTrack>,
+}
+
+pub fn save(path: &Path, entries: &[Entry], results: &[Outcome]) -> io::Result<()> {
+    let tracks: Vec<Value> = entries
+        .iter()
+        .zip(results)
+        .map(|(entry, outcome)| json!({ "entry": entry, "outcome": outcome }))
+        .collect();
+    let analysis = json!({
+        "schema_version": SCHEMA_VERSION,
+        "generator": format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        "tracks": tracks,
+    });
+    fs::write(path, serde_json::to_string(&analysis)?)
+}
+
+/*
+The version is checked before anything else is parsed, so a file from a newer
+or older version says so instead of failing on whichever field changed.
+*/
+pub fn load(path: &Path) -> io::Result<(Vec<Entry>, Vec<Outcome>)> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let version = value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid(String::from("not a --save-analysis file")))?;
+    if version != SCHEMA_VERSION {
+        let generator = value
+            .get("generator")
+            .and_then(Value::as_str)
+            .unwrap_or("an unknown version");
+        return Err(invalid(format!(
+            "written by {} with schema version {}, this version reads {}",
+            generator, version, SCHEMA_VERSION
+        )));
+    }
+
+    let analysis: Analysis = serde_json::from_value(value)?;
+    Ok(analysis
+        .tracks
+        .into_iter()
+        .map(|t| (t.entry, t.outcome))
+        .unzip())
+}
@@ -0,0 +1,209 @@
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/*
+How many ffmpeg and ffprobe processes may run at once, apart from how many
+threads analyse. Decoding is mostly reading the file, and on a slow disk or a
+NAS a few readers at a time get through a library faster than one per core
+fighting over it. Without a limit every thread runs its own.
+*/
+static LIMIT: OnceLock<Semaphore> = OnceLock::new();
+
+// set once the run is abandoned, processes still running are killed
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+// how often a running process checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+struct Semaphore {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    fn new(jobs: usize) -> Semaphore {
+        Semaphore {
+            free: Mutex::new(jobs.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    // runs `job` once a slot is free, or not at all if the run is cancelled while waiting
+    fn run<T>(&self, job: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 && !CANCELLED.load(Ordering::SeqCst) {
+            free = self.released.wait(free).unwrap();
+        }
+        if CANCELLED.load(Ordering::SeqCst) {
+            return Err(cancelled());
+        }
+        *free -= 1;
+        drop(free);
+
+        let result = job();
+
+        *self.free.lock().unwrap() += 1;
+        self.released.notify_one();
+        result
+    }
+}
+
+pub fn set_limit(jobs: usize) {
+    let _ = LIMIT.set(Semaphore::new(jobs));
+}
+
+/// Kills the processes that are running and fails the ones that would start
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+    if let Some(limit) = LIMIT.get() {
+        limit.released.notify_all();
+    }
+}
+
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "cancelled")
+}
+
+/// Runs the command to completion like `Command::output`, waiting for a free slot first
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    command.stdin(Stdio::null());
+    limited(command)
+}
+
+/// Like `output`, with `input` as the command's stdin, ours for ffmpeg reading pipe:0
+pub fn output_with_stdin(command: &mut Command, input: Stdio) -> io::Result<Output> {
+    command.stdin(input);
+    limited(command)
+}
+
+fn limited(command: &mut Command) -> io::Result<Output> {
+    match LIMIT.get() {
+        Some(limit) => limit.run(|| run(command)),
+        None => run(command),
+    }
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/*
+`Command::output` blocks until the process exits, so a cancelled run would wait
+for every ffmpeg in flight to decode its whole file. Instead the pipes are
+drained on their own threads while this one polls for the exit, killing the
+process if the run is cancelled in the meantime.
+*/
+fn run(command: &mut Command) -> io::Result<Output> {
+    if CANCELLED.load(Ordering::SeqCst) {
+        return Err(cancelled());
+    }
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if CANCELLED.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(cancelled());
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::sync::atomic::AtomicUsize;
+
+    // a 16 bit stereo WAV header and `frames` frames of a square wave
+    fn wav(frames: u32) -> Vec<u8> {
+        let data = frames * 4;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + data).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(44100u32.to_le_bytes());
+        wav.extend((44100u32 * 4).to_le_bytes());
+        wav.extend(4u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend(data.to_le_bytes());
+        for frame in 0..frames {
+            let sample: i16 = if frame % 100 < 50 { 8000 } else { -8000 };
+            wav.extend(sample.to_le_bytes());
+            wav.extend(sample.to_le_bytes());
+        }
+        wav
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn piped_wav_reaches_the_command() {
+        let dir = TempDir::new("pipe");
+        let path = dir.join("in.wav");
+        // more than a pipe holds, so it has to be read while it runs
+        let wav = wav(100_000);
+        std::fs::write(&path, &wav).unwrap();
+
+        let input = Stdio::from(std::fs::File::open(&path).unwrap());
+        let piped = output_with_stdin(&mut Command::new("cat"), input).unwrap();
+        assert!(piped.status.success());
+        assert_eq!(piped.stdout, wav);
+
+        // and nothing at all without one
+        let closed = output(&mut Command::new("cat")).unwrap();
+        assert!(closed.stdout.is_empty());
+    }
+
+    #[test]
+    fn jobs_hold_to_the_limit() {
+        const JOBS: usize = 3;
+        let limit = Semaphore::new(JOBS);
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..JOBS * 4 {
+                s.spawn(|| {
+                    limit
+                        .run(|| {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            most.fetch_max(now, Ordering::SeqCst);
+                            thread::sleep(Duration::from_millis(5));
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            Ok(())
+                        })
+                        .unwrap();
+                });
+            }
+        });
+        assert!(most.load(Ordering::SeqCst) <= JOBS);
+    }
+}
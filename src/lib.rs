@@ -0,0 +1,24 @@
+/*!
+The parts of rust-boundaries that don't need ffmpeg to run, for programs that
+run it themselves and want the measurements read the same way: the parser for
+what the ebur128 filter prints, and the threshold search the cue-in and the
+crossfade start from.
+
+```
+use rust_boundaries::{first_time_threshold, parse_ebur128_measurements};
+
+# let stderr = "";
+let (measurements, summary) = parse_ebur128_measurements(stderr, false)?;
+if let Some(loudness) = summary.integrated {
+    // the first moment within 40 LU of the track's loudness
+    let cue_in = first_time_threshold(&measurements, loudness - 40., false);
+}
+# Ok::<(), rust_boundaries::ParseError>(())
+```
+*/
+
+pub mod ebur128;
+
+pub use ebur128::{
+    first_time_threshold, parse_ebur128_measurements, Measurement, ParseError, Summary,
+};
@@ -1,17 +1,146 @@
-use clap::Parser;
-use rayon::prelude::*;
+/*
+Progress and summaries on stdout go through say!, which --quiet silences so a
+script gets nothing there but the paths of what was written. Warnings and
+errors are on stderr either way.
+*/
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod albums;
+mod batch;
+#[cfg(feature = "beat-align")]
+mod beats;
+mod cache;
+mod channels;
+mod chapters;
+mod csvinput;
+mod cuesheet;
+mod diff;
+mod group;
+mod headroom;
+mod histogram;
+mod ignore;
+mod intermediate;
+mod jobs;
+mod output;
+mod overlaps;
+mod overrides;
+mod pairwise;
+mod playlist;
+mod plsinput;
+mod preview;
+mod push;
+mod qc;
+mod queue;
+mod random;
+mod review;
+mod runinfo;
+mod since;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stream;
+mod summary;
+mod sweep;
+mod tags;
+#[cfg(test)]
+mod testing;
+mod timeline;
+mod tracklog;
+mod validate;
+mod watch;
+
+use cache::Verify;
+use chapters::ChapterSettings;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use diff::{DiffFormat, Tolerances};
+use output::Format;
+use playlist::{InputFormat, Outcome, Region, SortKey, Track};
+use regex::Regex;
+use rust_boundaries::ebur128::{self, first_time_threshold, Measurement};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
-use std::io::{prelude::*, BufReader, BufWriter};
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::{Arc, Mutex};
+use std::io::{self, prelude::*, BufWriter, IsTerminal};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tags::{LoudnessSource, TaggedLoudness};
+
+// Exit codes: some tracks failed but the playlist was still written, or the run
+// was aborted without writing anything
+const EXIT_PARTIAL: i32 = 1;
+const EXIT_FATAL: i32 = 2;
+
+// what ffmpeg reads with --single-stdin, and the path of its result
+const STDIN: &str = "pipe:0";
+
+// set by --quiet, see say!
+static QUIET: AtomicBool = AtomicBool::new(false);
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Path to the playlist
-    #[arg()]
-    path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Path to the playlist, or to a CUE sheet
+    #[arg(required_unless_present_any = ["from_analysis", "single_stdin"])]
+    path: Option<PathBuf>,
+
+    /// More playlists, or directories of them, with --per-input-output
+    #[arg(requires = "per_input_output")]
+    more_paths: Vec<PathBuf>,
+
+    /// Process every playlist given, and every one in a directory given, each to its own
+    /// -processed playlist next to it, all their tracks sharing the workers
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "path",
+        conflicts_with_all = ["output", "append", "update", "stream", "watch", "save_analysis", "from_analysis", "review", "sample", "report", "csv", "preview", "diff_against", "log_dir", "max_errors", "fail_fast"]
+    )]
+    per_input_output: bool,
+
+    /// With --per-input-output, also process the playlists in the directories under a directory given
+    #[arg(long, default_value_t = false, requires = "per_input_output")]
+    recursive: bool,
+
+    /// What the input is, by default guessed from its extension
+    #[arg(long, value_enum)]
+    input_format: Option<playlist::InputFormat>,
+
+    /// Column of a CSV input with the file paths, a number from 1 or a name in the header row
+    #[arg(long)]
+    path_column: Option<String>,
+
+    /// Analyse the playlist and write the results to this file instead of a playlist
+    #[arg(long, conflicts_with_all = ["update", "validate"])]
+    save_analysis: Option<PathBuf>,
+
+    /// Write the playlist from a file written by --save-analysis, without analysing anything
+    #[arg(long, conflicts_with_all = ["path", "save_analysis", "update", "validate"])]
+    from_analysis: Option<PathBuf>,
+
+    /// Analyse one audio file piped to stdin and print its result, JSON unless --format says otherwise
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["path", "from_analysis", "trust_tags", "channel_loudness"]
+    )]
+    single_stdin: bool,
 
     /// LU below average loudness to trigger next track
     #[arg(short, long, default_value_t = 8.)]
@@ -21,255 +150,5004 @@ struct Args {
     #[arg(short, long, default_value_t = 40.)]
     cue: f32,
 
-    /// Output filename (default: '-processed' suffix)
+    /// Loudness to normalise to, in LUFS (default: -23, the EBU R128 target). Several
+    /// (repeated or comma separated) write a playlist each, see --output
+    #[arg(long, allow_hyphen_values = true, value_delimiter = ',')]
+    target_lufs: Vec<f32>,
+
+    /// Which peak ffmpeg measures, for the reported peak and the crest factor
+    #[arg(long, value_enum, default_value_t = PeakMode::Sample)]
+    peak_mode: PeakMode,
+
+    /// Output filename (default: '-processed' suffix), with a {target} placeholder for several --target-lufs
     #[arg(short, long, default_value_t = String::from(""))]
     output: String,
 
     /// Append to output file instead of overwriting everything
     #[arg(short, long, default_value_t = false)]
     append: bool,
+
+    /// With --append, leave out the tracks whose file is already in the output
+    #[arg(long, default_value_t = false, requires = "append")]
+    skip_existing: bool,
+
+    /// Don't write the #EXTM3U header or the run comments, e.g. for a fragment to concatenate
+    #[arg(long, default_value_t = false)]
+    no_header: bool,
+
+    /// Write each entry's real path, with symlinks resolved, instead of the one in the playlist
+    #[arg(long, default_value_t = false)]
+    canonical_paths: bool,
+
+    /// Replace a leading FROM path prefix with TO in the written playlist (repeatable)
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_parser = playlist::parse_path_rewrite,
+        conflicts_with_all = ["update", "fingerprints"]
+    )]
+    path_rewrite: Vec<playlist::PathRewrite>,
+
+    /// CSV of hand-set values (path,cue_in,cross,amplify) that replace the analysed ones
+    #[arg(long, value_name = "CSV")]
+    overrides: Option<PathBuf>,
+
+    /// Match --overrides rows on the end of the track's path instead of the whole file
+    #[arg(long, default_value_t = false, requires = "overrides")]
+    overrides_suffix: bool,
+
+    /// Write the m3u as tracks finish instead of all at the end, for very long playlists
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["append", "update", "reverse", "exclude_flagged", "overlap_ceiling", "group_short", "save_analysis", "from_analysis", "overrides", "pairwise_cross"]
+    )]
+    stream: bool,
+
+    /// Also write the results as JSON to this file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Also write the results as CSV to this file
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Save each track's ffmpeg command and output to DIR/<index>-<stem>.log
+    #[arg(long)]
+    log_dir: Option<PathBuf>,
+
+    /// With --log-dir, only keep the logs of tracks that failed or were flagged
+    #[arg(long, default_value_t = false, requires = "log_dir")]
+    log_failures_only: bool,
+
+    /// Print each track's ffmpeg command to stderr before running it, ready to paste into a shell
+    #[arg(long, default_value_t = false)]
+    dump_ffmpeg_cmd: bool,
+
+    /// Add up the rotation's length with its crossfades, listing unusually long or short ones, in --summary-file
+    #[arg(long, default_value_t = false)]
+    overlap_report: bool,
+
+    /// Also print the --overlap-report to stderr
+    #[arg(long, default_value_t = false, requires = "overlap_report")]
+    overlap_report_stderr: bool,
+
+    /// Exit with 1 when the playlist plays for less than this many seconds with its crossfades
+    #[arg(long, value_name = "SECONDS", conflicts_with = "save_analysis")]
+    require_duration: Option<f32>,
+
+    /// Exit with 1 when the playlist plays for more than this many seconds with its crossfades
+    #[arg(long, value_name = "SECONDS", conflicts_with = "save_analysis")]
+    max_duration_total: Option<f32>,
+
+    /// Print each album's (#EXTALB or directory) loudness and crossfades at the end, the most anomalous first
+    #[arg(long, default_value_t = false)]
+    album_report: bool,
+
+    /// Print a histogram of the tracks' integrated loudness at the end
+    #[arg(long, default_value_t = false)]
+    histogram: bool,
+
+    /// After writing, push every track to Liquidsoap's command server at HOST:PORT
+    #[arg(long, value_name = "HOST:PORT")]
+    push_to: Option<String>,
+
+    /// Request queue the tracks are pushed to, for {queue} in --push-command
+    #[arg(long, default_value = "request", requires = "push_to")]
+    push_queue: String,
+
+    /// Command sent for every track, {uri} being its annotated URI
+    #[arg(long, default_value = "{queue}.push {uri}", requires = "push_to")]
+    push_command: String,
+
+    /// Send `auth PASSWORD` to the command server before pushing
+    #[arg(long, requires = "push_to")]
+    push_password: Option<String>,
+
+    /// Like --push-password, with the password read from the first line of FILE so it's not on the command line
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "push_to",
+        conflicts_with = "push_password"
+    )]
+    push_password_file: Option<PathBuf>,
+
+    /// Render the playlist's transitions, with their gains and crossfades, to this audio file
+    #[arg(long, value_name = "FILE")]
+    preview: Option<PathBuf>,
+
+    /// Seconds of each track before and after a crossfade in the --preview
+    #[arg(long, default_value_t = 5., requires = "preview")]
+    preview_excerpt: f32,
+
+    /// Only push to Liquidsoap, don't write the playlist
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "push_to",
+        conflicts_with_all = ["append", "update", "stream"]
+    )]
+    no_file: bool,
+
+    /// The crossfade convention of a playout system
+    #[arg(long, value_enum, default_value_t = output::Preset::Liquidsoap)]
+    preset: output::Preset,
+
+    /// Write each m3u entry's values as Liquidsoap annotations or as a comment above it
+    #[arg(long, value_enum, default_value_t = output::AnnotationStyle::Annotate)]
+    annotation_style: output::AnnotationStyle,
+
+    /// Duration written in the #EXTINF lines of analysed entries
+    #[arg(long, value_enum, default_value_t = output::ExtinfDuration::Measured)]
+    extinf_duration: output::ExtinfDuration,
+
+    /// Write the gain in dB or as a linear multiplier
+    #[arg(long, value_enum, default_value_t = output::GainFormat::Db)]
+    gain_format: output::GainFormat,
+
+    /// End the playlist's lines with \n, \r\n, or as the input playlist does (default: lf, auto with --update)
+    #[arg(long, value_enum)]
+    line_ending: Option<output::LineEnding>,
+
+    /// Annotation the gain is written as, for players reading another one
+    #[arg(long, default_value = "liq_amplify")]
+    gain_key: String,
+
+    /// Name of the comment written with --annotation-style comment
+    #[arg(long, default_value = "EXT-BOUNDARIES")]
+    comment_key: String,
+
+    /// Round every written time this way (default: cue-ins down, cue-outs up, the rest nearest)
+    #[arg(long, value_enum)]
+    round: Option<output::Rounding>,
+
+    /// Write what the run did (settings, counts, outputs, failures) as JSON to this file
+    #[arg(long)]
+    summary_file: Option<PathBuf>,
+
+    /// Create the output file's directory if it doesn't exist
+    #[arg(long, default_value_t = false)]
+    mkdir: bool,
+
+    /// Write the playlist in parts of at most this many tracks, numbered after the output's name
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["split_every_seconds", "append", "update", "stream", "no_file"]
+    )]
+    split_every_tracks: Option<u64>,
+
+    /// Write the playlist in parts of at most this many seconds on air, with the crossfades
+    #[arg(long, conflicts_with_all = ["append", "update", "stream", "no_file"])]
+    split_every_seconds: Option<f32>,
+
+    /// Output format (default: inferred from the output extension, m3u otherwise)
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Print extra information about the decisions being made
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+
+    /// Go through the analysed tracks in the terminal before writing, to accept, change or drop each
+    #[arg(long, conflicts_with_all = ["quiet", "stream", "save_analysis", "watch"])]
+    review: bool,
+
+    /// Print nothing on stdout but the path of each playlist written, for scripts
+    #[arg(short, long, visible_alias = "porcelain", conflicts_with_all = ["verbose", "validate"])]
+    quiet: bool,
+
+    /// Use R128/ReplayGain tags for the track loudness instead of measuring it
+    #[arg(long, default_value_t = false)]
+    trust_tags: bool,
+
+    /// Loudness range (LU) at or above which a track is classified as speech-like
+    #[arg(long, default_value_t = 10.)]
+    speech_lra: f32,
+
+    /// Don't look for a cue-in point, tracks start at 0 (per track: #BOUNDARIES-OPTS:no-cue)
+    #[arg(long, default_value_t = false)]
+    no_cue: bool,
+
+    /// Don't look for a crossfade point (per track: #BOUNDARIES-OPTS:no-cross)
+    #[arg(long, default_value_t = false)]
+    no_cross: bool,
+
+    /// Crossfade written for tracks without crossfade detection, instead of leaving it out
+    #[arg(long)]
+    cross_default: Option<f32>,
+
+    /// How to find the cue-in point
+    #[arg(long, value_enum, default_value_t = CueMode::Threshold)]
+    cue_mode: CueMode,
+
+    /// LU the loudness has to rise by within half a second to count as an onset
+    #[arg(long, default_value_t = 6.)]
+    onset_sensitivity: f32,
+
+    /// Leave liq_cue_in out instead of writing 0 for tracks without cue detection
+    #[arg(long, default_value_t = false)]
+    omit_cue: bool,
+
+    /// Widen the thresholds for dynamic tracks, judged by their LRA and crest factor
+    #[arg(long, default_value_t = false)]
+    auto_profile: bool,
+
+    /// Use the speech thresholds (--speech-level/--speech-cue) for speech-like tracks
+    #[arg(long, default_value_t = false)]
+    speech_profile: bool,
+
+    /// --level used for speech-like tracks with --speech-profile
+    #[arg(long, default_value_t = 15.)]
+    speech_level: f32,
+
+    /// --cue used for speech-like tracks with --speech-profile
+    #[arg(long, default_value_t = 40.)]
+    speech_cue: f32,
+
+    /// Sort the output by this key once analysis is done
+    #[arg(long, value_enum, default_value_t = SortKey::None)]
+    sort: SortKey,
+
+    /// Reverse the output order (after sorting, if any)
+    #[arg(long, default_value_t = false)]
+    reverse: bool,
+
+    /// Compare the results against a previously processed playlist
+    #[arg(long)]
+    diff_against: Option<PathBuf>,
+
+    #[command(flatten)]
+    tolerances: Tolerances,
+
+    #[command(flatten)]
+    chapters: ChapterSettings,
+
+    /// Threads analysing tracks (default: one per core)
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// ffmpeg/ffprobe processes running at once, lower it for slow disks (default: one per thread)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Abort the whole run once this many tracks have failed
+    #[arg(long)]
+    max_errors: Option<usize>,
+
+    /// Stop at the first track that fails, killing the ffmpeg processes still running
+    #[arg(long, default_value_t = false, conflicts_with = "max_errors")]
+    fail_fast: bool,
+
+    /// Also measure per-channel levels (an extra ffmpeg pass per track)
+    #[arg(long, default_value_t = false)]
+    channel_loudness: bool,
+
+    /// Flag tracks whose channels differ by more than this many dB [default: 3, implies --channel-loudness]
+    #[arg(long)]
+    imbalance_threshold: Option<f32>,
+
+    /// Measure mono files as dual mono, as they play on a stereo system, so they get the gain their stereo versions would
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    dual_mono_correction: bool,
+
+    /// Also run astats in the analysis pass, to flag clipped tracks and ones with a DC offset
+    #[arg(long, default_value_t = false)]
+    qc: bool,
+
+    /// Flag tracks near full scale with more samples than this at their peak as clipped [default: 100, implies --qc]
+    #[arg(long)]
+    max_peak_count: Option<f32>,
+
+    /// Flag tracks with a DC offset above this fraction of full scale [default: 0.005, implies --qc]
+    #[arg(long)]
+    max_dc_offset: Option<f32>,
+
+    /// Seconds added to every crossfade, negative to shorten them
+    #[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+    extra_overlap: f32,
+
+    /// Shift each written crossfade by a random amount of up to this many seconds either way
+    #[arg(long, default_value_t = 0.)]
+    cross_jitter: f32,
+
+    /// Seed for --cross-jitter and --sample, to get the same playlist again
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Analyse only this many tracks picked at random, to try settings on a large library
+    #[arg(long, value_name = "N", conflicts_with_all = ["update", "from_analysis"])]
+    sample: Option<usize>,
+
+    /// Only check that every file in the playlist exists and can be read, then exit
+    #[arg(long, default_value_t = false)]
+    validate: bool,
+
+    /// With --validate, also check http(s) entries with a HEAD request (needs curl)
+    #[arg(long, default_value_t = false, requires = "validate")]
+    validate_remote: bool,
+
+    /// Seconds each --validate-remote request may take before the entry counts as unreachable
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 10.,
+        requires = "validate_remote"
+    )]
+    validate_timeout: f32,
+
+    /// Record each file's size and mtime in a comment, for --update
+    #[arg(long, default_value_t = false)]
+    fingerprints: bool,
+
+    /// Re-analyse only the changed entries of an already processed playlist, in place
+    #[arg(long, default_value_t = false, conflicts_with = "append")]
+    update: bool,
+
+    /// Write each track's on-air start time as a comment above its entry
+    #[arg(long, default_value_t = false)]
+    timeline_comments: bool,
+
+    /// Don't read the .boundariesignore file next to the playlist
+    #[arg(long, default_value_t = false)]
+    no_ignore_file: bool,
+
+    /// Take --cue/--level as LU above each track's noise floor instead of below its loudness
+    #[arg(long, default_value_t = false)]
+    noise_floor: bool,
+
+    /// Flag tracks with more than this many seconds of leading silence, often a bad rip
+    #[arg(long)]
+    max_leading_silence: Option<f32>,
+
+    /// Flag tracks with a crest factor below this many dB, likely over-compressed
+    #[arg(long)]
+    min_crest: Option<f32>,
+
+    /// Silence (in seconds) after which the rest of a file is a hidden track, 0 to disable
+    #[arg(long, default_value_t = 30.)]
+    hidden_silence: f32,
+
+    /// Write hidden tracks as entries of their own instead of leaving them out
+    #[arg(long, default_value_t = false)]
+    split_hidden: bool,
+
+    /// Start the crossfade before applause at the end of live recordings
+    #[arg(long, default_value_t = false)]
+    detect_applause: bool,
+
+    /// Flag tracks with an integrated loudness below this many LUFS as probably broken
+    #[arg(long, default_value_t = -35., allow_hyphen_values = true)]
+    flag_below: f32,
+
+    /// Leave tracks flagged by --flag-below out of the playlist
+    #[arg(long, default_value_t = false)]
+    exclude_flagged: bool,
+
+    /// Warn about crossfades predicted to play louder than this many LUFS once normalised
+    #[arg(long, allow_hyphen_values = true)]
+    overlap_ceiling: Option<f32>,
+
+    /// Lower liq_amplify of tracks crossfaded into above --overlap-ceiling (up to 3 dB)
+    #[arg(long, default_value_t = false, requires = "overlap_ceiling")]
+    overlap_headroom: bool,
+
+    /// Average the momentary loudness over this many blocks (0.1s each) before looking for the cue and crossfade
+    #[arg(long, default_value_t = 1)]
+    smooth: usize,
+
+    /// Seconds a track has to play between its cue-in and the crossfade, shortening the crossfade if needed
+    #[arg(long, default_value_t = 1.)]
+    min_playable: f32,
+
+    /// Play runs of short elements back to back, crossfading only into and out of the run
+    #[arg(long, default_value_t = false)]
+    group_short: bool,
+
+    /// Elements heard for less than this many seconds count as short
+    #[arg(long, default_value_t = 10.)]
+    min_duration: f32,
+
+    /// Shorten crossfades into tracks that open at full level
+    #[arg(long, default_value_t = false)]
+    pairwise_cross: bool,
+
+    /// Share of its crossfade a track keeps before the loudest openings, with --pairwise-cross
+    #[arg(long, default_value_t = 0.5, requires = "pairwise_cross")]
+    pairwise_min_share: f32,
+
+    /// Apply the cue-in's 400ms block correction to the crossfade point too
+    #[arg(long, default_value_t = false)]
+    tail_block_correction: bool,
+
+    /// Give tracks that end at full level a short crossfade, so the next one punches in
+    #[arg(long, default_value_t = false)]
+    detect_cold_endings: bool,
+
+    /// LU under the track's loudness the last moment can be and still be a cold ending
+    #[arg(long, default_value_t = 6.)]
+    cold_ending_level: f32,
+
+    /// Crossfade in seconds for cold endings
+    #[arg(long, default_value_t = 1.)]
+    cold_ending_cross: f32,
+
+    /// Emit liq_fade_out from the shape of each track's ending (0 for hard endings)
+    #[arg(long, default_value_t = false)]
+    emit_fade_out: bool,
+
+    /// Emit liq_cue_out where the audio stops, for tracks that end in silence
+    #[arg(long, default_value_t = false, conflicts_with = "range")]
+    emit_cue_out: bool,
+
+    /// Seconds of silence at the end that --emit-cue-out cuts
+    #[arg(long, default_value_t = 2.)]
+    cue_out_min_silence: f32,
+
+    /// Emit liq_fade_out_type, the fade shape that suits each track's ending
+    #[arg(long, default_value_t = false)]
+    emit_fade_type: bool,
+
+    /// Emit liq_longtail="true" for tracks whose crossfade is placed for a long tail
+    #[arg(long, default_value_t = false)]
+    emit_longtail: bool,
+
+    /// LU an ending has to drop by to count as a fade for --emit-fade-type
+    #[arg(long, default_value_t = 6.)]
+    fade_min_drop: f32,
+
+    /// How straight (r² of a line fit, 0-1) a fade has to be to count as linear
+    #[arg(long, default_value_t = 0.8)]
+    fade_linearity: f32,
+
+    /// Longest fade-out --emit-fade-out will emit, in seconds
+    #[arg(long, default_value_t = 10.)]
+    max_fade_out: f32,
+
+    /// Regex with a capture group for the duration, overriding the status line parsing
+    #[arg(long, hide = true, value_parser = parse_field_regex)]
+    duration_regex: Option<Regex>,
+
+    /// Fail a track on any ebur128 line that can't be parsed, to diagnose ffmpeg output changes
+    #[arg(long, default_value_t = false)]
+    strict_parse: bool,
+
+    /// Share of the expected measurements (ten a second) below which a track's are taken as cut short
+    #[arg(long, default_value_t = 0.8)]
+    min_coverage: f32,
+
+    /// Fail tracks whose measurements were cut short instead of warning about them
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Exit with 1 when any warning was printed, the playlist is still written
+    #[arg(long, default_value_t = false)]
+    warnings_as_errors: bool,
+
+    /// Regex with a capture group for the integrated loudness, overriding the summary parsing
+    #[arg(long, hide = true, value_parser = parse_field_regex)]
+    loudness_regex: Option<Regex>,
+
+    /// Reuse results of earlier runs from this file, and store new ones in it
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Move the cue-in to the first beat after it, found with aubio (a best-effort heuristic)
+    #[cfg(feature = "beat-align")]
+    #[arg(long, default_value_t = false)]
+    beat_align: bool,
+
+    /// Upsert every analysed track into this SQLite database, a catalog to query
+    #[cfg(feature = "sqlite")]
+    #[arg(long)]
+    sqlite: Option<PathBuf>,
+
+    /// Keep running and process the playlist again whenever it changes
+    #[arg(
+        long,
+        default_value_t = false,
+        requires_all = ["path", "cache"],
+        conflicts_with_all = ["update", "append", "validate", "from_analysis", "save_analysis"]
+    )]
+    watch: bool,
+
+    /// Also process again when files in this directory change, with --watch (repeatable)
+    #[arg(long, value_name = "DIR", requires = "watch")]
+    watch_dir: Vec<PathBuf>,
+
+    /// Seconds without changes to wait for before processing again
+    #[arg(long, default_value_t = 2., requires = "watch")]
+    watch_debounce: f32,
+
+    /// Check cached files with ffprobe before using their results (default level: duration)
+    #[arg(long, value_enum, requires = "cache", num_args = 0..=1, default_missing_value = "duration")]
+    reanalyze_on_mismatch: Option<Verify>,
+
+    /// Only analyse files modified after this time (2024-03-01T02:00:00Z, or ago like 24h or 7d),
+    /// taking the others from --cache or leaving them as they are
+    #[arg(long, value_parser = since::parse, conflicts_with_all = ["from_analysis", "single_stdin"])]
+    since: Option<std::time::SystemTime>,
+
+    /// Only analyse this part of each file, in seconds (e.g. 30:90)
+    #[arg(long, value_parser = parse_range)]
+    range: Option<TimeRange>,
+
+    /// Whether --range results are relative to the file or to the window
+    #[arg(long, value_enum, default_value_t = RangeTimes::File)]
+    range_times: RangeTimes,
 }
 
-#[derive(Default)]
-struct AnalyzeResult {
-    start_next: f32,
-    cue_point: f32,
-    duration: f32,
-    loudness: f32,
-    path: String,
+/// A START:END window of a file, in seconds
+#[derive(Clone, Copy, Debug)]
+struct TimeRange {
+    start: f32,
+    end: f32,
 }
 
-fn first_time_threshold(measure: &Vec<(f32, f32)>, threshold: f32, rev: bool) -> f32 {
-    let iter: Box<dyn Iterator<Item = &(f32, f32)>> = if rev {
-        Box::new(measure.iter().rev())
-    } else {
-        Box::new(measure.iter())
-    };
+fn parse_range(s: &str) -> Result<TimeRange, String> {
+    let (start, end) = s
+        .split_once(':')
+        .ok_or_else(|| String::from("expected START:END in seconds"))?;
+    let start: f32 = start
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid start '{}'", start))?;
+    let end: f32 = end
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid end '{}'", end))?;
 
-    for item in iter {
-        if item.1 > threshold {
-            return item.0;
-        }
+    if start < 0. || end <= start {
+        return Err(String::from(
+            "END must be greater than START, and START at least 0",
+        ));
     }
 
-    0.
+    Ok(TimeRange { start, end })
 }
 
-fn analyze(path: &str, vol_drop: f32, vol_start: f32) -> AnalyzeResult {
-    /*
-    Analyses file in filename, returns seconds to end-of-file of place where volume last drops to level
-    below average loudness, given in volDrop in LU.
-    Also determines file start, where monentary loudness leaps above a certain point given by volStart
-    Also encode and store a mezzanine file, if a mezzanine directory name is given
-    Make a list containing many points, 1/10 sec apart, where loudness is measured.
-    We need TIME and MOMENTARY LOUDNESS
-    We also need full INTEGRATED LOUDNESS
-    */
+// the first capture group is the value, so there has to be one
+fn parse_field_regex(s: &str) -> Result<Regex, String> {
+    let regex = Regex::new(s).map_err(|e| e.to_string())?;
+    if regex.captures_len() < 2 {
+        return Err(String::from(
+            "the regex needs a capture group for the value",
+        ));
+    }
+    Ok(regex)
+}
 
-    println!("Processing filename: {}", path);
+/// How the cue-in point is found
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum CueMode {
+    /// Where the loudness first gets within --cue LU of the track's
+    Threshold,
+    /// The first sharp rise in loudness, for intros that swell in
+    Onset,
+}
 
-    let test = Command::new("ffmpeg")
-        .arg("-hide_banner")
-        .arg("-y")
-        .arg("-i")
-        .arg(path)
-        .arg("-vn")
-        .arg("-af")
-        .arg("ebur128")
-        .arg("-f")
-        .arg("null")
-        .arg("null")
-        .output()
-        .unwrap();
-    // We pass "-vn" because some music files have invalid images, which can't be processed by ffmpeg
+/// The peak ebur128 measures
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PeakMode {
+    /// The highest sample, cheap to measure
+    #[default]
+    Sample,
+    /// The true (inter-sample) peak of BS.1770, found by oversampling
+    True,
+}
 
-    // from_utf8_lossy replaces wrong chars with question marks preventing crashes
-    let test = String::from_utf8_lossy(&test.stderr).to_string();
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum RangeTimes {
+    /// Times count from the start of the file, the crossfade from its end
+    File,
+    /// Times count from the start of the window, the crossfade from its end
+    Window,
+}
 
-    let test: Vec<&str> = test.lines().collect();
+// what liq_amplify normalises to without --target-lufs
+const DEFAULT_TARGET: f32 = -23.;
 
-    let mut measure: Vec<(f32, f32)> = Vec::new();
+// a floor closer than this many LU to the loudness means there isn't really one
+const MIN_FLOOR_GAP: f32 = 20.;
 
-    for i in 0..test.len() {
-        if i > (test.len() - 13) || !test[i].starts_with("[Parsed_ebur128") {
-            continue;
-        }
-        let t_i = match test[i].find("t:") {
-            None => continue,
-            Some(i) => i,
-        };
-        let t: f32 = test[i][t_i + 2..t_i + 8].trim().parse().unwrap();
-        let m_i = match test[i].find("M:") {
-            None => continue,
-            Some(i) => i,
-        };
-        let m: f32 = test[i][m_i + 2..m_i + 8].trim().parse().unwrap();
-        measure.push((t, m))
-    }
-    // measure now contains a vector of a 2-float tuples: each item is ([time], [loudness])
+// a decline shorter than this many seconds is a hard ending, not a fade
+const MIN_FADE: f32 = 1.;
 
-    if measure.len() == 0 {
-        panic!("Couldn't measure filename: {}", path);
-    }
+// momentary loudness below this many LUFS counts as silence
+const SILENCE_LEVEL: f32 = -60.;
 
-    // get integrated loudness
-    let loudness: f32 = test[test.len() - 8][15..20].trim().parse().unwrap();
+// crossfade for tracks too quiet to find one in
+const QUIET_TRACK_CROSS: f32 = 1.;
 
-    // parse duration from the status line
-    let partially_parsed_duration = &test[test.len() - 13][14..25];
-    let hms_split: Vec<&str> = partially_parsed_duration.split(":").collect();
-    let hours = hms_split[0].parse::<f32>().unwrap() * 3600.00;
-    let minutes = hms_split[1].parse::<f32>().unwrap() * 60.00;
-    let seconds = hms_split[2].parse::<f32>().unwrap();
-    let duration = hours + minutes + seconds;
+// an applause tail has to last at least this many seconds
+const MIN_APPLAUSE: f32 = 10.;
 
-    /*
-    First, let us find the first timestamp where the momentary loudness is volStart below the
-    track's overall loudness level. That level is cueLevel
-    */
-    let cue_level = loudness - vol_start;
+// channel level differences above this many dB get a track flagged by default
+const IMBALANCE_THRESHOLD: f32 = 3.;
 
-    let ebu_cue_time = first_time_threshold(&measure, cue_level, false);
+// the absolute gate of EBU R128, an integrated loudness at or below it is silence
+const SILENCE_GATE: f32 = -70.;
 
-    /*
-    The EBU R.128 algorithm measures in 400ms blocks. Therefore, it marks 0.4s as the
-    start of the track, even if its audio begins at 0.0s. So, we must subtract 400ms
-    from the given time, then use either that time, or 0.0s (if the result is negative)
-    as our track starting point.
-    */
-    let cue_time = f32::max(0., ebu_cue_time - 0.4);
+// seconds of the start of a track that count as what plays under a crossfade into it
+const INTRO_SPAN: f32 = 5.;
 
-    /*
-    Now we must find the last timestamp where the momentary loudness is volDrop LU
-    below the track's overall loudness level. That level is nextLevel.
-    */
-    let mut next_level = loudness - vol_drop;
-    let mut next_time = first_time_threshold(&measure, next_level, true);
+// tracks shorter than this aren't checked against --min-coverage
+const MIN_COVERAGE_DURATION: f32 = 5.;
 
-    /*
-    Little piece of logic to fix "Bohemian Rhapsody" and other songs with a long
-    but important tail.
-    */
-    if duration - next_time > 15. {
-        next_level = loudness - vol_drop - 15.;
-        next_time = first_time_threshold(&measure, next_level, true);
-    }
+#[derive(Subcommand)]
+enum Commands {
+    /// Compare two processed playlists track by track
+    Diff(DiffArgs),
+    /// Show how one track's cue-in and crossfade move with the thresholds
+    Sweep(SweepArgs),
+}
 
-    let start_next = f32::max(duration - next_time, 0.);
+#[derive(clap::Args)]
+#[command(group(clap::ArgGroup::new("ranges").required(true).multiple(true)))]
+struct SweepArgs {
+    /// The track to measure
+    path: PathBuf,
 
-    AnalyzeResult {
-        start_next,
-        cue_point: cue_time,
-        duration,
-        loudness,
-        path: path.to_string(),
-    }
-}
+    /// --level values to try, as FROM..TO in LU
+    #[arg(long, value_name = "FROM..TO", value_parser = sweep::parse_range, group = "ranges")]
+    level_range: Option<sweep::SweepRange>,
 
-fn main() {
-    let args = Args::parse();
+    /// --cue values to try, as FROM..TO in LU
+    #[arg(long, value_name = "FROM..TO", value_parser = sweep::parse_range, group = "ranges")]
+    cue_range: Option<sweep::SweepRange>,
 
-    let mut playlist_lines: Vec<String> = Vec::new();
+    /// Difference between the values tried
+    #[arg(long, default_value_t = 1.)]
+    step: f32,
 
-    let playlist_path = args.path.to_path_buf();
+    /// How to print the results
+    #[arg(short, long, value_enum, default_value_t = sweep::SweepFormat::Table)]
+    format: sweep::SweepFormat,
+}
 
-    let use_custom_path = if args.output.ne("") { true } else { false };
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// The earlier processed playlist
+    old: PathBuf,
 
-    let custom_pathbuf = PathBuf::from(args.output);
+    /// The later processed playlist
+    new: PathBuf,
 
-    println!("Processing playlist: {}", args.path.display());
+    /// How to print the differences
+    #[arg(short, long, value_enum, default_value_t = DiffFormat::Table)]
+    format: DiffFormat,
 
-    // remove last piece from the path of the original playlist and add the new one
-    let mut new_path = PathBuf::from(playlist_path);
-    let file_stem = match new_path.file_stem() {
-        Some(s) => s.to_string_lossy().to_string(),
-        None => panic!("Wrong output path"),
-    };
-    let new_filename = format!("{}-processed.m3u8", file_stem);
-    new_path.set_file_name(new_filename);
+    #[command(flatten)]
+    tolerances: Tolerances,
+}
 
-    let file = File::open(&args.path).unwrap();
-    let reader = BufReader::new(file);
+/// The subset of the command line that affects how a single track is analysed
+#[derive(Clone, Debug)]
+struct AnalyzeOptions {
+    level: f32,
+    cue: f32,
+    no_cue: bool,
+    #[cfg(feature = "beat-align")]
+    beat_align: bool,
+    cue_mode: CueMode,
+    onset_sensitivity: f32,
+    no_cross: bool,
+    cross_default: Option<f32>,
+    target_lufs: Option<f32>,
+    peak_mode: PeakMode,
+    dual_mono: bool,
+    trust_tags: bool,
+    speech_lra: f32,
+    speech_profile: bool,
+    speech_level: f32,
+    speech_cue: f32,
+    auto_profile: bool,
+    extra_overlap: f32,
+    channel_loudness: bool,
+    imbalance_threshold: f32,
+    qc: bool,
+    max_peak_count: f32,
+    max_dc_offset: f32,
+    noise_floor: bool,
+    hidden_silence: f32,
+    split_hidden: bool,
+    detect_applause: bool,
+    smooth: usize,
+    min_playable: f32,
+    tail_block_correction: bool,
+    detect_cold_endings: bool,
+    cold_ending_level: f32,
+    cold_ending_cross: f32,
+    emit_fade_out: bool,
+    emit_cue_out: bool,
+    cue_out_min_silence: f32,
+    max_fade_out: f32,
+    emit_fade_type: bool,
+    fade_min_drop: f32,
+    fade_linearity: f32,
+    range: Option<TimeRange>,
+    range_times: RangeTimes,
+    duration_regex: Option<Regex>,
+    loudness_regex: Option<Regex>,
+    strict_parse: bool,
+    min_coverage: f32,
+    strict: bool,
+    /// Segment tracks into chapters, only for chapter output
+    chapters: Option<ChapterSettings>,
+    verbose: bool,
+    dump_ffmpeg_cmd: bool,
+}
 
-    for line in reader.lines() {
-        if let Ok(s) = line {
-            if s != "#EXTM3U\n" {
-                playlist_lines.push(s);
-            }
+impl From<&Args> for AnalyzeOptions {
+    fn from(args: &Args) -> Self {
+        AnalyzeOptions {
+            level: args.level,
+            cue: args.cue,
+            no_cue: args.no_cue,
+            #[cfg(feature = "beat-align")]
+            beat_align: args.beat_align,
+            cue_mode: args.cue_mode,
+            onset_sensitivity: args.onset_sensitivity,
+            no_cross: args.no_cross,
+            cross_default: args.cross_default,
+            // only the first target goes to ffmpeg, the others are applied when writing
+            target_lufs: args.target_lufs.first().copied(),
+            peak_mode: args.peak_mode,
+            dual_mono: args.dual_mono_correction,
+            trust_tags: args.trust_tags,
+            speech_lra: args.speech_lra,
+            speech_profile: args.speech_profile,
+            speech_level: args.speech_level,
+            speech_cue: args.speech_cue,
+            auto_profile: args.auto_profile,
+            extra_overlap: args.extra_overlap,
+            channel_loudness: args.channel_loudness || args.imbalance_threshold.is_some(),
+            imbalance_threshold: args.imbalance_threshold.unwrap_or(IMBALANCE_THRESHOLD),
+            qc: args.qc || args.max_peak_count.is_some() || args.max_dc_offset.is_some(),
+            max_peak_count: args.max_peak_count.unwrap_or(qc::MAX_PEAK_COUNT),
+            max_dc_offset: args.max_dc_offset.unwrap_or(qc::MAX_DC_OFFSET),
+            noise_floor: args.noise_floor,
+            hidden_silence: args.hidden_silence,
+            split_hidden: args.split_hidden,
+            detect_applause: args.detect_applause,
+            smooth: args.smooth,
+            min_playable: args.min_playable,
+            tail_block_correction: args.tail_block_correction,
+            detect_cold_endings: args.detect_cold_endings,
+            cold_ending_level: args.cold_ending_level,
+            cold_ending_cross: args.cold_ending_cross,
+            emit_fade_out: args.emit_fade_out,
+            emit_cue_out: args.emit_cue_out,
+            cue_out_min_silence: args.cue_out_min_silence,
+            max_fade_out: args.max_fade_out,
+            emit_fade_type: args.emit_fade_type,
+            fade_min_drop: args.fade_min_drop,
+            fade_linearity: args.fade_linearity,
+            range: args.range,
+            range_times: args.range_times,
+            duration_regex: args.duration_regex.clone(),
+            loudness_regex: args.loudness_regex.clone(),
+            strict_parse: args.strict_parse,
+            min_coverage: args.min_coverage,
+            strict: args.strict,
+            chapters: None,
+            verbose: args.verbose,
+            dump_ffmpeg_cmd: args.dump_ffmpeg_cmd,
         }
     }
+}
 
+impl AnalyzeOptions {
     /*
-    We could just push the AnalyzeResults to the vector as they come, but since
-    we're doing this with threads, that would mess up the order of the tracks,
-    which may not be desired. So we make a vector with n "empty slots"
-    (for n tracks in the playlist) pushing n AnalyzeResults with default values
-    to the vector.
+    Applies the #BOUNDARIES-OPTS: options of an entry. They can only switch
+    things on for a track, so they don't clash with the command line.
     */
-    let results = Arc::new(Mutex::new(Vec::<AnalyzeResult>::new()));
-
-    for _line in &playlist_lines {
-        results.lock().unwrap().push(Default::default());
+    fn for_entry(&self, entry: &playlist::Entry) -> Result<AnalyzeOptions, String> {
+        let mut opts = self.clone();
+        for option in playlist::track_options(entry) {
+            match option {
+                "no-cue" => opts.no_cue = true,
+                "no-cross" => opts.no_cross = true,
+                other => return Err(format!("unknown track option '{}'", other)),
+            }
+        }
+        Ok(opts)
     }
 
-    playlist_lines
-        .par_iter_mut()
-        .enumerate()
-        .for_each(|(i, op)| {
-            let r = analyze(&op, args.level, args.cue);
-            results.lock().unwrap()[i] = r;
-        });
+    /// Everything that changes the results, so cached results from other
+    /// settings (or another version) aren't used
+    fn settings_key(&self) -> String {
+        let opts = AnalyzeOptions {
+            verbose: false,
+            dump_ffmpeg_cmd: false,
+            ..self.clone()
+        };
+        format!("{} {:?}", env!("CARGO_PKG_VERSION"), opts)
+    }
+}
 
-    println!(
-        "Done with analysis, now {} to output playlist: {}",
-        if args.append { "appending" } else { "writing" },
-        if use_custom_path {
-            custom_pathbuf.display()
-        } else {
-            new_path.display()
-        }
-    );
+/// Which set of thresholds a track was analysed with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Profile {
+    /// --level and --cue as given
+    #[default]
+    Default,
+    /// --speech-level and --speech-cue, with --speech-profile
+    Speech,
+    /// Somewhat dynamic material, with --auto-profile
+    Moderate,
+    /// Highly dynamic material like classical, with --auto-profile
+    Dynamic,
+}
 
-    let mut write_options = OpenOptions::new();
-    write_options.write(true);
-    if args.append {
-        write_options.append(true)
-    } else {
-        write_options.truncate(true)
-    };
+impl Profile {
+    /*
+    The --auto-profile decision table. Quiet passages in dynamic material sit far
+    below its integrated loudness, so the offsets get wider to keep them from
+    triggering the next track, and the long tail relaxation is off since these
+    tracks' ends are genuinely quiet rather than an afterthought.
 
-    let new_file = match write_options.open(if use_custom_path {
-        &custom_pathbuf
-    } else {
-        &new_path
-    }) {
-        Ok(fd) => fd,
-        Err(_) => File::create(if use_custom_path {
-            &custom_pathbuf
-        } else {
-            &new_path
-        })
-        .unwrap(),
-    };
-    let mut writer = BufWriter::new(new_file);
+    | profile  | LRA (LU) |    | crest (dB) | --level  | --cue    | long tail |
+    |----------|----------|----|------------|----------|----------|-----------|
+    | dynamic  | >= 12    | or | >= 18      | level+6  | cue+10   | off       |
+    | moderate | >= 8     | or | >= 13      | level+3  | cue      | on        |
+    | default  | below    |    | below      | level    | cue      | on        |
 
-    /*
-    We save the whole thing into a big string and then write that to avoid
-    writing (and saving) to the file multiple times unnecessarily
+    Brickwalled masters (crest around 6-8 dB) land in default.
     */
-    let mut result_string = String::new();
-
-    if !args.append {
-        result_string.push_str("#EXTM3U\n");
+    fn from_dynamics(lra: f32, crest: f32) -> Profile {
+        if lra >= 12. || crest >= 18. {
+            Profile::Dynamic
+        } else if lra >= 8. || crest >= 13. {
+            Profile::Moderate
+        } else {
+            Profile::Default
+        }
     }
 
-    for result in results.lock().unwrap().iter() {
-        let annotate = format!("annotate:liq_cue_in=\"{:.3}\",liq_cross_duration=\"{:.3}\",duration=\"{:.3}\",liq_amplify=\"{:.3}dB\":{}\n", 
-        result.cue_point, result.start_next, result.duration, (-23.) - result.loudness, result.path);
-        result_string.push_str(&annotate);
+    // level, cue, and whether the long tail relaxation applies
+    fn thresholds(self, opts: &AnalyzeOptions) -> (f32, f32, bool) {
+        match self {
+            Profile::Default => (opts.level, opts.cue, true),
+            Profile::Speech => (opts.speech_level, opts.speech_cue, true),
+            Profile::Moderate => (opts.level + 3., opts.cue, true),
+            Profile::Dynamic => (opts.level + 6., opts.cue + 10., false),
+        }
     }
+}
 
-    write!(writer, "{result_string}").unwrap();
+/// Where a hidden track sits in a file, in seconds
+#[derive(Clone, Serialize, Deserialize)]
+struct HiddenTrack {
+    /// End of the main track's audio, where the silence starts
+    main_end: f32,
+    /// Crossfade duration of the main track, counted back from main_end
+    main_cross: f32,
+    /// Cue-in of the hidden track
+    start: f32,
+    /// Written as an entry of its own (--split-hidden). Otherwise the
+    /// track's crossfade already leaves the hidden part out.
+    split: bool,
+}
 
-    println!("Done!")
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct AnalyzeResult {
+    start_next: f32,
+    cue_point: f32,
+    /// The cue-in found from the loudness, when --beat-align moved cue_point to a beat
+    #[serde(default)]
+    loudness_cue: Option<f32>,
+    /// Analysed without cue detection, the cue-in is 0
+    cue_disabled: bool,
+    /// Analysed without crossfade detection, start_next is --cross-default or 0
+    cross_disabled: bool,
+    /// Where the track ends inside its file, for CUE sheet tracks
+    cue_out: Option<f32>,
+    /// Seconds before the audio first rises above SILENCE_LEVEL
+    leading_silence: f32,
+    duration: f32,
+    loudness: f32,
+    /// Loudness liq_amplify brings the track to
+    target: f32,
+    loudness_source: LoudnessSource,
+    peak: Option<f32>,
+    /// Which peak `peak` and `crest` were measured with
+    #[serde(default)]
+    peak_mode: PeakMode,
+    lra: f32,
+    /// Sample peak to integrated loudness ratio in dB
+    crest: f32,
+    speechlike: bool,
+    /// Thresholds the track was analysed with
+    profile: Profile,
+    /// Estimated noise floor in LUFS, when --noise-floor found one
+    noise_floor: Option<f32>,
+    /// The crossfade was moved to before an applause tail
+    applause: bool,
+    /// The track stops at full level and got the short --cold-ending-cross
+    #[serde(default)]
+    cold_ending: bool,
+    /// Never rose above the crossfade level and got the QUIET_TRACK_CROSS crossfade
+    #[serde(default)]
+    quiet: bool,
+    /// The crossfade is 15 LU further down than the level, the tail was long and mattered
+    #[serde(default)]
+    long_tail: bool,
+    /// The crossfade was shortened to leave --min-playable seconds after the cue-in
+    #[serde(default)]
+    overlap_clamped: bool,
+    /// ffmpeg measured far less of the track than its duration, see --min-coverage
+    #[serde(default)]
+    truncated: bool,
+    /// Played back to back with its short neighbours by --group-short, set once the order is known
+    #[serde(default)]
+    grouped: bool,
+    /// The crossfade found from the track alone, when --pairwise-cross changed start_next
+    #[serde(default)]
+    own_cross: Option<f32>,
+    /// Loudness of the crossfade region in LU relative to the track's
+    outro_level: Option<f32>,
+    /// Loudness of the first INTRO_SPAN seconds after the cue-in, relative like outro_level
+    intro_level: Option<f32>,
+    /// dB taken off liq_amplify by --overlap-headroom, set once the order is known
+    #[serde(default)]
+    headroom_reduction: f32,
+    /// Gain from --overrides, used as is in place of the computed one
+    #[serde(default)]
+    amplify_override: Option<f32>,
+    /// Why the track looks broken, by --flag-below, set after analysis
+    #[serde(default)]
+    flag: Option<Flag>,
+    /// A hidden track found after a long silence
+    hidden: Option<HiddenTrack>,
+    /// Fade-out length in seconds with --emit-fade-out, 0 for a hard ending
+    fade_out: Option<f32>,
+    /// Shape of the ending with --emit-fade-type
+    fade_type: Option<FadeType>,
+    /// Where each chapter starts, with chapter output
+    #[serde(default)]
+    chapters: Vec<f32>,
+    /// RMS level of each channel in dBFS, empty unless --channel-loudness
+    channel_levels: Vec<f32>,
+    /// Channel levels differ by more than --imbalance-threshold
+    imbalanced: bool,
+    /// A mono file measured as dual mono, with --dual-mono-correction
+    #[serde(default)]
+    dual_mono: bool,
+    /// What astats found, with --qc, boxed to keep every result small without it
+    #[serde(default)]
+    qc: Option<Box<qc::Stats>>,
+    /// Near full scale and at its peak more often than --max-peak-count
+    #[serde(default)]
+    clipping: bool,
+    /// DC offset above --max-dc-offset
+    #[serde(default)]
+    dc_offset_high: bool,
+    path: String,
+}
+
+impl AnalyzeResult {
+    /// What a warning was printed for in its analysis, for --warnings-as-errors
+    fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.truncated {
+            warnings.push("measured far less than its duration");
+        }
+        if self.quiet {
+            warnings.push("never rises above the crossfade level");
+        }
+        warnings
+    }
+
+    /*
+    Puts hand-set values back in line with what the analysis guarantees: the
+    cue-in inside the track, no negative crossfade, and --min-playable seconds
+    between the two. The cue-in is kept, it's the one set on purpose more often.
+    Whether anything had to change.
+    */
+    fn keep_consistent(&mut self, min_playable: f32) -> bool {
+        let end = self.cue_out.unwrap_or(self.duration);
+        let cue_point = self.cue_point.clamp(0., f32::max(end, 0.));
+        let (start_next, clamped) =
+            keep_playable(end, cue_point, f32::max(self.start_next, 0.), min_playable);
+        let changed = cue_point != self.cue_point || start_next != self.start_next;
+        self.cue_point = cue_point;
+        self.start_next = start_next;
+        self.overlap_clamped |= clamped;
+        changed
+    }
+
+    /// Gain in dB that brings the track to the target loudness
+    fn amplify(&self) -> f32 {
+        if let Some(amplify) = self.amplify_override {
+            return amplify;
+        }
+        self.target - self.loudness - self.headroom_reduction
+    }
+
+    fn channel_imbalance(&self) -> Option<f32> {
+        channels::imbalance(&self.channel_levels)
+    }
+}
+
+/*
+Now we must find the last timestamp where the momentary loudness is volDrop LU
+below the track's overall loudness level. That level is nextLevel. `end` is where
+the audio we're looking at ends, the end of the file unless a hidden track was
+cut off.
+*/
+fn next_track_time(
+    measure: &[Measurement],
+    end: f32,
+    loudness: f32,
+    level: f32,
+    floor: Option<f32>,
+    long_tail: bool,
+) -> (f32, bool) {
+    let next_level = match floor {
+        Some(f) => f + level,
+        None => loudness - level,
+    };
+    let next_time = first_time_threshold(measure, next_level, true);
+
+    /*
+    Little piece of logic to fix "Bohemian Rhapsody" and other songs with a long
+    but important tail. Relative to the noise floor there's nowhere lower to go.
+    */
+    if long_tail && floor.is_none() && end - next_time > 15. {
+        return (
+            first_time_threshold(measure, loudness - level - 15., true),
+            true,
+        );
+    }
+
+    (next_time, false)
+}
+
+/*
+Looks for the first stretch of at least `min_length` seconds below SILENCE_LEVEL
+with audio on both sides. Returns the indices of its first block and of the
+first block after it.
+*/
+fn find_hidden_track(measure: &[Measurement], min_length: f32) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < measure.len() {
+        if measure[i].momentary >= SILENCE_LEVEL {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < measure.len() && measure[i].momentary < SILENCE_LEVEL {
+            i += 1;
+        }
+        if start > 0 && i < measure.len() && measure[i - 1].time - measure[start].time >= min_length
+        {
+            return Some((start, i));
+        }
+    }
+    None
+}
+
+fn mean(values: &[Measurement]) -> f32 {
+    values
+        .iter()
+        .map(|&Measurement { momentary: m, .. }| m)
+        .sum::<f32>()
+        / values.len() as f32
+}
+
+/*
+Moving average over `window` blocks centred on each one, to keep a noisy
+momentary loudness from crossing a threshold and coming back a few times. It
+blurs the curve, so a crossing can move by up to half the window.
+*/
+fn smooth(measure: &[Measurement], window: usize) -> Vec<Measurement> {
+    let half = window / 2;
+    (0..measure.len())
+        .map(|i| {
+            let around = &measure[i.saturating_sub(half)..(i + window - half).min(measure.len())];
+            Measurement {
+                time: measure[i].time,
+                momentary: mean(around),
+            }
+        })
+        .collect()
+}
+
+// average of the blocks from `from` to `to` as power, the way loudness adds up
+fn energy_mean(measure: &[Measurement], from: f32, to: f32) -> Option<f32> {
+    let powers: Vec<f32> = measure
+        .iter()
+        .filter(|&&Measurement { time: t, .. }| t >= from && t < to)
+        .map(|&Measurement { momentary: m, .. }| 10f32.powf(m / 10.))
+        .collect();
+    if powers.is_empty() {
+        return None;
+    }
+    Some(10. * (powers.iter().sum::<f32>() / powers.len() as f32).log10())
+}
+
+/*
+Applause is loud enough to keep the loudness drop from triggering until it dies
+away, but it's also very even: the level hovers around one value for as long as
+it lasts. Going back from the end of audio, the plateau runs for as long as each
+block stays within 4 LU of the plateau's mean so far. It counts as applause when:
+
+- it lasts at least MIN_APPLAUSE seconds,
+- it's flat, its first and last thirds within 2 LU of each other, and
+- the music before it was at least 4 LU louder, so there's a drop into it.
+
+A sustained final chord fails the last two: it decays steadily and it starts
+at the level of the music. Returns the time the music ended. ffmpeg's astats
+could tell noise from tone by the zero crossing rate, but only for the whole
+file, which says nothing about the tail.
+*/
+fn applause_start(measure: &[Measurement], last: usize) -> Option<f32> {
+    let mut start = last;
+    let mut sum = measure[last].momentary;
+    while start > 0 {
+        let m = measure[start - 1].momentary;
+        let plateau = sum / (last - start + 1) as f32;
+        if (m - plateau).abs() > 4. {
+            break;
+        }
+        sum += m;
+        start -= 1;
+    }
+
+    let plateau = &measure[start..=last];
+    if plateau[plateau.len() - 1].time - plateau[0].time < MIN_APPLAUSE {
+        return None;
+    }
+
+    let third = plateau.len() / 3;
+    if (mean(&plateau[..third]) - mean(&plateau[plateau.len() - third..])).abs() > 2. {
+        return None;
+    }
+
+    // the 5 seconds (50 blocks) of music before it
+    let before = &measure[start.saturating_sub(50)..start];
+    if before.is_empty() || mean(before) - mean(plateau) < 4. {
+        return None;
+    }
+
+    Some(plateau[0].time)
+}
+
+/// Why a track is flagged as probably broken
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Flag {
+    /// Digital silence, nothing above the absolute gate
+    Silent,
+    /// Below --flag-below, too quiet to be on purpose
+    Quiet,
+}
+
+impl Flag {
+    /*
+    ebur128 gates everything below -70 LUFS out, so a silent file comes back
+    at -70 or as -inf. Anything else under the threshold has some audio, just
+    far too little of it: a wrong channel, a failed decode.
+    */
+    fn classify(loudness: f32, threshold: f32) -> Option<Flag> {
+        if !loudness.is_finite() || loudness <= SILENCE_GATE {
+            Some(Flag::Silent)
+        } else if loudness < threshold {
+            Some(Flag::Quiet)
+        } else {
+            None
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Flag::Silent => "silent",
+            Flag::Quiet => "suspiciously quiet",
+        }
+    }
+}
+
+/// Shape of a track's ending, as Liquidsoap's fade types
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FadeType {
+    /// A steady, natural fade
+    Lin,
+    /// Level held until a late drop, like a sustained chord
+    Exp,
+    /// Barely any decay, a short fade is best
+    Sin,
+}
+
+impl FadeType {
+    fn name(self) -> &'static str {
+        match self {
+            FadeType::Lin => "lin",
+            FadeType::Exp => "exp",
+            FadeType::Sin => "sin",
+        }
+    }
+}
+
+/*
+Classifies the crossfade region, from `from` to the end of `tail`. If the
+loudness drops less than `min_drop` LU from its first fifth to its last there's
+no fade to speak of. Otherwise a straight line fit tells a steady decay (its r²
+at least `linearity`) from a level that holds and then falls away. Digital
+silence is counted as -70 so a few blocks of it don't dominate the fit.
+*/
+fn classify_tail(tail: &[Measurement], from: f32, min_drop: f32, linearity: f32) -> FadeType {
+    let points: Vec<Measurement> = tail
+        .iter()
+        .filter(|&&Measurement { time: t, .. }| t >= from)
+        .map(|&m| Measurement {
+            momentary: m.momentary.max(-70.),
+            ..m
+        })
+        .collect();
+    let fifth = points.len() / 5;
+    if fifth == 0 {
+        return FadeType::Sin;
+    }
+
+    let drop = mean(&points[..fifth]) - mean(&points[points.len() - fifth..]);
+    if drop < min_drop {
+        return FadeType::Sin;
+    }
+
+    let n = points.len() as f32;
+    let mean_t = points.iter().map(|p| p.time).sum::<f32>() / n;
+    let mean_m = mean(&points);
+    let (mut stt, mut smm, mut stm) = (0., 0., 0.);
+    for &Measurement {
+        time: t,
+        momentary: m,
+    } in &points
+    {
+        stt += (t - mean_t) * (t - mean_t);
+        smm += (m - mean_m) * (m - mean_m);
+        stm += (t - mean_t) * (m - mean_m);
+    }
+    let r2 = if stt * smm > 0. {
+        stm * stm / (stt * smm)
+    } else {
+        0.
+    };
+
+    if r2 >= linearity {
+        FadeType::Lin
+    } else {
+        FadeType::Exp
+    }
+}
+
+/*
+A fade starts where the track last sits near its loudness, within 3 LU, and ends
+where it has dropped 20 LU below it, by which point it's effectively gone. Music
+that stops dead covers that distance within a block or two; whatever silence
+follows doesn't count, so a hard ending comes out as 0 however it's padded.
+*/
+fn fade_out_length(measure: &[Measurement], loudness: f32) -> f32 {
+    let onset = match measure
+        .iter()
+        .rposition(|&Measurement { momentary: m, .. }| m >= loudness - 3.)
+    {
+        Some(i) => i,
+        None => return 0.,
+    };
+    let end = measure[onset..]
+        .iter()
+        .find(|&&Measurement { momentary: m, .. }| m < loudness - 20.)
+        .unwrap_or(&measure[measure.len() - 1]);
+    let length = end.time - measure[onset].time;
+    if length < MIN_FADE {
+        0.
+    } else {
+        length
+    }
+}
+
+/*
+The noise floor is taken as the 5th percentile of the momentary loudness. Digital
+silence (ffmpeg reports it as -120.7) isn't noise, so it's left out, otherwise a
+clean lead-in would put the floor at the bottom of the scale. Dense material has
+no quiet passages at all, and its "floor" ends up close to the loudness itself;
+that's reported as no floor.
+*/
+fn noise_floor(measure: &[Measurement], loudness: f32) -> Option<f32> {
+    let mut values: Vec<f32> = measure
+        .iter()
+        .map(|&Measurement { momentary: m, .. }| m)
+        .filter(|&m| m > -120.)
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    let floor = values[values.len() * 5 / 100];
+    if loudness - floor < MIN_FLOOR_GAP {
+        return None;
+    }
+    Some(floor)
+}
+
+// blocks (0.1s each) over which a rise counts as an onset
+const ONSET_SPAN: usize = 5;
+
+/*
+The first point where the loudness jumps by at least `sensitivity` LU within
+half a second, above the cue level so noise bursts don't count. An intro that
+swells in crosses the cue threshold long before the music properly starts, and
+this finds the start of the beat instead. The time returned is where the rise
+begins. None if nothing rises that fast.
+*/
+fn first_onset(measure: &[Measurement], cue_level: f32, sensitivity: f32) -> Option<f32> {
+    (ONSET_SPAN..measure.len())
+        .find(|&i| {
+            measure[i].momentary >= cue_level
+                && measure[i].momentary - measure[i - ONSET_SPAN].momentary >= sensitivity
+        })
+        .map(|i| measure[i - ONSET_SPAN].time)
+}
+
+/*
+The escape hatch for ffmpeg builds whose output we can't parse: the first capture
+group of the last line the regex matches. Durations can be HH:MM:SS.ss or plain
+seconds.
+*/
+fn regex_value(lines: &[&str], regex: &Regex) -> Option<String> {
+    lines
+        .iter()
+        .rev()
+        .find_map(|l| Some(regex.captures(l)?.get(1)?.as_str().to_string()))
+}
+
+// parses ffmpeg's HH:MM:SS.ss timestamps into seconds
+fn parse_hms(s: &str) -> Option<f32> {
+    let hms_split: Vec<f32> = s
+        .trim()
+        .split(':')
+        .map(|x| x.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if hms_split.len() != 3 {
+        return None;
+    }
+    let hours = hms_split[0] * 3600.00;
+    let minutes = hms_split[1] * 60.00;
+    let seconds = hms_split[2];
+    Some(hours + minutes + seconds)
+}
+
+/*
+The length ffmpeg reports for the input, from the "Duration: 00:03:25.04, start:
+0.000000, bitrate: 320 kb/s" header line. The label is looked for anywhere in the
+line and the value taken up to the comma, so spacing or fields after it don't
+matter. Streams without a known length (live inputs, broken headers) say N/A,
+which gives None like anything else that doesn't parse.
+*/
+fn header_duration(lines: &[&str]) -> Option<f32> {
+    lines.iter().find_map(|l| {
+        let after = &l[l.find("Duration:")? + "Duration:".len()..];
+        parse_hms(after.split(',').next()?)
+    })
+}
+
+// whether the first audio stream in ffmpeg's header is mono, as in "Audio: flac, 44100 Hz, mono, s16"
+fn header_mono(lines: &[&str]) -> bool {
+    lines
+        .iter()
+        .find_map(|l| Some(&l[l.find("Audio:")?..]))
+        .is_some_and(|audio| audio.split(", ").any(|field| field == "mono"))
+}
+
+fn parse_error(what: &str) -> String {
+    format!("couldn't parse {} from the ffmpeg output", what)
+}
+
+fn analyze(
+    path: &str,
+    region: Option<Region>,
+    opts: &AnalyzeOptions,
+    log: Option<&mut String>,
+) -> Result<AnalyzeResult, String> {
+    /*
+    Analyses file in filename, returns seconds to end-of-file of place where volume last drops to level
+    below average loudness, given in volDrop in LU.
+    Also determines file start, where monentary loudness leaps above a certain point given by volStart
+    Also encode and store a mezzanine file, if a mezzanine directory name is given
+    Make a list containing many points, 1/10 sec apart, where loudness is measured.
+    We need TIME and MOMENTARY LOUDNESS
+    We also need full INTEGRATED LOUDNESS
+    */
+
+    say!("Processing filename: {}", path);
+    let tagged = read_tags(path, opts);
+    analyze_from(path, region, opts, tagged, || {
+        measure_loudness(path, region, opts, log)
+    })
+}
+
+// The analysis given the tags, `measure` runs the ebur128 pass if it's needed
+fn analyze_from(
+    path: &str,
+    region: Option<Region>,
+    opts: &AnalyzeOptions,
+    tagged: Option<TaggedLoudness>,
+    measure: impl FnOnce() -> Result<String, String>,
+) -> Result<AnalyzeResult, String> {
+    if let Some(result) = tagged
+        .as_ref()
+        .filter(|_| tags_suffice(opts, region))
+        .and_then(|t| analyze_tagged(path, opts, t))
+    {
+        if opts.verbose {
+            say!("{}: loudness and duration from the tags, not decoded", path);
+        }
+        return Ok(result);
+    }
+    let output = measure()?;
+    analyze_measured(path, region, opts, &output, tagged)
+}
+
+fn read_tags(path: &str, opts: &AnalyzeOptions) -> Option<TaggedLoudness> {
+    if opts.trust_tags {
+        tags::read_loudness_tags(path)
+    } else {
+        None
+    }
+}
+
+/*
+Whether the tags alone can stand in for the ebur128 pass. That's only when
+nothing needs the momentary curve: no cue or crossfade detection, no profile
+chosen by the dynamics and nothing else measured from the audio. A CUE sheet
+track's tags are the whole file's, so those are always measured.
+*/
+fn tags_suffice(opts: &AnalyzeOptions, region: Option<Region>) -> bool {
+    opts.trust_tags
+        && opts.no_cue
+        && opts.no_cross
+        && region.is_none()
+        && opts.range.is_none()
+        && !opts.split_hidden
+        && !opts.speech_profile
+        && !opts.auto_profile
+        && !opts.qc
+        && !opts.noise_floor
+        && !opts.channel_loudness
+        && !opts.emit_fade_out
+        && !opts.emit_cue_out
+        && !opts.emit_fade_type
+        && opts.chapters.is_none()
+}
+
+// The result of a track taken from its tags, None when ffprobe didn't know the duration
+fn analyze_tagged(
+    path: &str,
+    opts: &AnalyzeOptions,
+    tagged: &TaggedLoudness,
+) -> Option<AnalyzeResult> {
+    let duration = tagged.duration?;
+    Some(AnalyzeResult {
+        start_next: f32::min(opts.cross_default.unwrap_or(0.), duration),
+        cue_disabled: true,
+        cross_disabled: opts.cross_default.is_none(),
+        duration,
+        loudness: tagged.loudness,
+        target: opts.target_lufs.unwrap_or(DEFAULT_TARGET),
+        loudness_source: tagged.source,
+        peak_mode: opts.peak_mode,
+        peak: tagged.peak,
+        path: path.to_string(),
+        ..Default::default()
+    })
+}
+
+// Runs ffmpeg's ebur128 filter over the track, returns what it printed
+fn measure_loudness(
+    path: &str,
+    region: Option<Region>,
+    opts: &AnalyzeOptions,
+    log: Option<&mut String>,
+) -> Result<String, String> {
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg.arg("-hide_banner").arg("-y");
+    if region.is_some_and(|r| r.raw) {
+        ffmpeg
+            .arg("-f")
+            .arg("s16le")
+            .arg("-ar")
+            .arg("44100")
+            .arg("-ac")
+            .arg("2");
+    }
+    // seeking on the input side makes every timestamp relative to the window
+    if let Some(region) = &region {
+        ffmpeg.arg("-ss").arg(region.start.to_string());
+        if let Some(end) = region.end {
+            ffmpeg.arg("-t").arg((end - region.start).to_string());
+        }
+    } else if let Some(range) = &opts.range {
+        ffmpeg
+            .arg("-ss")
+            .arg(range.start.to_string())
+            .arg("-t")
+            .arg((range.end - range.start).to_string());
+    }
+
+    /*
+    The target only changes what ffmpeg prints, but it keeps its log in line
+    with ours. ffmpeg takes whole LUFS between -23 and 0 and refuses to run with
+    anything else, so other targets are only applied on our side.
+    */
+    let peak = match opts.peak_mode {
+        PeakMode::Sample => "sample",
+        PeakMode::True => "true",
+    };
+    let filter = match opts.target_lufs {
+        Some(target) if target.fract() == 0. && (-23. ..=0.).contains(&target) => {
+            format!("ebur128=peak={}:target={}", peak, target)
+        }
+        _ => format!("ebur128=peak={}", peak),
+    };
+    /*
+    BS.1770 sums the channels' power, so one mono channel measures 3 LU below
+    the same signal on both channels of a stereo file, which is how it's heard.
+    dualmono counts it twice; it does nothing to anything that isn't mono.
+    */
+    let filter = if opts.dual_mono {
+        format!("{}:dualmono=true", filter)
+    } else {
+        filter
+    };
+    // ebur128 passes the audio on, so astats measures it in the same decode
+    let filter = if opts.qc {
+        format!("{},{}", filter, qc::FILTER)
+    } else {
+        filter
+    };
+
+    // We pass "-vn" because some music files have invalid images, which can't be processed by ffmpeg
+    ffmpeg
+        .arg("-i")
+        .arg(path)
+        .arg("-vn")
+        .arg("-af")
+        .arg(filter)
+        .arg("-f")
+        .arg("null")
+        .arg("null");
+    // on stderr, so it's there with --quiet and stays out of what scripts read
+    if opts.dump_ffmpeg_cmd {
+        eprintln!("{}", tracklog::command_line(&ffmpeg));
+    }
+    let test = if path == STDIN {
+        jobs::output_with_stdin(&mut ffmpeg, Stdio::inherit())
+    } else {
+        jobs::output(&mut ffmpeg)
+    }
+    .map_err(|e| format!("couldn't run ffmpeg: {}", e))?;
+
+    // from_utf8_lossy replaces wrong chars with question marks preventing crashes
+    let test = String::from_utf8_lossy(&test.stderr).to_string();
+
+    if let Some(log) = log {
+        log.push_str(&tracklog::command_line(&ffmpeg));
+        log.push('\n');
+        log.push_str(&test);
+    }
+
+    Ok(test)
+}
+
+/*
+The analysis proper, from ffmpeg's output. It's apart from running ffmpeg so
+the sweep subcommand can go through it with many thresholds after one pass.
+*/
+fn analyze_measured(
+    path: &str,
+    region: Option<Region>,
+    opts: &AnalyzeOptions,
+    output: &str,
+    tagged: Option<TaggedLoudness>,
+) -> Result<AnalyzeResult, String> {
+    let test: Vec<&str> = output.lines().collect();
+
+    let (measure, summary) = ebur128::parse_ebur128_measurements(output, opts.strict_parse)
+        .map_err(|e| match e {
+            ebur128::ParseError::Unparsed(_) => format!("--strict-parse: {}", e),
+            e => e.to_string(),
+        })?;
+
+    if measure.is_empty() {
+        // when ffmpeg can't open the file its last words are usually the reason
+        return Err(match test.last() {
+            Some(reason) => format!("couldn't measure: {}", reason),
+            None => String::from("couldn't measure"),
+        });
+    }
+
+    // get integrated loudness, unless the file's tags already told us
+    let measured_loudness: f32 = match &opts.loudness_regex {
+        Some(regex) => regex_value(&test, regex).and_then(|l| l.trim().parse().ok()),
+        None => summary.integrated,
+    }
+    .ok_or_else(|| parse_error("the integrated loudness"))?;
+    let measured_peak = summary.peak.ok_or_else(|| parse_error("the peak"))?;
+    let dual_mono = opts.dual_mono && header_mono(&test);
+    if dual_mono && opts.verbose {
+        say!(
+            "{}: mono, measured as dual mono ({:.1} LUFS, +3.0 LU over one channel)",
+            path,
+            measured_loudness
+        );
+    }
+    let (loudness, loudness_source, peak) = match &tagged {
+        Some(t) => (t.loudness, t.source, t.peak),
+        None => (
+            measured_loudness,
+            LoudnessSource::Measured,
+            Some(measured_peak),
+        ),
+    };
+
+    /*
+    How far the peaks stick out above the loudness, the peak to loudness ratio.
+    A brickwalled master sits at 6-8 dB, open dynamic material at 15 and more.
+    Both come from our own pass, tags or not, so they're measured the same way.
+    */
+    let crest = measured_peak - measured_loudness;
+    if opts.verbose && opts.trust_tags {
+        match &tagged {
+            Some(t) => say!(
+                "{}: using tagged loudness {:.1} LUFS ({:?}), measured {:.1} LUFS",
+                path,
+                t.loudness,
+                t.source,
+                measured_loudness
+            ),
+            None => say!("{}: no loudness tags, using measured loudness", path),
+        }
+    }
+
+    /*
+    Speech has pauses and varying emphasis, which shows up as a wide loudness
+    range, while most music (classical aside) is far more even. It's a coarse
+    classification but it comes for free from the pass we already did.
+    */
+    let lra = summary.lra.unwrap_or(0.);
+    let speechlike = lra >= opts.speech_lra;
+
+    let profile = if opts.speech_profile && speechlike {
+        if opts.verbose {
+            say!(
+                "{}: speech-like (LRA {:.1} LU), using speech profile",
+                path,
+                lra
+            );
+        }
+        Profile::Speech
+    } else if opts.auto_profile {
+        let profile = Profile::from_dynamics(lra, crest);
+        if opts.verbose {
+            say!(
+                "{}: LRA {:.1} LU, crest {:.1} dB, using {:?} profile",
+                path,
+                lra,
+                crest,
+                profile
+            );
+        }
+        profile
+    } else {
+        Profile::Default
+    };
+    let (level, cue, long_tail) = profile.thresholds(opts);
+
+    /*
+    Parse duration from the last progress update of the status line. Live or
+    malformed inputs can leave it at time=N/A, the last momentary measurement is
+    then as close as we get to where the audio ended.
+    */
+    let duration = match &opts.duration_regex {
+        Some(regex) => {
+            regex_value(&test, regex).and_then(|d| parse_hms(&d).or_else(|| d.trim().parse().ok()))
+        }
+        None => test
+            .iter()
+            .rev()
+            .find_map(|l| l.rfind("time=").map(|i| &l[i + 5..]))
+            .and_then(|t| parse_hms(t.split_whitespace().next().unwrap_or("")))
+            .or_else(|| measure.last().map(|&Measurement { time: t, .. }| t)),
+    }
+    .ok_or_else(|| parse_error("the duration"))?;
+
+    /*
+    ebur128 measures every 100ms, so a track should have about ten measurements
+    a second. Far fewer means ffmpeg gave up partway, on a decoder error say, or
+    the duration is wrong, and either way so is everything found from them.
+    Very short tracks are left out, a few missing blocks are a big share there.
+    */
+    let expected = duration * 10.;
+    let truncated =
+        duration >= MIN_COVERAGE_DURATION && (measure.len() as f32) < expected * opts.min_coverage;
+    if truncated {
+        let reason = format!(
+            "only {} measurements for {:.1}s of audio, {:.0}% of the expected",
+            measure.len(),
+            duration,
+            measure.len() as f32 / expected * 100.
+        );
+        if opts.strict {
+            return Err(format!("{} (--strict)", reason));
+        }
+        eprintln!("Warning: {}: {}", path, reason);
+    }
+
+    let floor = if opts.noise_floor {
+        let floor = noise_floor(&measure, loudness);
+        match floor {
+            Some(f) if opts.verbose => say!("{}: noise floor at {:.1} LUFS", path, f),
+            Some(_) => {}
+            None => say!(
+                "{}: no discernible noise floor, using loudness-relative thresholds",
+                path
+            ),
+        }
+        floor
+    } else {
+        None
+    };
+
+    /*
+    First, let us find the first timestamp where the momentary loudness is volStart below the
+    track's overall loudness level. That level is cueLevel. With a noise floor
+    it's the point where the signal rises volStart above the noise instead.
+    */
+    let cue_level = match floor {
+        Some(f) => f + cue,
+        None => loudness - cue,
+    };
+
+    // --smooth only steadies the threshold crossings, everything else sees the raw blocks
+    let smoothed;
+    let detect: &[Measurement] = if opts.smooth > 1 {
+        smoothed = smooth(&measure, opts.smooth);
+        &smoothed
+    } else {
+        &measure
+    };
+
+    // without cue detection the track is heard from the very start
+    let ebu_cue_time = if opts.no_cue {
+        0.
+    } else {
+        let threshold = first_time_threshold(detect, cue_level, false);
+        match opts.cue_mode {
+            CueMode::Threshold => threshold,
+            CueMode::Onset => {
+                first_onset(&measure, cue_level, opts.onset_sensitivity).unwrap_or(threshold)
+            }
+        }
+    };
+
+    /*
+    The EBU R.128 algorithm measures in 400ms blocks. Therefore, it marks 0.4s as the
+    start of the track, even if its audio begins at 0.0s. So, we must subtract 400ms
+    from the given time, then use either that time, or 0.0s (if the result is negative)
+    as our track starting point.
+    */
+    let cue_time = f32::max(0., ebu_cue_time - 0.4);
+
+    /*
+    The cue also waits for quiet intros, so the plain silence before the audio
+    is measured on its own, against an absolute level. A file that's silent
+    throughout is all leading silence.
+    */
+    let leading_silence = measure
+        .iter()
+        .find(|&&Measurement { momentary: m, .. }| m >= SILENCE_LEVEL)
+        .map_or(duration, |&Measurement { time: t, .. }| {
+            f32::max(t - 0.4, 0.)
+        });
+
+    /*
+    A hidden track after a long silence would otherwise have the crossfade
+    measured at its end, keeping all that silence on air. Unless it's split off
+    into an entry of its own, the track is taken to end where the silence starts.
+    */
+    let silence = if opts.hidden_silence > 0. {
+        find_hidden_track(&measure, opts.hidden_silence)
+    } else {
+        None
+    };
+    let (tail, detect_tail, end) = match silence {
+        Some((s, _)) if !opts.split_hidden => (&measure[..s], &detect[..s], measure[s].time - 0.4),
+        _ => (&measure[..], detect, duration),
+    };
+
+    let mut applause = false;
+    let mut cold_ending = false;
+    let mut quiet = false;
+    let mut tail_fallback = false;
+    let mut fade_type = None;
+    let start_next = if opts.no_cross {
+        // the playout handles transitions, or they all get --cross-default
+        f32::min(
+            opts.cross_default.unwrap_or(0.),
+            f32::max(duration - cue_time, 0.),
+        )
+    } else {
+        /*
+        A track that's still loud at its very last moment stops on purpose, and
+        the threshold search (and the long tail fallback after it) would only
+        find a crossfade point somewhere in the last bars, smearing the next
+        track over the hard stop. This is checked first so that fallback never
+        gets a say. A loud applause tail counts as a cold ending too.
+        */
+        cold_ending = opts.detect_cold_endings
+            && tail
+                .last()
+                .is_some_and(|&Measurement { momentary: m, .. }| {
+                    m >= loudness - opts.cold_ending_level
+                });
+        let mut next_time = if cold_ending {
+            if opts.verbose {
+                say!("{}: cold ending", path);
+            }
+            f32::max(end - opts.cold_ending_cross, 0.)
+        } else {
+            let (next_time, fell_back) =
+                next_track_time(detect_tail, end, loudness, level, floor, long_tail);
+            if fell_back && opts.verbose {
+                say!("{}: long tail, crossfading 15 LU further down", path);
+            }
+            tail_fallback = fell_back;
+            next_time
+        };
+
+        /*
+        A track that never gets above the crossfade level, all of it quiet, would
+        have the next one start right away and effectively be skipped on air. It
+        gets a short default crossfade instead.
+        */
+        if next_time <= 0. && !cold_ending {
+            eprintln!(
+                "Warning: {} never rises above the crossfade level, using a {}s crossfade",
+                path, QUIET_TRACK_CROSS
+            );
+            next_time = f32::max(end - QUIET_TRACK_CROSS, 0.);
+            quiet = true;
+        }
+
+        if opts.detect_applause && !cold_ending {
+            if let Some(last) = tail
+                .iter()
+                .rposition(|&Measurement { time: t, .. }| t == next_time)
+            {
+                if let Some(music_end) = applause_start(tail, last) {
+                    if opts.verbose {
+                        say!(
+                            "{}: applause from {:.1}s, crossfading before it",
+                            path,
+                            music_end
+                        );
+                    }
+                    next_time = music_end;
+                    applause = true;
+                    tail_fallback = false;
+                }
+            }
+        }
+
+        /*
+        The last block above the crossfade level is stamped with the end of its
+        400ms, like the cue-in, so the audio it measured started that much
+        earlier. Opt-in, as it moves every crossfade.
+        */
+        if opts.tail_block_correction && !cold_ending {
+            next_time = f32::max(next_time - 0.4, 0.);
+        }
+
+        if opts.emit_fade_type {
+            fade_type = Some(classify_tail(
+                tail,
+                next_time,
+                opts.fade_min_drop,
+                opts.fade_linearity,
+            ));
+        }
+
+        // --extra-overlap can't make the crossfade longer than what's heard of the track
+        f32::max(duration - next_time + opts.extra_overlap, 0.)
+            .min(f32::max(duration - cue_time, 0.))
+    };
+
+    let fade_out = if opts.emit_fade_out {
+        Some(fade_out_length(tail, loudness).min(opts.max_fade_out))
+    } else {
+        None
+    };
+
+    let mut chapters = match &opts.chapters {
+        Some(settings) => chapters::segment(&measure, loudness, settings),
+        None => Vec::new(),
+    };
+
+    // same 400ms correction as the cue
+    let mut hidden = silence.map(|(s, h)| {
+        let main_end = measure[s].time - 0.4;
+        let (mut main_next, _) =
+            next_track_time(&detect[..s], main_end, loudness, level, floor, long_tail);
+        if opts.tail_block_correction {
+            main_next = f32::max(main_next - 0.4, 0.);
+        }
+        HiddenTrack {
+            main_end,
+            main_cross: f32::max(main_end - main_next, 0.),
+            start: f32::max(measure[h].time - 0.4, 0.),
+            split: opts.split_hidden,
+        }
+    });
+    if let Some(h) = &hidden {
+        if opts.verbose {
+            say!(
+                "{}: hidden track at {:.1}s after silence from {:.1}s",
+                path,
+                h.start,
+                h.main_end
+            );
+        }
+    }
+
+    /*
+    With --beat-align the cue moves to the first beat after it, for beatmatched
+    transitions. aubio can't read a raw CD image. How far it moved is kept so
+    the loudness cue can be reported next to the snapped one.
+    */
+    #[cfg(feature = "beat-align")]
+    let (cue_time, beat_shift) =
+        if opts.beat_align && !opts.no_cue && !region.is_some_and(|r| r.raw) {
+            let offset = region
+                .map(|r| r.start)
+                .or(opts.range.as_ref().map(|r| r.start))
+                .unwrap_or(0.);
+            beats::align(path, offset, cue_time, opts.verbose)
+        } else {
+            (cue_time, None)
+        };
+    #[cfg(not(feature = "beat-align"))]
+    let beat_shift: Option<f32> = None;
+
+    /*
+    The crossfade is already kept from starting before the cue-in, but a short
+    or quiet track can still end up with next to nothing heard in between: a
+    three second stinger with a two second crossfade. The crossfade gives way
+    until --min-playable seconds are left, or it's gone. The cue stays where it
+    is, it's what keeps the track from starting on silence.
+    */
+    let (start_next, overlap_clamped) =
+        keep_playable(duration, cue_time, start_next, opts.min_playable);
+    if overlap_clamped && opts.verbose {
+        say!(
+            "{}: only {:.3}s between cue-in and crossfade, shortening the crossfade",
+            path,
+            duration - cue_time - start_next
+        );
+    }
+
+    let outro_level = energy_mean(tail, end - start_next, end).map(|l| l - loudness);
+    let intro_level = energy_mean(&measure, cue_time, cue_time + INTRO_SPAN).map(|l| l - loudness);
+
+    /*
+    With --emit-cue-out a track that ends in silence (or in a hidden track that
+    isn't split off) is cut where its audio stops, the end of the last block
+    above SILENCE_LEVEL, rather than playing the silence out under the next
+    track. The crossfade stays at the same moment of the track, so it's counted
+    back from the cue-out instead of the end.
+    */
+    let trailing_silence = tail
+        .iter()
+        .rev()
+        .find(|&&Measurement { momentary: m, .. }| m >= SILENCE_LEVEL)
+        .map(|&Measurement { time: t, .. }| duration - t)
+        .filter(|&s| opts.emit_cue_out && s >= opts.cue_out_min_silence);
+    let start_next = match trailing_silence {
+        Some(s) => {
+            if opts.verbose {
+                say!("{}: cue-out {:.3}s before the end", path, s);
+            }
+            f32::max(start_next - s, 0.)
+        }
+        None => start_next,
+    };
+
+    /*
+    With --range everything above was measured inside the window. The window has
+    to fit in the file, and in file time the cue moves forward by the window
+    start while the crossfade moves back by whatever follows the window.
+    */
+    let (cue_time, leading_silence, start_next, duration) = match (&region, &opts.range) {
+        /*
+        A CUE sheet track is its own track, so only the times counted from its
+        start move to file time. The crossfade stays relative to its end, where
+        the cue-out goes, and the duration becomes that end.
+        */
+        (Some(region), _) => {
+            if let Some(h) = &mut hidden {
+                h.main_end += region.start;
+                h.start += region.start;
+            }
+            chapters.iter_mut().for_each(|c| *c += region.start);
+            (
+                cue_time + region.start,
+                leading_silence + region.start,
+                start_next,
+                duration + region.start,
+            )
+        }
+        (None, Some(range)) => {
+            /*
+            The input header has the length of the whole file. Without one (N/A)
+            the file is taken to end where the window's audio did, which is all
+            that was measured.
+            */
+            let file_duration = header_duration(&test)
+                .unwrap_or_else(|| f32::max(range.start + duration, range.end));
+            if range.end > file_duration {
+                return Err(format!(
+                    "--range ends at {}s but the file is only {:.3}s long",
+                    range.end, file_duration
+                ));
+            }
+            match opts.range_times {
+                RangeTimes::File => {
+                    if let Some(h) = &mut hidden {
+                        h.main_end += range.start;
+                        h.start += range.start;
+                    }
+                    chapters.iter_mut().for_each(|c| *c += range.start);
+                    (
+                        cue_time + range.start,
+                        leading_silence + range.start,
+                        start_next + (file_duration - range.end),
+                        file_duration,
+                    )
+                }
+                RangeTimes::Window => (cue_time, leading_silence, start_next, duration),
+            }
+        }
+        (None, None) => (cue_time, leading_silence, start_next, duration),
+    };
+
+    let loudness_cue = beat_shift.map(|shift| cue_time - shift);
+
+    // a failed channel pass shouldn't cost us the boundaries we already have
+    // astats would measure the whole image, not the track
+    let channel_levels = if opts.channel_loudness && region.is_none() {
+        channels::measure_channel_levels(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't measure channel levels of {}: {}", path, e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let imbalanced =
+        channels::imbalance(&channel_levels).is_some_and(|i| i > opts.imbalance_threshold);
+
+    let qc = opts
+        .qc
+        .then(|| qc::parse_astats(output))
+        .flatten()
+        .map(Box::new);
+    if opts.qc && qc.is_none() {
+        eprintln!("Couldn't read the astats values of {}", path);
+    }
+
+    Ok(AnalyzeResult {
+        start_next,
+        cue_point: cue_time,
+        loudness_cue,
+        cue_disabled: opts.no_cue,
+        cross_disabled: opts.no_cross && opts.cross_default.is_none(),
+        cue_out: match trailing_silence {
+            Some(s) => Some(duration - s),
+            None => region.and_then(|r| r.end),
+        },
+        leading_silence,
+        duration,
+        loudness,
+        target: opts.target_lufs.unwrap_or(DEFAULT_TARGET),
+        loudness_source,
+        peak_mode: opts.peak_mode,
+        peak,
+        lra,
+        crest,
+        speechlike,
+        profile,
+        noise_floor: floor,
+        applause,
+        cold_ending,
+        quiet,
+        long_tail: tail_fallback,
+        overlap_clamped,
+        truncated,
+        grouped: false,
+        own_cross: None,
+        outro_level,
+        intro_level,
+        headroom_reduction: 0.,
+        amplify_override: None,
+        flag: None,
+        hidden,
+        fade_out,
+        fade_type,
+        chapters,
+        channel_levels,
+        imbalanced,
+        dual_mono,
+        clipping: qc.as_ref().is_some_and(|q| q.clipping(opts.max_peak_count)),
+        dc_offset_high: qc
+            .as_ref()
+            .is_some_and(|q| q.dc_offset_high(opts.max_dc_offset)),
+        qc,
+        path: path.to_string(),
+    })
+}
+
+// the crossfade of a track ending at `end`, shortened to leave `min_playable` seconds after the cue
+fn keep_playable(end: f32, cue: f32, cross: f32, min_playable: f32) -> (f32, bool) {
+    let clamped = end - cue - cross < min_playable && cross > 0.;
+    if clamped {
+        (f32::max(end - cue - min_playable, 0.), true)
+    } else {
+        (cross, false)
+    }
+}
+
+/*
+A panic in one track's analysis, say a parsing bug some odd file trips over,
+would otherwise take the whole run down with it. It becomes that track's error
+instead. The default hook has already printed where it happened.
+*/
+fn analyze_guarded(
+    path: &str,
+    region: Option<Region>,
+    opts: &AnalyzeOptions,
+    log: Option<&mut String>,
+) -> Result<AnalyzeResult, String> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| analyze(path, region, opts, log))).unwrap_or_else(
+        |payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown cause"));
+            Err(format!("analysis panicked: {}", message))
+        },
+    )
+}
+
+/*
+In --update mode the input is a playlist we processed earlier. Entries whose
+file still matches the fingerprint recorded above them are kept as they are,
+everything else (changed files, entries without a fingerprint, plain paths)
+goes through analysis again, under the path of the file it plays. Returns why
+each entry is skipped, if it is.
+*/
+fn update_skips(entries: &mut [playlist::Entry]) -> Vec<Option<String>> {
+    let mut skip = vec![None; entries.len()];
+    for (entry, skip) in entries.iter_mut().zip(skip.iter_mut()) {
+        if playlist::parse_annotate(&entry.path).is_none() {
+            continue;
+        }
+        // paths with colons were written as file:// URIs
+        let path = playlist::played_path(&entry.path);
+        let recorded = entry
+            .directives
+            .iter()
+            .find_map(|d| d.strip_prefix(playlist::FINGERPRINT_PREFIX));
+        if recorded.is_some() && recorded == playlist::fingerprint(&path).as_deref() {
+            *skip = Some(String::from("unchanged"));
+        } else {
+            entry.path = path;
+        }
+    }
+    skip
+}
+
+// the files a playlist plays, resolved, nothing for one that doesn't exist yet
+fn existing_paths(path: &Path) -> io::Result<HashSet<String>> {
+    match playlist::read_playlist(path) {
+        Ok(entries) => Ok(entries
+            .iter()
+            .map(|e| playlist::resolve(&playlist::played_path(&e.path)))
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// leaves out the entries whose file is in `existing`, returns how many
+fn skip_existing(entries: &mut Vec<playlist::Entry>, existing: &HashSet<String>) -> usize {
+    let before = entries.len();
+    entries.retain(|e| !existing.contains(&playlist::resolve(&e.path)));
+    before - entries.len()
+}
+
+// the first line of a --push-password-file, without its line ending
+fn read_password(path: &Path) -> io::Result<String> {
+    let content = std::fs::read_to_string(path)?;
+    let password = content.lines().next().unwrap_or_default();
+    if password.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the first line is empty",
+        ));
+    }
+    Ok(password.to_string())
+}
+
+fn read_annotated(path: &Path) -> io::Result<Vec<(String, diff::Values)>> {
+    Ok(playlist::read_annotations(path)?
+        .iter()
+        .map(|a| {
+            (
+                playlist::played_path(&a.path),
+                diff::Values::from_annotation(a),
+            )
+        })
+        .collect())
+}
+
+// read_annotated, ending the run when the playlist can't be read
+fn read_annotated_or_exit(path: &Path) -> Vec<(String, diff::Values)> {
+    read_annotated(path).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", path.display(), e);
+        summary::exit(EXIT_FATAL);
+    })
+}
+
+fn run_diff(args: &DiffArgs) {
+    let old = read_annotated_or_exit(&args.old);
+    let new = read_annotated_or_exit(&args.new);
+    let mut report = diff::diff(&old, &new, &args.tolerances);
+    // playlists from before the header, or written with --no-header, have none
+    report.old_runs = runinfo::read(&args.old).unwrap_or_default();
+    report.new_runs = runinfo::read(&args.new).unwrap_or_default();
+
+    match args.format {
+        DiffFormat::Table => print!("{}", diff::render_table(&report)),
+        DiffFormat::Json => print!("{}", diff::render_json(&report)),
+    }
+}
+
+/*
+Measures the track once and goes through the analysis again for every
+threshold value, which is cheap next to the ffmpeg pass. Every other option is
+at its default.
+*/
+fn run_sweep(args: &SweepArgs) {
+    if args.step <= 0. {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                "--step has to be above 0",
+            )
+            .exit();
+    }
+
+    let path = args.path.to_string_lossy();
+    let defaults = Args::parse_from([env!("CARGO_PKG_NAME"), &path]);
+    let opts = AnalyzeOptions::from(&defaults);
+    let fail = |e: String| -> ! {
+        eprintln!("Couldn't analyse {}: {}", path, e);
+        summary::exit(EXIT_FATAL);
+    };
+    let output = measure_loudness(&path, None, &opts, None).unwrap_or_else(|e| fail(e));
+
+    let sweep = |range: Option<sweep::SweepRange>,
+                 set: fn(&mut AnalyzeOptions, f32),
+                 get: fn(&AnalyzeResult) -> f32| {
+        let values = range.map(|r| r.values(args.step)).unwrap_or_default();
+        values
+            .into_iter()
+            .map(|threshold| {
+                let mut opts = opts.clone();
+                set(&mut opts, threshold);
+                let result =
+                    analyze_measured(&path, None, &opts, &output, None).unwrap_or_else(|e| fail(e));
+                sweep::Point {
+                    threshold: output::round3(threshold),
+                    value: output::round3(get(&result)),
+                }
+            })
+            .collect()
+    };
+    let report = sweep::SweepReport {
+        path: path.to_string(),
+        level: sweep(args.level_range, |o, v| o.level = v, |r| r.start_next),
+        cue: sweep(args.cue_range, |o, v| o.cue = v, |r| r.cue_point),
+    };
+
+    match args.format {
+        sweep::SweepFormat::Table => print!("{}", sweep::render_table(&report)),
+        sweep::SweepFormat::Json => print!("{}", sweep::render_json(&report)),
+    }
+}
+
+/*
+The line endings the playlist at `path` is written with. Appended lines take
+those of the file they're added to, when it has any, so it doesn't end up with
+both. Auto takes the input playlist's, \n when there's no telling.
+*/
+fn line_ending(args: &Args, playlist_path: &Path, path: &Path) -> output::LineEnding {
+    let existing = args
+        .append
+        .then(|| std::fs::read(path).ok())
+        .flatten()
+        .and_then(|bytes| output::LineEnding::of(&String::from_utf8_lossy(&bytes)));
+    // an update keeps the file as it was, line endings included, unless told otherwise
+    let requested = args.line_ending.unwrap_or(if args.update {
+        output::LineEnding::Auto
+    } else {
+        output::LineEnding::Lf
+    });
+    match (existing, requested) {
+        (Some(ending), _) => ending,
+        (None, output::LineEnding::Auto) => playlist::read_input(playlist_path)
+            .ok()
+            .and_then(|contents| output::LineEnding::of(&contents))
+            .unwrap_or(output::LineEnding::Lf),
+        (None, ending) => ending,
+    }
+}
+
+// makes sure the directory `path` goes into is there, creating it with --mkdir
+fn output_dir(path: &Path, mkdir: bool) -> Result<(), String> {
+    let Some(dir) = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty() && !d.exists())
+    else {
+        return Ok(());
+    };
+    if mkdir {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Couldn't create the directory {}: {}", dir.display(), e))
+    } else {
+        Err(format!(
+            "The output directory {} does not exist (--mkdir creates it)",
+            dir.display()
+        ))
+    }
+}
+
+fn write_output(path: &Path, append: bool, contents: &str) {
+    let mut write_options = OpenOptions::new();
+    write_options.write(true);
+    if append {
+        write_options.append(true)
+    } else {
+        write_options.truncate(true)
+    };
+
+    let new_file = match write_options.open(path) {
+        Ok(fd) => fd,
+        Err(_) => File::create(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't create {}: {}", path.display(), e);
+            summary::exit(EXIT_FATAL);
+        }),
+    };
+    let mut writer = BufWriter::new(new_file);
+
+    write!(writer, "{contents}").unwrap();
+    // we may exit() later on, which doesn't run destructors
+    writer.flush().unwrap();
+    summary::output(path);
+}
+
+// what an entry for the same file as an earlier one gets
+fn duplicate_outcome(first: &Outcome, entry: &playlist::Entry) -> Outcome {
+    match first {
+        Outcome::Analyzed(r) => Outcome::Analyzed(AnalyzeResult {
+            path: entry.path.clone(),
+            ..r.clone()
+        }),
+        other => other.clone(),
+    }
+}
+
+/*
+The middle and the spread of what a setting changes, to compare a --sample run
+with one on the same tracks with other settings.
+*/
+fn print_sample_statistics(tracks: &[Track]) {
+    let results: Vec<&AnalyzeResult> = tracks.iter().filter_map(Track::analyzed).collect();
+    if results.is_empty() {
+        return;
+    }
+    say!("Statistics of {} analysed tracks:", results.len());
+    for (name, unit, value) in [
+        (
+            "cue-in",
+            "s",
+            (|r| r.cue_point) as fn(&AnalyzeResult) -> f32,
+        ),
+        ("crossfade", "s", |r| r.start_next),
+        ("loudness", " LUFS", |r| r.loudness),
+        ("amplify", " dB", |r| r.amplify()),
+    ] {
+        let mut values: Vec<f32> = results
+            .iter()
+            .map(|r| value(r))
+            .filter(|v| v.is_finite())
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        values.sort_by(f32::total_cmp);
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        say!(
+            "  {:<10} median {:.2}{unit}, mean {:.2}{unit}, from {:.2} to {:.2}{unit}",
+            name,
+            values[values.len() / 2],
+            mean,
+            values[0],
+            values[values.len() - 1],
+        );
+    }
+}
+
+// a path with no file behind it is shown escaped, so a stray character in it is visible
+fn shown_path(path: &str) -> String {
+    if validate::is_remote(path) || Path::new(path).exists() {
+        path.to_string()
+    } else {
+        format!("{:?}", path)
+    }
+}
+
+// --input-format, or what the extension says
+fn input_format(args: &Args, path: &Path) -> InputFormat {
+    args.input_format
+        .unwrap_or_else(|| InputFormat::detect(path))
+}
+
+type Analyzer =
+    fn(&str, Option<Region>, &AnalyzeOptions, Option<&mut String>) -> Result<AnalyzeResult, String>;
+
+/*
+What analysing an entry takes, the same whether the playlist is collected or
+streamed as it's read, see analyze_playlist and stream_playlist. The counters
+are shared by every thread working on the playlist.
+*/
+struct Analysis<'a> {
+    args: &'a Args,
+    input: InputFormat,
+    options: AnalyzeOptions,
+    cache: Option<cache::Cache>,
+    // analyze_guarded, or what a test has instead
+    analyze: Analyzer,
+    failures: AtomicUsize,
+    // left as they were by --since
+    unchanged: AtomicUsize,
+    // --max-errors was reached
+    aborted: AtomicBool,
+    max_errors: Option<usize>,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(args: &'a Args, input: InputFormat, format: Format) -> Analysis<'a> {
+        let mut options = AnalyzeOptions::from(args);
+        if format == Format::Chapters {
+            options.chapters = Some(args.chapters);
+        }
+        let cache = args.cache.as_ref().and_then(|path| {
+            cache::Cache::load(path)
+                .map_err(|e| eprintln!("Couldn't read the cache {}: {}", path.display(), e))
+                .ok()
+        });
+        Analysis {
+            args,
+            input,
+            options,
+            cache,
+            analyze: analyze_guarded,
+            failures: AtomicUsize::new(0),
+            unchanged: AtomicUsize::new(0),
+            aborted: AtomicBool::new(false),
+            max_errors: if args.fail_fast {
+                Some(1)
+            } else {
+                args.max_errors
+            },
+        }
+    }
+
+    /*
+    What becomes of entry `i`, and whether it came from the cache. `real` is the
+    entry resolved to its real file, which the cache is keyed by, and `skip` why
+    it's passed through as it is, if it is.
+    */
+    fn entry(
+        &self,
+        i: usize,
+        entry: &playlist::Entry,
+        real: &playlist::Entry,
+        skip: Option<&str>,
+    ) -> (Outcome, bool) {
+        let args = self.args;
+        // once --max-errors is reached there's no point in starting anything else
+        if self.aborted.load(Ordering::SeqCst) {
+            return (Outcome::Skipped(String::from("not analysed")), false);
+        }
+
+        let mut log = String::new();
+        let mut cached = false;
+        let outcome = 'outcome: {
+            if let Some(reason) = skip {
+                break 'outcome Outcome::Skipped(reason.to_string());
+            }
+
+            let opts = match playlist::broken_link(&entry.path) {
+                Some(target) => Err(format!(
+                    "missing file, {} is a symlink to {}, which doesn't exist",
+                    entry.path, target
+                )),
+                None => self.options.for_entry(entry),
+            };
+
+            if let Some(r) = self
+                .cache
+                .as_ref()
+                .zip(opts.as_ref().ok())
+                .and_then(|(c, o)| c.get(real, &o.settings_key(), args.reanalyze_on_mismatch))
+            {
+                if args.verbose {
+                    say!("Using cached result for {}", entry.path);
+                }
+                cached = true;
+                // the cached one may have been analysed through another path
+                break 'outcome Outcome::Analyzed(AnalyzeResult {
+                    path: entry.path.clone(),
+                    ..r.clone()
+                });
+            }
+
+            // what --since leaves out and the cache doesn't have is passed through
+            if args
+                .since
+                .is_some_and(|since| !since::modified_after(&entry.path, since))
+            {
+                self.unchanged.fetch_add(1, Ordering::Relaxed);
+                break 'outcome Outcome::Skipped(String::from("not modified since --since"));
+            }
+
+            let log = args.log_dir.is_some().then_some(&mut log);
+            let analyzed = opts.and_then(|opts| {
+                if !args.per_input_output {
+                    return (self.analyze)(&entry.path, entry.region, &opts, log);
+                }
+                let key = (cache::key(real), opts.settings_key());
+                let (result, shared) = batch::analyze_once(key, || {
+                    (self.analyze)(&entry.path, entry.region, &opts, log)
+                });
+                if shared && args.verbose {
+                    say!("Using the result from another playlist for {}", entry.path);
+                }
+                // analysed through another playlist's path
+                result.map(|r| AnalyzeResult {
+                    path: entry.path.clone(),
+                    ..r
+                })
+            });
+            match analyzed {
+                Ok(r) => Outcome::Analyzed(r),
+                // killed because another track failed, that one is what gets reported
+                Err(_) if self.aborted.load(Ordering::SeqCst) => {
+                    Outcome::Skipped(String::from("cancelled"))
+                }
+                Err(e) => {
+                    if self.input == InputFormat::Csv {
+                        eprintln!(
+                            "Error processing {} (row {}): {}",
+                            shown_path(&entry.path),
+                            entry.line,
+                            e
+                        );
+                    } else {
+                        eprintln!("Error processing {}: {}", shown_path(&entry.path), e);
+                    }
+                    let failed = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+                    if self.max_errors.is_some_and(|max| failed >= max)
+                        && !self.aborted.swap(true, Ordering::SeqCst)
+                    {
+                        // what's still decoding would be thrown away anyway
+                        jobs::cancel();
+                    }
+                    Outcome::Failed(e)
+                }
+            }
+        };
+
+        /*
+        Only tracks that ran ffmpeg (or failed trying) have a log, cached and
+        skipped ones don't. With --log-failures-only the tracks that came out
+        fine are dropped, but not the ones --flag-below would flag.
+        */
+        let keep = match &outcome {
+            Outcome::Failed(reason) => {
+                log.push_str(&format!("# failed: {}\n", reason));
+                true
+            }
+            Outcome::Analyzed(_) if log.is_empty() => false,
+            Outcome::Analyzed(r) => {
+                !args.log_failures_only
+                    || r.truncated
+                    || Flag::classify(r.loudness, args.flag_below).is_some()
+            }
+            Outcome::Skipped(_) => false,
+        };
+        if let Some(dir) = args.log_dir.as_ref().filter(|_| keep) {
+            if let Err(e) = tracklog::write(dir, i + 1, &entry.path, &log) {
+                eprintln!("Couldn't write the log of {}: {}", entry.path, e);
+            }
+        }
+        (outcome, cached)
+    }
+
+    /*
+    Once every entry is done: says what --since left alone, and if --max-errors
+    was reached, ends the run without writing anything, after `record` has put
+    down what was done. Returns how many tracks failed.
+    */
+    fn finish(&self, stream: Option<&Mutex<stream::Stream>>, record: impl FnOnce()) -> usize {
+        let failures = self.failures.load(Ordering::SeqCst);
+        if self.args.since.is_some() {
+            say!(
+                "Left {} tracks not modified since --since as they were",
+                self.unchanged.load(Ordering::Relaxed)
+            );
+        }
+        if self.aborted.load(Ordering::SeqCst) {
+            record();
+            if self.args.fail_fast {
+                eprintln!("Aborting at the first failure (--fail-fast). Nothing was written.");
+            } else {
+                eprintln!(
+                    "Aborting: {} tracks failed, reaching --max-errors. Nothing was written.",
+                    failures
+                );
+            }
+            if let Some(stream) = stream {
+                stream.lock().unwrap().discard();
+            }
+            summary::exit(EXIT_FATAL);
+        }
+        failures
+    }
+}
+
+// the entry as the cache knows it, its real file without the directives
+fn real_entry(entry: &playlist::Entry) -> playlist::Entry {
+    playlist::Entry {
+        path: playlist::resolve(&entry.path),
+        directives: Vec::new(),
+        line: entry.line,
+        region: entry.region,
+    }
+}
+
+// playlist::clean_paths, warning about every path it had to change
+fn clean_paths(entries: &mut [playlist::Entry]) {
+    for (line, removed) in playlist::clean_paths(entries) {
+        let warning = format!(
+            "line {}: removed invisible characters from the path ({})",
+            line,
+            removed.join(", ")
+        );
+        eprintln!("Warning: {}", warning);
+        summary::warning(warning);
+    }
+}
+
+// playlist::normalize_paths, saying which paths it changed
+fn normalize_paths(entries: &mut [playlist::Entry]) {
+    for (line, form) in playlist::normalize_paths(entries) {
+        say!(
+            "line {}: the file was only found under the {} form of its path",
+            line,
+            form
+        );
+    }
+}
+
+// a .boundariesignore next to the playlist lists entries to pass through as they are
+fn ignore_rules(args: &Args, playlist_path: &Path) -> Option<ignore::IgnoreRules> {
+    if args.no_ignore_file {
+        return None;
+    }
+    let dir = playlist_path.parent().unwrap_or(Path::new(""));
+    match ignore::load(dir) {
+        Ok(rules) => {
+            if rules.is_some() && args.verbose {
+                say!("Using {}", dir.join(ignore::IGNORE_FILE_NAME).display());
+            }
+            rules
+        }
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", ignore::IGNORE_FILE_NAME, e);
+            None
+        }
+    }
+}
+
+// why an entry is skipped, if the ignore file lists it
+fn ignored(rules: Option<&ignore::IgnoreRules>, entry: &playlist::Entry) -> Option<String> {
+    rules
+        .filter(|rules| rules.is_ignored(&entry.path))
+        .map(|_| format!("matched {}", ignore::IGNORE_FILE_NAME))
+}
+
+/*
+Whether --stream writes the playlist as its lines are read, see
+stream_playlist. Whatever needs every entry or every result at once has the
+playlist collected first instead: another input format, --sample and
+--validate, the cache and the database, which hold every track anyway, and the
+outputs and reports made from all the results.
+*/
+fn streams_flat(args: &Args, input: InputFormat) -> bool {
+    let whole_playlist = args.sample.is_some()
+        || args.validate
+        || args.cache.is_some()
+        || args.report.is_some()
+        || args.csv.is_some()
+        || args.push_to.is_some()
+        || args.preview.is_some()
+        || args.diff_against.is_some()
+        || args.histogram
+        || args.album_report
+        || args.overlap_report;
+    #[cfg(feature = "sqlite")]
+    let whole_playlist = whole_playlist || args.sqlite.is_some();
+    args.stream && input == InputFormat::M3u && !whole_playlist
+}
+
+/*
+Whether a streamed playlist keeps a track for the notes printed at the end:
+failed ones, skipped ones with --verbose, and analysed ones one of the notes
+lists. Has to follow what the notes in `run` look at.
+*/
+fn noted(args: &Args, track: &Track) -> bool {
+    match &track.outcome {
+        Outcome::Failed(_) => true,
+        Outcome::Skipped(_) => args.verbose,
+        Outcome::Analyzed(r) => {
+            r.imbalanced
+                || r.clipping
+                || r.dc_offset_high
+                || r.applause
+                || r.overlap_clamped
+                || r.truncated
+                || r.hidden.is_some()
+                || Flag::classify(r.loudness, args.flag_below).is_some()
+                || args
+                    .max_leading_silence
+                    .is_some_and(|max| r.leading_silence > max)
+                || args.min_crest.is_some_and(|min| r.crest < min)
+        }
+    }
+}
+
+/// What's known of a streamed playlist as a whole, once it's written
+struct Played {
+    tracks: usize,
+    runtime: timeline::Clock,
+}
+
+/*
+Reads the playlist (or CUE sheet, or CSV export) and analyses its entries,
+everything up to rendering. Returns the entries with what happened to each, and
+how many failed. A playlist streamed as it's read only returns the tracks the
+notes at the end need, with what's known of the whole of it.
+*/
+fn analyze_playlist(
+    args: &Args,
+    playlist_path: &Path,
+    format: Format,
+    stream: Option<&Mutex<stream::Stream>>,
+    seed: u64,
+    existing: &HashSet<String>,
+) -> (Vec<playlist::Entry>, Vec<Outcome>, usize, Option<Played>) {
+    let input = input_format(args, playlist_path);
+    if let Some(stream) = stream.filter(|_| streams_flat(args, input)) {
+        let (entries, results, failures, played) =
+            stream_playlist(Analysis::new(args, input, format), playlist_path, stream);
+        return (entries, results, failures, Some(played));
+    }
+
+    let read = match input {
+        InputFormat::Cue => cuesheet::read_cue(playlist_path),
+        InputFormat::M3u => playlist::read_playlist(playlist_path),
+        InputFormat::Pls => plsinput::read_pls(playlist_path),
+        InputFormat::Csv => {
+            // clap can't tell an extension from a format, so this is checked here
+            let column = args.path_column.as_deref().unwrap_or_else(|| {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::MissingRequiredArgument,
+                        "a CSV input needs --path-column",
+                    )
+                    .exit()
+            });
+            csvinput::read_csv(playlist_path, column).map(|(entries, skipped)| {
+                if skipped > 0 {
+                    say!("Skipped {} rows without a file path", skipped);
+                }
+                entries
+            })
+        }
+    };
+    let mut entries = read.unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", playlist_path.display(), e);
+        summary::exit(EXIT_FATAL);
+    });
+    clean_paths(&mut entries);
+
+    // the same seed picks the same tracks, so two runs can be compared
+    if let Some(n) = args.sample.filter(|&n| n < entries.len()) {
+        let total = entries.len();
+        let picked = random::Rng::new(seed).sample(total, n);
+        let mut picked = picked.into_iter().peekable();
+        let mut i = 0;
+        entries.retain(|_| {
+            let keep = picked.next_if_eq(&i).is_some();
+            i += 1;
+            keep
+        });
+        say!("Sampled {} of {} tracks (seed {})", n, total, seed);
+    }
+
+    let mut skip: Vec<Option<String>> = if args.update {
+        update_skips(&mut entries)
+    } else {
+        vec![None; entries.len()]
+    };
+
+    normalize_paths(&mut entries);
+
+    // --skip-existing, the files the playlist appended to already has
+    if !existing.is_empty() {
+        let skipped = skip_existing(&mut entries, existing);
+        say!("Skipped {} tracks already in the output", skipped);
+    }
+
+    if args.validate {
+        let problems = validate::validate(&entries, args.validate_remote, args.validate_timeout);
+        for p in &problems {
+            say!("line {}: {}: {}", p.line, shown_path(&p.path), p.reason);
+        }
+        let unchecked = if args.validate_remote {
+            0
+        } else {
+            entries
+                .iter()
+                .filter(|e| validate::is_remote(&e.path))
+                .count()
+        };
+        say!(
+            "{} of {} entries OK",
+            entries.len() - problems.len() - unchecked,
+            entries.len()
+        );
+        if unchecked > 0 {
+            say!(
+                "{} remote entries not checked, see --validate-remote",
+                unchecked
+            );
+        }
+        if !problems.is_empty() {
+            summary::exit(EXIT_PARTIAL);
+        }
+        summary::exit(0);
+    }
+
+    let rules = ignore_rules(args, playlist_path);
+    for (entry, skip) in entries.iter().zip(skip.iter_mut()) {
+        if skip.is_none() {
+            *skip = ignored(rules.as_ref(), entry);
+        }
+    }
+
+    /*
+    Tracks finish in whatever order the threads get through them, but
+    queue::in_order hands them back in playlist order, so the outcomes are
+    pushed as they come and line up with the entries.
+    */
+    let results = Mutex::new(Vec::<Outcome>::with_capacity(entries.len()));
+    let analysis = Analysis::new(args, input, format);
+
+    /*
+    The same file can be reached through several paths: symlinked trees, ./ and
+    ../, a relative and an absolute one. Each entry is resolved to its real
+    file, which is what the cache is keyed by, and an entry for a file that's
+    already in the playlist with the same options takes the result of the first
+    one instead of being analysed again. Entries keep their own path in the
+    output unless --canonical-paths.
+    */
+    // only what the cache looks at, the directives would double what a long playlist takes
+    let real: Vec<playlist::Entry> = entries.iter().map(real_entry).collect();
+    if args.canonical_paths {
+        for (entry, real) in entries.iter_mut().zip(&real) {
+            entry.path = real.path.clone();
+        }
+    }
+    let mut first_of: HashMap<(String, String), usize> = HashMap::new();
+    let duplicate_of: Vec<Option<usize>> = (0..entries.len())
+        .map(|i| {
+            if skip[i].is_some() {
+                return None;
+            }
+            let settings = analysis.options.for_entry(&entries[i]).ok()?.settings_key();
+            let first = *first_of
+                .entry((cache::key(&real[i]), settings))
+                .or_insert(i);
+            (first != i).then_some(first)
+        })
+        .collect();
+
+    // cached results don't need storing again
+    let cached: Vec<AtomicBool> = entries.iter().map(|_| AtomicBool::new(false)).collect();
+
+    // with --stream, entries write themselves as soon as everything before them is done
+    let streamed = |i: usize| {
+        let Some(stream) = stream else { return };
+        let track_at = |j: usize| {
+            let results = results.lock().unwrap();
+            let outcome = match duplicate_of[j] {
+                Some(first) => duplicate_outcome(&results[first], &entries[j]),
+                None => results[j].clone(),
+            };
+            Track {
+                entry: entries[j].clone(),
+                outcome,
+            }
+            .rewritten(&args.path_rewrite)
+        };
+        if let Err(e) = stream.lock().unwrap().finish_entry(i, track_at) {
+            eprintln!("Couldn't write the playlist: {}", e);
+            summary::exit(EXIT_FATAL);
+        }
+    };
+    /*
+    Here every entry and its outcome stay in memory until the end, for whatever
+    needs the whole playlist, see streams_flat. They take a few hundred bytes
+    each. What a track takes while it's analysed, ffmpeg's output above all, is
+    only held by the entries in flight, at most queue::DEPTH for every thread.
+    */
+    let bound = rayon::current_num_threads() * queue::DEPTH;
+    let analyze_entry = |i: usize, entry: &playlist::Entry| -> Outcome {
+        // filled in from the first one once everything is done
+        if duplicate_of[i].is_some() {
+            return Outcome::Skipped(String::from("not analysed"));
+        }
+        let (outcome, from_cache) = analysis.entry(i, entry, &real[i], skip[i].as_deref());
+        cached[i].store(from_cache, Ordering::Relaxed);
+        outcome
+    };
+    // a duplicate comes after the entry it copies, so that one is always there to stream
+    queue::in_order(entries.iter(), bound, analyze_entry, |i, outcome| {
+        results.lock().unwrap().push(outcome);
+        streamed(i);
+    });
+
+    // duplicates get marked as well further down, but the first one is the one from the cache
+    let from_cache = cached.iter().filter(|c| c.load(Ordering::Relaxed)).count();
+    let mut failures = analysis.finish(stream, || {
+        summary::tracks(&entries, &results.lock().unwrap(), from_cache)
+    });
+    let Analysis { options, cache, .. } = analysis;
+
+    let mut results = std::mem::take(&mut *results.lock().unwrap());
+
+    for (i, first) in duplicate_of.iter().enumerate() {
+        let Some(first) = *first else { continue };
+        if args.verbose {
+            say!(
+                "{}: same file as line {}, using its result",
+                entries[i].path,
+                entries[first].line
+            );
+        }
+        if matches!(results[first], Outcome::Failed(_)) {
+            failures += 1;
+        }
+        results[i] = duplicate_outcome(&results[first], &entries[i]);
+        // the first one stores it
+        cached[i].store(true, Ordering::Relaxed);
+    }
+
+    // before sorting, while results still line up with `cached`
+    if let Some(mut cache) = cache {
+        for (((entry, real_entry), outcome), cached) in
+            entries.iter().zip(&real).zip(&results).zip(&cached)
+        {
+            if let Outcome::Analyzed(r) = outcome {
+                // only entries whose options parsed got this far
+                let settings = options.for_entry(entry).unwrap().settings_key();
+                if !cached.load(Ordering::Relaxed) {
+                    cache.insert(real_entry, &settings, r, args.reanalyze_on_mismatch);
+                }
+            }
+        }
+        if let Err(e) = cache.save() {
+            eprintln!("Couldn't write the cache: {}", e);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let analyzed =
+            entries
+                .iter()
+                .zip(&results)
+                .zip(&cached)
+                .filter_map(|((entry, outcome), cached)| match outcome {
+                    Outcome::Analyzed(r) => Some((entry, r, !cached.load(Ordering::Relaxed))),
+                    _ => None,
+                });
+        if let Err(e) = sqlite::Catalog::open(path).and_then(|mut c| c.store(analyzed)) {
+            eprintln!("Couldn't write to the database {}: {}", path.display(), e);
+        }
+    }
+
+    summary::tracks(&entries, &results, from_cache);
+    (entries, results, failures, None)
+}
+
+/*
+A --stream that reads the m3u a line at a time as the queue takes entries on,
+and writes every entry and lets go of it as soon as it's done, see
+streams_flat. However long the playlist, what's held is the entries in flight,
+at most queue::DEPTH for every thread, and the tracks the notes at the end
+name, see noted. A file that's in the playlist twice is analysed twice, since
+knowing it came before would take remembering every file.
+*/
+fn stream_playlist(
+    analysis: Analysis,
+    playlist_path: &Path,
+    stream: &Mutex<stream::Stream>,
+) -> (Vec<playlist::Entry>, Vec<Outcome>, usize, Played) {
+    let args = analysis.args;
+    let unreadable = |e: io::Error| -> ! {
+        eprintln!("Couldn't read {}: {}", playlist_path.display(), e);
+        stream.lock().unwrap().discard();
+        summary::exit(EXIT_FATAL);
+    };
+    let lines = playlist::entries(playlist_path).unwrap_or_else(|e| unreadable(e));
+    let rules = ignore_rules(args, playlist_path);
+
+    // a line that can't be read ends the playlist there, and the run once what came before is done
+    let unread = Mutex::new(None);
+    let entries = lines
+        .map_while(|entry| entry.map_err(|e| *unread.lock().unwrap() = Some(e)).ok())
+        .map(|mut entry| {
+            clean_paths(std::slice::from_mut(&mut entry));
+            normalize_paths(std::slice::from_mut(&mut entry));
+            entry
+        });
+
+    let mut noted_tracks = Vec::new();
+    let mut played = Played {
+        tracks: 0,
+        runtime: timeline::Clock::default(),
+    };
+    let bound = rayon::current_num_threads() * queue::DEPTH;
+    queue::in_order(
+        entries,
+        bound,
+        |i, mut entry: playlist::Entry| {
+            let real = real_entry(&entry);
+            if args.canonical_paths {
+                entry.path = real.path.clone();
+            }
+            let skip = ignored(rules.as_ref(), &entry);
+            let (outcome, cached) = analysis.entry(i, &entry, &real, skip.as_deref());
+            (Track { entry, outcome }, cached)
+        },
+        |i, (track, cached)| {
+            summary::track(&track.entry, &track.outcome, cached);
+            played.tracks += 1;
+            played.runtime.advance(&track);
+            let written = track.rewritten(&args.path_rewrite);
+            if let Err(e) = stream.lock().unwrap().finish_entry(i, |_| written.clone()) {
+                eprintln!("Couldn't write the playlist: {}", e);
+                summary::exit(EXIT_FATAL);
+            }
+            if noted(args, &track) {
+                noted_tracks.push(track);
+            }
+        },
+    );
+
+    if let Some(e) = unread.into_inner().unwrap() {
+        unreadable(e);
+    }
+    let failures = analysis.finish(Some(stream), || {});
+    let (entries, results) = noted_tracks
+        .into_iter()
+        .map(|t| (t.entry, t.outcome))
+        .unzip();
+    (entries, results, failures, played)
+}
+
+fn main() {
+    // the matches are kept for --summary-file, which records every option
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    match &args.command {
+        Some(Commands::Diff(diff_args)) => {
+            run_diff(diff_args);
+            return;
+        }
+        Some(Commands::Sweep(sweep_args)) => {
+            run_sweep(sweep_args);
+            return;
+        }
+        None => {}
+    }
+
+    QUIET.store(args.quiet, Ordering::Relaxed);
+
+    // the runs themselves happen in child processes, see watch::watch
+    if args.watch && std::env::var_os(watch::CHILD_VAR).is_none() {
+        let mut paths = vec![args.path.clone().unwrap()];
+        paths.extend(args.watch_dir.iter().cloned());
+        watch::watch(
+            &paths,
+            std::time::Duration::from_secs_f32(args.watch_debounce.max(0.)),
+        );
+    }
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+    if let Some(jobs) = args.jobs {
+        jobs::set_limit(jobs);
+    }
+
+    if args.single_stdin {
+        run_single_stdin(&args);
+    }
+    if args.per_input_output {
+        run_per_input(&args, &matches);
+    }
+
+    // clap makes sure we have a playlist or an analysis when there's no subcommand
+    let playlist_path = args
+        .path
+        .clone()
+        .or_else(|| args.from_analysis.clone())
+        .unwrap();
+    run(&args, &matches, playlist_path);
+}
+
+fn render_options(args: &Args, playlist_path: &Path, seed: u64) -> output::RenderOptions {
+    output::RenderOptions {
+        header: !args.append && !args.no_header,
+        fingerprints: args.fingerprints || args.update,
+        timeline_comments: args.timeline_comments,
+        cross_jitter: args.cross_jitter,
+        seed,
+        chapter_style: args.chapters.chapter_style,
+        omit_cue: args.omit_cue,
+        annotation_style: args.annotation_style,
+        comment_key: args.comment_key.clone(),
+        round: args.round,
+        extinf_duration: args.extinf_duration,
+        gain_format: args.gain_format,
+        gain_key: args.gain_key.clone(),
+        preset: args.preset,
+        emit_longtail: args.emit_longtail,
+        run_info: (!args.no_header).then(|| {
+            // with --append every run gets a block of its own after the last one
+            let mut info = runinfo::RunInfo::new(playlist_path, args.append);
+            let off = |disabled: bool, value: f32| {
+                if disabled {
+                    String::from("off")
+                } else {
+                    value.to_string()
+                }
+            };
+            info.set("level", off(args.no_cross, args.level));
+            info.set("cue", off(args.no_cue, args.cue));
+            let target = args.target_lufs.first().copied().unwrap_or(DEFAULT_TARGET);
+            info.set("target", target.to_string());
+            if args.preset != output::Preset::Liquidsoap {
+                let name = args.preset.to_possible_value().unwrap();
+                info.set("preset", name.get_name().to_string());
+            }
+            info
+        }),
+    }
+}
+
+/*
+--single-stdin: one file, piped in, analysed with the options given and its
+result printed to stdout, for a quick look at a file without a playlist.
+ffmpeg reads it once, from the start, so nothing that opens the file again
+(tags, per-channel levels, beats) goes with it.
+*/
+fn run_single_stdin(args: &Args) -> ! {
+    if std::io::stdin().is_terminal() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--single-stdin reads the audio from stdin, pipe a file into it",
+            )
+            .exit();
+    }
+    #[cfg(feature = "beat-align")]
+    if args.beat_align {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--beat-align reads the file again, it doesn't go with --single-stdin",
+            )
+            .exit();
+    }
+
+    match single_stdin(args, |opts| measure_loudness(STDIN, None, opts, None)) {
+        Ok(rendered) => print!("{}", rendered),
+        Err(e) => {
+            eprintln!("Couldn't analyse stdin: {}", e);
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+    std::process::exit(0);
+}
+
+// the --single-stdin result as it's printed, from what `measure` gets out of ffmpeg
+fn single_stdin(
+    args: &Args,
+    measure: impl FnOnce(&AnalyzeOptions) -> Result<String, String>,
+) -> Result<String, String> {
+    let opts = AnalyzeOptions::from(args);
+    let result =
+        measure(&opts).and_then(|output| analyze_measured(STDIN, None, &opts, &output, None))?;
+    let track = Track {
+        entry: playlist::Entry {
+            path: STDIN.to_string(),
+            directives: Vec::new(),
+            line: 1,
+            region: None,
+        },
+        outcome: Outcome::Analyzed(result),
+    };
+    let seed = args.seed.unwrap_or_else(random::Rng::seed_from_time);
+    let format = args.format.unwrap_or(Format::Json);
+    Ok(output::render(
+        format,
+        &[track],
+        &render_options(args, Path::new(STDIN), seed),
+    ))
+}
+
+/*
+With --per-input-output, every playlist is processed the way it would be on
+its own, each on a thread of its own. Their tracks all go to the one rayon pool
+(and --jobs limit), so a handful of short playlists keep every core busy
+without 24 processes fighting over them. A playlist that fails ends its own run
+and nothing else, see summary::exit.
+*/
+fn run_per_input(args: &Args, matches: &clap::ArgMatches) -> ! {
+    let mut given = vec![args.path.clone().unwrap()];
+    given.extend(args.more_paths.iter().cloned());
+    let inputs = batch::expand(&given, args.recursive).unwrap_or_else(|e| {
+        eprintln!("Couldn't list the playlists: {}", e);
+        std::process::exit(EXIT_FATAL);
+    });
+    if inputs.is_empty() {
+        eprintln!("No playlists found");
+        std::process::exit(EXIT_FATAL);
+    }
+
+    let started = summary::now();
+    let finished = batch::run_all(&inputs, matches, |path| {
+        run(args, matches, path.to_path_buf())
+    });
+
+    say!("Processed {} playlists:", inputs.len());
+    for f in &finished {
+        say!("  {}", f.describe());
+    }
+    say!("{}", summary::describe_total(&finished));
+    if batch::shared() > 0 {
+        say!(
+            "{} entries used the analysis of the same file in another playlist",
+            batch::shared()
+        );
+    }
+    // the worst of them, so a single failed playlist still shows
+    let code = finished.iter().map(|f| f.code).max().unwrap_or(0);
+    if let Some(file) = &args.summary_file {
+        summary::write_per_input(file, matches, started, &finished, code);
+    }
+    std::process::exit(code)
+}
+
+// everything from a single playlist (or analysis) to its output and the exit
+fn run(args: &Args, matches: &clap::ArgMatches, playlist_path: PathBuf) -> ! {
+    let use_custom_path = !args.output.is_empty();
+
+    let custom_pathbuf = PathBuf::from(&args.output);
+
+    let format = output::select_format(
+        args.format,
+        if use_custom_path {
+            Some(custom_pathbuf.as_path())
+        } else {
+            None
+        },
+        args.verbose,
+    );
+
+    // the other formats are whole documents, appending to them would corrupt the file
+    if args.append && format != Format::M3u {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--append is only supported for m3u output",
+            )
+            .exit();
+    }
+
+    // fingerprints only exist in m3u comments
+    if args.update && format != Format::M3u {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--update is only supported for m3u output",
+            )
+            .exit();
+    }
+
+    // waiting on a pipe or a file for answers that never come would look like a hang
+    if args.review && !std::io::stdin().is_terminal() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--review asks for every track, it needs a terminal on stdin",
+            )
+            .exit();
+    }
+
+    // the playlist would be written uncompressed under the .gz name
+    if args.update && !use_custom_path && playlist::is_gzipped(&playlist_path) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--update can't rewrite a gzipped playlist in place, name the output with --output",
+            )
+            .exit();
+    }
+
+    // a partial playlist under the usual name would pass for the whole one
+    if args.sample.is_some() && !use_custom_path && !args.no_file {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--sample writes a partial playlist, name it with --output",
+            )
+            .exit();
+    }
+
+    if args.split_every_seconds.is_some_and(|s| s <= 0.) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                "--split-every-seconds has to be above 0",
+            )
+            .exit();
+    }
+
+    if args.validate_timeout <= 0. {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                "--validate-timeout has to be above 0",
+            )
+            .exit();
+    }
+
+    if args.target_lufs.len() > 1 {
+        if args.skip_existing {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--skip-existing reads one playlist, it takes a single --target-lufs",
+                )
+                .exit();
+        }
+        if args.update {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--update rewrites one playlist, it takes a single --target-lufs",
+                )
+                .exit();
+        }
+        if use_custom_path && !args.output.contains("{target}") {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "with several --target-lufs, --output needs a {target} placeholder",
+                )
+                .exit();
+        }
+    }
+
+    // everything else needs all the results before it can write anything
+    if args.stream
+        && (format != Format::M3u || args.sort != SortKey::None || args.target_lufs.len() > 1)
+    {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--stream only writes m3u in playlist order, with a single --target-lufs",
+            )
+            .exit();
+    }
+
+    // every push is one playlist's worth of requests
+    if args.push_to.is_some() && args.target_lufs.len() > 1 {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--push-to takes a single --target-lufs",
+            )
+            .exit();
+    }
+
+    // two entries for one file need cue-out points, which only the annotate line has
+    if args.split_hidden && format != Format::M3u {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--split-hidden is only supported for m3u output",
+            )
+            .exit();
+    }
+
+    summary::start(args.summary_file.as_deref(), &playlist_path, matches);
+
+    /*
+    Checked before analysing anything, rather than finding out at the end that
+    the playlist has nowhere to go.
+    */
+    if use_custom_path {
+        if let Err(e) = output_dir(&custom_pathbuf, args.mkdir) {
+            eprintln!("{}", e);
+            summary::exit(EXIT_FATAL);
+        }
+    }
+
+    say!("Processing playlist: {}", playlist_path.display());
+
+    // remove last piece from the path of the original playlist and add the new one
+    let mut new_path = playlist_path.clone();
+    // show.m3u.gz gives show-processed.m3u8
+    let file_stem = match playlist::without_gz(&new_path).file_stem() {
+        Some(s) => s.to_string_lossy().to_string(),
+        None => panic!("Wrong output path"),
+    };
+    let new_filename = format!("{}-processed.{}", file_stem, format.extension());
+    new_path.set_file_name(new_filename);
+
+    // updating rewrites the processed playlist we were given
+    if args.update {
+        new_path = playlist_path.clone();
+    }
+
+    // read before the analysis, so a mistake in it doesn't waste one
+    let overrides = args.overrides.as_ref().map(|path| {
+        overrides::load(path, args.overrides_suffix).unwrap_or_else(|e| {
+            eprintln!("Couldn't read the overrides {}: {}", path.display(), e);
+            summary::exit(EXIT_FATAL);
+        })
+    });
+
+    let push_password = match &args.push_password_file {
+        Some(path) => Some(read_password(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read the password {}: {}", path.display(), e);
+            summary::exit(EXIT_FATAL);
+        })),
+        None => args.push_password.clone(),
+    };
+
+    let seed = args.seed.unwrap_or_else(random::Rng::seed_from_time);
+    if args.cross_jitter > 0. && args.verbose {
+        say!("Crossfade jitter seed: {}", seed);
+    }
+    let render_options = render_options(args, &playlist_path, seed);
+
+    let out_path = if use_custom_path {
+        custom_pathbuf.clone()
+    } else {
+        new_path.clone()
+    };
+    /*
+    Appending the same additions again would repeat them, so --skip-existing
+    leaves out the tracks the output already has. The paths it has are
+    compared with the input's as real files, so a relative and an absolute
+    path to the same one match. An output that doesn't exist yet has nothing.
+    */
+    let existing: HashSet<String> = if args.skip_existing {
+        existing_paths(&out_path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read {}: {}", out_path.display(), e);
+            summary::exit(EXIT_FATAL);
+        })
+    } else {
+        HashSet::new()
+    };
+
+    let stream = args.stream.then(|| {
+        // the number of entries isn't known yet, the stream grows with the first one
+        Mutex::new(
+            stream::Stream::create(
+                &out_path,
+                render_options.clone(),
+                line_ending(args, &playlist_path, &out_path),
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("Couldn't create {}: {}", out_path.display(), e);
+                summary::exit(EXIT_FATAL);
+            }),
+        )
+    });
+
+    let (entries, results, failures, played) = match &args.from_analysis {
+        Some(path) => match intermediate::load(path) {
+            Ok((entries, results)) => {
+                let failures = results
+                    .iter()
+                    .filter(|o| matches!(o, Outcome::Failed(_)))
+                    .count();
+                summary::tracks(&entries, &results, 0);
+                (entries, results, failures, None)
+            }
+            Err(e) => {
+                eprintln!("Couldn't read the analysis {}: {}", path.display(), e);
+                summary::exit(EXIT_FATAL);
+            }
+        },
+        None => analyze_playlist(
+            args,
+            &playlist_path,
+            format,
+            stream.as_ref(),
+            seed,
+            &existing,
+        ),
+    };
+
+    if let Some(stream) = stream {
+        if let Err(e) = stream.into_inner().unwrap().close() {
+            eprintln!("Couldn't write {}: {}", out_path.display(), e);
+            summary::exit(EXIT_FATAL);
+        }
+        summary::output(&out_path);
+    }
+
+    if let Some(path) = &args.save_analysis {
+        if let Err(e) = intermediate::save(path, &entries, &results) {
+            eprintln!("Couldn't write the analysis {}: {}", path.display(), e);
+            summary::exit(EXIT_FATAL);
+        }
+        if args.quiet {
+            println!("{}", path.display());
+        }
+        say!("Analysis written to {}", path.display());
+        summary::output(path);
+        if failures > 0 {
+            eprintln!("{} of {} tracks failed", failures, entries.len());
+            summary::exit(EXIT_PARTIAL);
+        }
+        check_warnings(args);
+        summary::exit(0);
+    }
+
+    let mut tracks: Vec<Track> = entries
+        .into_iter()
+        .zip(results)
+        .map(|(entry, outcome)| Track { entry, outcome })
+        .collect();
+
+    // hand-set values go in before anything that depends on the crossfades
+    let unmatched_overrides = match &overrides {
+        Some(overrides) => {
+            let applied = overrides.apply(&mut tracks, args.min_playable);
+            for warning in applied.warnings {
+                eprintln!("Warning: {}", warning);
+                summary::warning(warning);
+            }
+            summary::overridden(applied.count);
+            say!("{} tracks had overrides applied", applied.count);
+            applied.unmatched
+        }
+        None => Vec::new(),
+    };
+
+    for r in tracks.iter_mut().filter_map(Track::analyzed_mut) {
+        r.flag = Flag::classify(r.loudness, args.flag_below);
+    }
+    let flagged: Vec<(String, Flag, f32)> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter_map(|r| Some((r.path.clone(), r.flag?, r.loudness)))
+        .collect();
+    if args.exclude_flagged {
+        tracks.retain(|t| t.analyzed().is_none_or(|r| r.flag.is_none()));
+    }
+
+    /*
+    Only now that everything is in its slot can we reorder. Whatever looks at a
+    track's neighbours (grouping, pairwise crossfades, overlap loudness, start
+    times) comes after this, so it sees them in the order they're written.
+    */
+    playlist::sort_tracks(&mut tracks, args.sort, args.reverse);
+
+    let groups = if args.group_short {
+        group::play_through(&mut tracks, args.min_duration)
+    } else {
+        Vec::new()
+    };
+
+    let pair_adjustments = if args.pairwise_cross {
+        pairwise::adjust(&mut tracks, args.pairwise_min_share)
+    } else {
+        Vec::new()
+    };
+
+    // crossfades depend on what follows, which is only known now
+    let loud_transitions = match args.overlap_ceiling {
+        Some(ceiling) => headroom::check(&mut tracks, ceiling, args.overlap_headroom),
+        None => Vec::new(),
+    };
+
+    // the final values, after everything that changes them, for a person to check
+    if args.review {
+        let stdin = std::io::stdin();
+        match review::review(
+            &mut tracks,
+            args.min_playable,
+            stdin.lock(),
+            std::io::stdout(),
+        ) {
+            Ok(review::Reviewed::Done(edits)) => {
+                summary::reviewed(&edits);
+                say!("{} changes made in review", edits.len());
+            }
+            Ok(review::Reviewed::Aborted) => {
+                eprintln!("Review aborted. Nothing was written.");
+                summary::exit(EXIT_FATAL);
+            }
+            Err(e) => {
+                eprintln!("Couldn't review the tracks: {}", e);
+                summary::exit(EXIT_FATAL);
+            }
+        }
+    }
+
+    // analysed with local paths, written with the ones the playlist is served from
+    let emitted: Vec<Track> = tracks
+        .iter()
+        .map(|t| t.rewritten(&args.path_rewrite))
+        .collect();
+
+    /*
+    With several targets every one gets a playlist of its own, named from the
+    --output template or with the target after the default name.
+    */
+    let outputs: Vec<(Option<f32>, PathBuf)> = if args.target_lufs.len() > 1 {
+        args.target_lufs
+            .iter()
+            .map(|&target| {
+                let path = if use_custom_path {
+                    PathBuf::from(args.output.replace("{target}", &target.to_string()))
+                } else {
+                    new_path.with_file_name(format!(
+                        "{}-processed{:+}.{}",
+                        file_stem,
+                        target,
+                        format.extension()
+                    ))
+                };
+                (Some(target), path)
+            })
+            .collect()
+    } else {
+        vec![(None, out_path.clone())]
+    };
+
+    /*
+    Split, every output is written in the same parts, each a playlist of its own
+    with its own header, named after the output with the part's number.
+    */
+    let split = match (args.split_every_tracks, args.split_every_seconds) {
+        (Some(n), _) => Some(split::Split::Tracks(n as usize)),
+        (None, Some(s)) => Some(split::Split::Seconds(s)),
+        (None, None) => None,
+    };
+    let parts = match split {
+        Some(split) => split::parts(&emitted, split),
+        None => std::iter::once(0..emitted.len()).collect(),
+    };
+    let outputs: Vec<(Option<f32>, PathBuf, usize)> = match split {
+        Some(_) => outputs
+            .into_iter()
+            .flat_map(|(target, path)| {
+                (0..parts.len()).map(move |n| (target, split::part_path(&path, n + 1), n))
+            })
+            .collect(),
+        None => outputs
+            .into_iter()
+            .map(|(target, path)| (target, path, 0))
+            .collect(),
+    };
+
+    if args.stream {
+        say!(
+            "Done with analysis, playlist written to {}",
+            out_path.display()
+        );
+    } else if args.no_file {
+        say!("Done with analysis");
+    } else {
+        say!(
+            "Done with analysis, now {} to output playlist{}: {}",
+            if args.append { "appending" } else { "writing" },
+            if outputs.len() > 1 { "s" } else { "" },
+            outputs
+                .iter()
+                .map(|(_, path, _)| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        /*
+        We render the whole thing into a big string and then write that to avoid
+        writing (and saving) to the file multiple times unnecessarily
+        */
+        for (target, path, n) in &outputs {
+            let part = &emitted[parts[*n].clone()];
+            let mut opts = render_options.clone();
+            if let (Some(info), Some(_)) = (&mut opts.run_info, split) {
+                info.set("part", format!("{} of {}", n + 1, parts.len()));
+            }
+            let result_string = match target {
+                // the analysis is the same, only the gain changes
+                Some(target) => {
+                    let mut retargeted = part.to_vec();
+                    for r in retargeted.iter_mut().filter_map(Track::analyzed_mut) {
+                        r.target = *target;
+                    }
+                    if let Some(info) = &mut opts.run_info {
+                        info.set("target", target.to_string());
+                    }
+                    output::render(format, &retargeted, &opts)
+                }
+                None => output::render(format, part, &opts),
+            };
+            let ending = line_ending(args, &playlist_path, path);
+            write_output(path, args.append, &ending.apply(&result_string));
+            if split.is_some() {
+                let duration = timeline::timeline(part).total;
+                say!(
+                    "Part {}: {} tracks, {} on air",
+                    n + 1,
+                    part.len(),
+                    timeline::hms(duration)
+                );
+                summary::part(path, part.len(), duration);
+            }
+        }
+    }
+
+    // side outputs from the same results, whatever the main format is
+    for (path, format, what) in [
+        (&args.report, Format::Json, "JSON report"),
+        (&args.csv, Format::Csv, "CSV"),
+    ] {
+        if let Some(path) = path {
+            write_output(
+                path,
+                false,
+                &output::render(format, &emitted, &render_options),
+            );
+            say!("{} written to {}", what, path.display());
+        }
+    }
+
+    /*
+    The requests are the annotated lines the m3u would have, one per entry (two
+    for a split hidden track), comments left out.
+    */
+    let mut push_failed = false;
+    if let Some(address) = &args.push_to {
+        let mut m3u = output::M3uWriter::new(render_options.clone());
+        let uris: Vec<String> = emitted
+            .iter()
+            .flat_map(|t| {
+                m3u.entry(t)
+                    .lines()
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        match push::push(
+            address,
+            push_password.as_deref(),
+            &args.push_command,
+            &args.push_queue,
+            &uris,
+        ) {
+            Ok(replies) => {
+                let refused: Vec<&push::Reply> = replies.iter().filter(|r| !r.ok).collect();
+                if args.verbose {
+                    for r in replies.iter().filter(|r| r.ok) {
+                        say!("Pushed {}: {}", r.uri, r.answer);
+                    }
+                }
+                say!(
+                    "Pushed {} of {} requests to {}",
+                    replies.len() - refused.len(),
+                    replies.len(),
+                    address
+                );
+                for r in &refused {
+                    eprintln!("  refused {}: {}", r.uri, r.answer);
+                }
+                push_failed = !refused.is_empty();
+            }
+            Err(e) => {
+                eprintln!("Couldn't push to {}: {}", address, e);
+                push_failed = true;
+            }
+        }
+    }
+
+    // rendered from the local paths, the ones ffmpeg can read
+    let mut preview_failed = false;
+    if let Some(path) = &args.preview {
+        match preview::render(&tracks, args.preview_excerpt, path) {
+            Ok(transitions) => {
+                say!(
+                    "Preview of {} transitions written to {}",
+                    transitions,
+                    path.display()
+                );
+                summary::output(path);
+            }
+            Err(e) => {
+                eprintln!("Couldn't render the preview {}: {}", path.display(), e);
+                preview_failed = true;
+            }
+        }
+    }
+
+    if args.target_lufs.len() > 1 {
+        say!("Wrote {} playlists:", outputs.len());
+        for (target, path, _) in &outputs {
+            say!("  {} LUFS  {}", target.unwrap(), path.display());
+        }
+    }
+
+    if let Some(old) = &args.diff_against {
+        let new: Vec<(String, diff::Values)> = emitted
+            .iter()
+            .filter_map(|t| Some((t.entry.path.clone(), diff::Values::from_track(t)?)))
+            .collect();
+        let report = diff::diff(&read_annotated_or_exit(old), &new, &args.tolerances);
+        say!("Differences against {}:", old.display());
+        say!("{}", diff::render_table(&report).trim_end());
+    }
+
+    let imbalanced: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.imbalanced)
+        .collect();
+    if !imbalanced.is_empty() {
+        say!(
+            "{} tracks with a channel imbalance above {} dB:",
+            imbalanced.len(),
+            args.imbalance_threshold.unwrap_or(IMBALANCE_THRESHOLD)
+        );
+        for r in imbalanced {
+            say!("  {:.1} dB  {}", r.channel_imbalance().unwrap(), r.path);
+        }
+    }
+
+    let clipping: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.clipping)
+        .collect();
+    if !clipping.is_empty() {
+        say!(
+            "{} tracks look clipped, more than {} samples at a peak of {} dBFS or above:",
+            clipping.len(),
+            args.max_peak_count.unwrap_or(qc::MAX_PEAK_COUNT),
+            qc::CLIP_LEVEL
+        );
+        for r in clipping {
+            let qc = r.qc.as_ref().unwrap();
+            say!(
+                "  {} samples at {:.2} dBFS  {}",
+                qc.peak_count,
+                qc.peak_level,
+                r.path
+            );
+        }
+    }
+
+    let dc_offset: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.dc_offset_high)
+        .collect();
+    if !dc_offset.is_empty() {
+        say!(
+            "{} tracks with a DC offset above {}:",
+            dc_offset.len(),
+            args.max_dc_offset.unwrap_or(qc::MAX_DC_OFFSET)
+        );
+        for r in dc_offset {
+            say!("  {:.4}  {}", r.qc.as_ref().unwrap().dc_offset, r.path);
+        }
+    }
+
+    let applause: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.applause)
+        .collect();
+    if !applause.is_empty() {
+        say!(
+            "{} tracks crossfade before an applause tail, worth a listen:",
+            applause.len()
+        );
+        for r in applause {
+            say!("  {}", r.path);
+        }
+    }
+
+    let clamped: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.overlap_clamped)
+        .collect();
+    if !clamped.is_empty() {
+        say!(
+            "{} tracks had their crossfade shortened to play at least {}s:",
+            clamped.len(),
+            args.min_playable
+        );
+        for r in clamped {
+            say!("  {}", r.path);
+        }
+    }
+
+    if !groups.is_empty() {
+        say!(
+            "{} runs of elements shorter than {}s played back to back:",
+            groups.len(),
+            args.min_duration
+        );
+        for run in &groups {
+            say!(
+                "  {} elements from {}",
+                run.len(),
+                tracks[run.start].entry.path
+            );
+        }
+    }
+
+    let truncated: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.truncated)
+        .collect();
+    if !truncated.is_empty() {
+        say!(
+            "{} tracks were only partly measured, their values are likely wrong:",
+            truncated.len()
+        );
+        for r in truncated {
+            say!("  {}", r.path);
+        }
+    }
+
+    if !pair_adjustments.is_empty() {
+        say!(
+            "{} crossfades shortened for a loud opening of the next track:",
+            pair_adjustments.len()
+        );
+        for a in &pair_adjustments {
+            say!(
+                "  {:.2}s -> {:.2}s  {} -> {}",
+                a.own,
+                a.adjusted,
+                a.from,
+                a.to
+            );
+        }
+    }
+
+    if !unmatched_overrides.is_empty() {
+        say!(
+            "{} override rows matched no track, maybe stale:",
+            unmatched_overrides.len()
+        );
+        for row in &unmatched_overrides {
+            say!("  line {}  {}", row.line, row.path);
+        }
+    }
+
+    if !flagged.is_empty() {
+        say!(
+            "{} tracks look broken{}:",
+            flagged.len(),
+            if args.exclude_flagged {
+                ", left out of the playlist"
+            } else {
+                ""
+            }
+        );
+        for (path, flag, loudness) in &flagged {
+            say!("  {} ({:.1} LUFS)  {}", flag.describe(), loudness, path);
+        }
+    }
+
+    if !loud_transitions.is_empty() {
+        say!(
+            "{} crossfades predicted above {} LUFS:",
+            loud_transitions.len(),
+            args.overlap_ceiling.unwrap()
+        );
+        for t in &loud_transitions {
+            if t.reduction > 0. {
+                say!(
+                    "  {:.1} LUFS  {} -> {} (lowered by {:.1} dB)",
+                    t.level,
+                    t.from,
+                    t.to,
+                    t.reduction
+                );
+            } else {
+                say!("  {:.1} LUFS  {} -> {}", t.level, t.from, t.to);
+            }
+        }
+    }
+
+    let hidden: Vec<&AnalyzeResult> = tracks
+        .iter()
+        .filter_map(Track::analyzed)
+        .filter(|r| r.hidden.is_some())
+        .collect();
+    if !hidden.is_empty() {
+        say!(
+            "{} tracks with a hidden track after a long silence{}:",
+            hidden.len(),
+            if args.split_hidden {
+                ", written as separate entries"
+            } else {
+                ", left out"
+            }
+        );
+        for r in hidden {
+            say!("  {}", r.path);
+        }
+    }
+
+    if let Some(max) = args.max_leading_silence {
+        let silent: Vec<&AnalyzeResult> = tracks
+            .iter()
+            .filter_map(Track::analyzed)
+            .filter(|r| r.leading_silence > max)
+            .collect();
+        if !silent.is_empty() {
+            say!(
+                "{} tracks with more than {}s of leading silence:",
+                silent.len(),
+                max
+            );
+            for r in silent {
+                say!("  {:.1}s  {}", r.leading_silence, r.path);
+            }
+        }
+    }
+
+    if let Some(min) = args.min_crest {
+        let compressed: Vec<&AnalyzeResult> = tracks
+            .iter()
+            .filter_map(Track::analyzed)
+            .filter(|r| r.crest < min)
+            .collect();
+        if !compressed.is_empty() {
+            say!(
+                "{} tracks with a crest factor below {} dB:",
+                compressed.len(),
+                min
+            );
+            for r in compressed {
+                say!("  {:.1} dB  {}", r.crest, r.path);
+            }
+        }
+    }
+
+    if args.extra_overlap != 0. {
+        say!(
+            "Crossfades padded by {}s with --extra-overlap",
+            args.extra_overlap
+        );
+    }
+
+    if args.histogram {
+        let loudness: Vec<f32> = tracks
+            .iter()
+            .filter_map(Track::analyzed)
+            .map(|r| r.loudness)
+            .collect();
+        if !loudness.is_empty() {
+            say!("Integrated loudness of {} tracks:", loudness.len());
+            say!("{}", histogram::render(&loudness).trim_end());
+        }
+    }
+
+    if args.album_report {
+        let albums = albums::albums(&tracks);
+        if !albums.is_empty() {
+            say!("{} albums, the most anomalous first:", albums.len());
+            say!("{}", albums::render(&albums).trim_end());
+        }
+        summary::albums(albums);
+    }
+
+    if args.sample.is_some() {
+        print_sample_statistics(&tracks);
+    }
+
+    // a streamed playlist only kept some of its tracks, it timed all of them as they went
+    let (total, missing) = match &played {
+        Some(played) => (played.runtime.total(), played.runtime.missing()),
+        None => {
+            let timeline = timeline::timeline(&tracks);
+            (timeline.total, timeline.missing)
+        }
+    };
+    say!("Total runtime with crossfades: {}", timeline::hms(total));
+    if missing > 0 {
+        let warning = format!(
+            "{} tracks without analysis are left out of the runtime and start times",
+            missing
+        );
+        eprintln!("Warning: {}", warning);
+        summary::warning(warning);
+    }
+    let duration_failed = !duration_fits(args, total);
+
+    if args.overlap_report {
+        let report = overlaps::report(&tracks);
+        if args.overlap_report_stderr {
+            eprint!("{}", overlaps::render(&report));
+        }
+        summary::overlaps(report);
+    }
+
+    if args.update {
+        let kept = tracks
+            .iter()
+            .filter(|t| matches!(t.outcome, Outcome::Skipped(_)))
+            .count();
+        say!("{} entries refreshed, {} kept", tracks.len() - kept, kept);
+    }
+
+    if args.verbose {
+        for track in &tracks {
+            if let Outcome::Skipped(reason) = &track.outcome {
+                say!("Skipped {}: {}", track.entry.path, reason);
+            }
+        }
+    }
+
+    // what --quiet prints, written even when some tracks failed
+    if args.quiet && !args.no_file {
+        for (_, path, _) in &outputs {
+            println!("{}", path.display());
+        }
+    }
+
+    if failures > 0 {
+        eprintln!(
+            "{} of {} tracks failed and were left unannotated:",
+            failures,
+            played.as_ref().map_or(tracks.len(), |p| p.tracks)
+        );
+        for track in &tracks {
+            if let Outcome::Failed(reason) = &track.outcome {
+                if input_format(args, &playlist_path) == InputFormat::Csv {
+                    eprintln!(
+                        "  {} (row {}): {}",
+                        shown_path(&track.entry.path),
+                        track.entry.line,
+                        reason
+                    );
+                } else {
+                    eprintln!("  {}: {}", shown_path(&track.entry.path), reason);
+                }
+            }
+        }
+        say!("Done!");
+        summary::exit(EXIT_PARTIAL);
+    }
+
+    if push_failed || preview_failed || duration_failed {
+        say!("Done!");
+        summary::exit(EXIT_PARTIAL);
+    }
+
+    check_warnings(args);
+    say!("Done!");
+    summary::exit(0);
+}
+
+/*
+Whether the playlist's time on air, cue-ins, cue-outs and crossfades taken off,
+is within --require-duration and --max-duration-total, saying by how much it
+isn't. The playlist is written either way, to see what's missing or too much.
+*/
+fn duration_fits(args: &Args, total: f32) -> bool {
+    if let Some(required) = args.require_duration.filter(|&r| total < r) {
+        eprintln!(
+            "The playlist plays for {}, {} short of --require-duration {}",
+            timeline::hms(total),
+            timeline::hms(required - total),
+            timeline::hms(required)
+        );
+        return false;
+    }
+    if let Some(max) = args.max_duration_total.filter(|&m| total > m) {
+        eprintln!(
+            "The playlist plays for {}, {} over --max-duration-total {}",
+            timeline::hms(total),
+            timeline::hms(total - max),
+            timeline::hms(max)
+        );
+        return false;
+    }
+    true
+}
+
+/*
+With --warnings-as-errors, ends a run that printed any warning with
+EXIT_PARTIAL, once everything is written: the playlist is as good as it would
+have been, what's wrong is in the files it points to. They're listed again,
+as they may be far up in the output.
+*/
+fn check_warnings(args: &Args) {
+    let warnings = summary::warnings();
+    if !args.warnings_as_errors || warnings.is_empty() {
+        return;
+    }
+    eprintln!("{} warnings (--warnings-as-errors):", warnings.len());
+    for warning in &warnings {
+        eprintln!("  {}", warning);
+    }
+    say!("Done!");
+    summary::exit(EXIT_PARTIAL);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::TempDir;
+
+    fn options(args: &[&str]) -> AnalyzeOptions {
+        let args = [env!("CARGO_PKG_NAME"), "playlist.m3u8"].iter().chain(args);
+        AnalyzeOptions::from(&Args::parse_from(args))
+    }
+
+    // a curve of momentary loudnesses every 100ms over `seconds`, from the time of each
+    fn curve(seconds: f32, loudness: impl Fn(f32) -> f32) -> Vec<f32> {
+        (1..=(seconds * 10.).round() as usize)
+            .map(|k| loudness(k as f32 / 10.))
+            .collect()
+    }
+
+    // a track at -14 LUFS after 2s of silence, fading out over its last 4s
+    fn track(seconds: f32) -> Vec<f32> {
+        curve(seconds, |t| {
+            if t <= 2. {
+                -70.
+            } else if t > seconds - 4. {
+                -14. - (t - (seconds - 4.)) * 8.
+            } else {
+                -14.
+            }
+        })
+    }
+
+    fn hms(seconds: f32) -> String {
+        format!(
+            "{:02}:{:02}:{:05.2}",
+            (seconds / 3600.) as u32,
+            (seconds % 3600. / 60.) as u32,
+            seconds % 60.
+        )
+    }
+
+    // what ffmpeg prints measuring `curve`, with the header duration or N/A
+    fn ffmpeg_output(header: Option<f32>, curve: &[f32]) -> String {
+        ffmpeg_output_at(header, curve, -14.)
+    }
+
+    // the same with the integrated loudness ebur128 sums up
+    fn ffmpeg_output_at(header: Option<f32>, curve: &[f32], integrated: f32) -> String {
+        let mut output = format!(
+            "Input #0, flac, from 'a.flac':\n  Duration: {}, start: 0.000000, bitrate: 900 kb/s\n  Stream #0:0: Audio: flac, 44100 Hz, stereo, s16\n",
+            header.map_or(String::from("N/A"), hms)
+        );
+        for (k, m) in curve.iter().enumerate() {
+            output += &format!(
+                "[Parsed_ebur128_0 @ 0x5555] t: {:<10} TARGET:-23 LUFS    M:{:6.1} S:{:6.1}     I:{:6.1} LUFS       LRA:   5.0 LU\n",
+                (k + 1) as f32 / 10.,
+                m,
+                m,
+                integrated
+            );
+        }
+        output += &format!(
+            "size=N/A time={} bitrate=N/A speed= 500x\n",
+            hms(curve.len() as f32 / 10.)
+        );
+        output += &format!("[Parsed_ebur128_0 @ 0x5555] Summary:\n\n  Integrated loudness:\n    I:         {:.1} LUFS\n", integrated);
+        output += "    Threshold: -24.0 LUFS\n\n  Loudness range:\n    LRA:         5.0 LU\n    Threshold: -34.0 LUFS\n    LRA low:   -19.0 LUFS\n    LRA high:  -12.0 LUFS\n\n  Sample peak:\n    Peak:        -1.0 dBFS\n";
+        output
+    }
+
+    fn analyzed(args: &[&str], output: &str) -> Result<AnalyzeResult, String> {
+        analyze_measured("a.flac", None, &options(args), output, None)
+    }
+
+    #[test]
+    fn range_parses() {
+        let range = parse_range(" 30 : 90.5").unwrap();
+        assert_eq!((range.start, range.end), (30., 90.5));
+        assert!(parse_range("90:30").is_err());
+        assert!(parse_range("30:30").is_err());
+        assert!(parse_range("-1:30").is_err());
+        assert!(parse_range("30").is_err());
+        assert!(parse_range("a:30").is_err());
+    }
+
+    #[test]
+    fn range_times_count_from_the_file_or_the_window() {
+        // a 60s window out of a 200s file, measured from its start
+        let output = ffmpeg_output(Some(200.), &track(60.));
+        let window = analyzed(&["--range", "30:90", "--range-times", "window"], &output).unwrap();
+        let file = analyzed(&["--range", "30:90"], &output).unwrap();
+
+        assert_eq!(window.duration, 60.);
+        assert_eq!(file.duration, 200.);
+        assert!(window.cue_point > 1. && window.cue_point <= 2.);
+        assert!((file.cue_point - (window.cue_point + 30.)).abs() < 1e-4);
+        assert!((file.leading_silence - (window.leading_silence + 30.)).abs() < 1e-4);
+        // the crossfade counts from the end, and the file goes on 110s after the window
+        assert!(window.start_next > 0. && window.start_next < 4.);
+        assert!((file.start_next - (window.start_next + 110.)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn range_past_the_end_of_the_file() {
+        let output = ffmpeg_output(Some(200.), &track(60.));
+        let e = analyzed(&["--range", "170:230"], &output).err().unwrap();
+        assert!(e.contains("only 200.000s long"), "{}", e);
+
+        // without a header duration the file ends where the window did
+        let output = ffmpeg_output(None, &track(60.));
+        let file = analyzed(&["--range", "30:90"], &output).unwrap();
+        assert_eq!(file.duration, 90.);
+    }
+
+    #[test]
+    fn quiet_throughout_gets_a_short_crossfade() {
+        // digital silence, which ebur128 sums up as -70 LUFS, its gate
+        let output = ffmpeg_output_at(Some(30.), &curve(30., |_| -120.7), -70.);
+        let result = analyzed(&[], &output).unwrap();
+        assert!(result.quiet);
+        assert!((result.start_next - QUIET_TRACK_CROSS).abs() < 1e-4);
+        assert_eq!(result.warnings(), ["never rises above the crossfade level"]);
+
+        // loud and quiet, it still finds its own crossfade
+        let result = analyzed(&[], &ffmpeg_output(Some(30.), &track(30.))).unwrap();
+        assert!(!result.quiet);
+        assert!(result.warnings().is_empty());
+    }
+
+    // a swell of 2 LU/s from -60 LUFS, and the beat coming in at 12s
+    fn swell() -> Vec<f32> {
+        curve(40., |t| if t <= 12. { -60. + t * 2. } else { -14. })
+    }
+
+    #[test]
+    fn onset_finds_the_rise() {
+        let measure: Vec<Measurement> = swell()
+            .into_iter()
+            .enumerate()
+            .map(|(k, momentary)| Measurement {
+                time: (k + 1) as f32 / 10.,
+                momentary,
+            })
+            .collect();
+        // where the rise begins, half a second before the first block at full level
+        let onset = first_onset(&measure, -54., 6.).unwrap();
+        assert!((onset - 11.6).abs() < 1e-4, "{}", onset);
+        // the swell alone never rises fast enough
+        assert_eq!(first_onset(&measure[..115], -54., 6.), None);
+        // and nothing below the cue level counts
+        assert_eq!(first_onset(&measure, -10., 6.), None);
+    }
+
+    #[test]
+    fn onset_and_threshold_cue_ins() {
+        let output = ffmpeg_output(Some(40.), &swell());
+        let threshold = analyzed(&[], &output).unwrap();
+        let onset = analyzed(&["--cue-mode", "onset"], &output).unwrap();
+        // the threshold cues in early in the swell, 400ms before its first block above -54
+        assert!(
+            (threshold.cue_point - 2.7).abs() < 1e-4,
+            "{}",
+            threshold.cue_point
+        );
+        assert!((onset.cue_point - 11.2).abs() < 1e-4, "{}", onset.cue_point);
+
+        // without a sharp rise onset falls back to the threshold
+        let output = ffmpeg_output(Some(30.), &track(30.));
+        let onset = analyzed(
+            &["--cue-mode", "onset", "--onset-sensitivity", "60"],
+            &output,
+        );
+        let threshold = analyzed(&[], &output);
+        assert_eq!(onset.unwrap().cue_point, threshold.unwrap().cue_point);
+    }
+
+    #[test]
+    fn tail_block_correction() {
+        // a fade of 8 LU/s over the last 4s, and a slow one of 1 LU/s over the last 20s
+        let slow = curve(30., |t| if t > 10. { -14. - (t - 10.) } else { -14. });
+        for (curve, last_block) in [(track(30.), 26.9), (slow, 17.9)] {
+            let output = ffmpeg_output(Some(30.), &curve);
+            let plain = analyzed(&[], &output).unwrap();
+            let corrected = analyzed(&["--tail-block-correction"], &output).unwrap();
+            assert!((plain.start_next - (30. - last_block)).abs() < 1e-3);
+            assert!((corrected.start_next - (30. - last_block + 0.4)).abs() < 1e-3);
+        }
+
+        // a cold ending is placed from the end, not from a block
+        let cold = ffmpeg_output(Some(30.), &curve(30., |_| -14.));
+        let args = ["--detect-cold-endings", "--tail-block-correction"];
+        let result = analyzed(&args, &cold).unwrap();
+        assert!(result.cold_ending);
+        assert!((result.start_next - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn stinger_keeps_min_playable() {
+        // three seconds, a hit from 0.6s that falls 16 LU/s after 0.8s
+        let stinger = curve(3., |t| match t {
+            t if t <= 0.6 => -70.,
+            t if t > 0.8 => -14. - (t - 0.8) * 16.,
+            _ => -14.,
+        });
+        let output = ffmpeg_output(Some(3.), &stinger);
+        for min_playable in ["1", "2"] {
+            let result = analyzed(&["--min-playable", min_playable], &output).unwrap();
+            let playable = result.duration - result.cue_point - result.start_next;
+            assert!(result.overlap_clamped);
+            assert!((playable - min_playable.parse::<f32>().unwrap()).abs() < 1e-4);
+        }
+        let result = analyzed(&["--min-playable", "0"], &output).unwrap();
+        assert!(!result.overlap_clamped);
+    }
+
+    #[test]
+    fn never_crossing_a_threshold_keeps_min_playable() {
+        // silent throughout, no cue-in and the quiet track crossfade, longer than what's left
+        let output = ffmpeg_output_at(Some(1.5), &curve(1.5, |_| -120.7), -70.);
+        let result = analyzed(&[], &output).unwrap();
+        assert!(result.quiet && result.overlap_clamped);
+        assert_eq!(result.cue_point, 0.);
+        assert!((result.start_next - 0.5).abs() < 1e-4);
+
+        // shorter than --min-playable on its own, no crossfade at all
+        let output = ffmpeg_output_at(Some(0.8), &curve(0.8, |_| -120.7), -70.);
+        let result = analyzed(&[], &output).unwrap();
+        assert_eq!(result.start_next, 0.);
+    }
+
+    fn blocks(values: &[f32]) -> Vec<Measurement> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(k, &momentary)| Measurement {
+                time: (k + 1) as f32 / 10.,
+                momentary,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn smooth_averages_around_each_block() {
+        let measure = blocks(&[0., 3., 0., 3., 0.]);
+        assert_eq!(smooth(&measure, 1), measure);
+        let smoothed: Vec<f32> = smooth(&measure, 3).iter().map(|m| m.momentary).collect();
+        // the ends average what's there of their window
+        assert_eq!(smoothed, [1.5, 1., 2., 1., 1.5]);
+        assert_eq!(smooth(&measure, 3)[2].time, measure[2].time);
+    }
+
+    #[test]
+    fn smooth_steadies_a_noisy_tail() {
+        // falling 1 LU/s over the last 10s, through -22 at 28s, with 4 LU either way of noise
+        let noisy = curve(30., |t| {
+            let noise = if (t * 10.).round() as i32 % 2 == 0 {
+                4.
+            } else {
+                -4.
+            };
+            if t > 20. {
+                -14. - (t - 20.) + noise
+            } else {
+                -14. + noise
+            }
+        });
+        let output = ffmpeg_output(Some(30.), &noisy);
+        // the noise keeps poking above the level until the very end
+        let raw = analyzed(&[], &output).unwrap();
+        assert!(raw.start_next < 0.5, "{}", raw.start_next);
+        let smoothed = analyzed(&["--smooth", "5"], &output).unwrap();
+        assert!(
+            (smoothed.start_next - 2.).abs() < 1.,
+            "{}",
+            smoothed.start_next
+        );
+    }
+
+    #[test]
+    fn duration_header() {
+        let duration = |line: &str| {
+            header_duration(&["Input #0, flac, from 'a.flac':", line]).map(output::round3)
+        };
+        assert_eq!(
+            duration("  Duration: 00:03:25.04, start: 0.000000, bitrate: 320 kb/s"),
+            Some(205.04)
+        );
+        assert_eq!(duration("  Duration:01:00:00.50,start: 0"), Some(3600.5));
+        assert_eq!(
+            duration("\tDuration:   00:00:07.10   , bitrate: N/A"),
+            Some(7.1)
+        );
+        assert_eq!(duration("  Duration: 00:00:07.10"), Some(7.1));
+        assert_eq!(
+            duration("  Duration: N/A, start: 0.000000, bitrate: N/A"),
+            None
+        );
+        assert_eq!(duration("  Duration: 3:25, start: 0"), None);
+        assert_eq!(header_duration(&["no header at all"]), None);
+    }
+
+    #[test]
+    fn hms_timestamps() {
+        assert_eq!(parse_hms("00:03:25.04").map(output::round3), Some(205.04));
+        assert_eq!(parse_hms(" 10:00:00.00 ").map(output::round3), Some(36000.));
+        assert_eq!(parse_hms("N/A"), None);
+        assert_eq!(parse_hms("03:25.04"), None);
+        assert_eq!(parse_hms(""), None);
+    }
+
+    #[test]
+    fn duration_not_available() {
+        // time=N/A and no header, the last measurement is where the audio ended
+        let output = ffmpeg_output(None, &track(30.)).replace("time=00:00:30.00", "time=N/A");
+        assert_eq!(analyzed(&[], &output).unwrap().duration, 30.);
+        // the status line's time wins over the header when there is one
+        let output = ffmpeg_output(Some(31.), &track(30.));
+        assert_eq!(analyzed(&[], &output).unwrap().duration, 30.);
+    }
+
+    #[test]
+    fn cue_out_where_the_audio_stops() {
+        // the 30s track, then 5s of digital silence
+        let mut silent_end = track(30.);
+        silent_end.extend(curve(5., |_| -120.7));
+        let output = ffmpeg_output(Some(35.), &silent_end);
+
+        let plain = analyzed(&[], &output).unwrap();
+        assert_eq!(plain.cue_out, None);
+        let cut = analyzed(&["--emit-cue-out"], &output).unwrap();
+        assert!((cut.cue_out.unwrap() - 30.).abs() < 1e-4);
+        // the crossfade stays at the same moment, counted from the cue-out
+        assert!((plain.start_next - cut.start_next - 5.).abs() < 1e-4);
+
+        // not enough silence to cut
+        let args = ["--emit-cue-out", "--cue-out-min-silence", "6"];
+        assert_eq!(analyzed(&args, &output).unwrap().cue_out, None);
+        let output = ffmpeg_output(Some(30.), &track(30.));
+        assert_eq!(
+            analyzed(&["--emit-cue-out"], &output).unwrap().cue_out,
+            None
+        );
+    }
+
+    fn r128(duration: Option<f32>) -> TaggedLoudness {
+        TaggedLoudness {
+            loudness: -14.,
+            peak: Some(-1.),
+            source: LoudnessSource::R128,
+            duration,
+        }
+    }
+
+    #[test]
+    fn update_keeps_line_endings() {
+        let dir = TempDir::new("endings");
+        let processed = dir.join("processed.m3u8");
+        std::fs::write(&processed, "#EXTM3U\r\na.flac\r\n").unwrap();
+
+        let ending = |args: &[&str]| {
+            let mut argv = vec![env!("CARGO_PKG_NAME"), processed.to_str().unwrap()];
+            argv.extend(args);
+            line_ending(&Args::parse_from(argv), &processed, &processed)
+        };
+        assert_eq!(ending(&["--update"]), output::LineEnding::Crlf);
+        assert_eq!(
+            ending(&["--update", "--line-ending", "lf"]),
+            output::LineEnding::Lf
+        );
+        assert_eq!(ending(&[]), output::LineEnding::Lf);
+    }
+
+    #[test]
+    fn annotated_uris_read_back_as_paths() {
+        let dir = TempDir::new("uri");
+        let track = dir.join("Live: Café del Mar.flac");
+        std::fs::write(&track, "").unwrap();
+        let track = track.to_str().unwrap();
+
+        // written as a file:// URI for the colon, with the space and é encoded
+        let uri = output::annotate_uri(track);
+        assert!(uri.starts_with("file://") && uri.contains("%20") && uri.contains("%C3%A9"));
+        let line = format!("annotate:liq_cue_in=\"1.000\":{}", uri);
+        let processed = dir.join("processed.m3u8");
+        std::fs::write(&processed, format!("{}\n", line)).unwrap();
+        let annotated = read_annotated(&processed).unwrap();
+        assert!(read_annotated(&dir.join("missing.m3u8")).is_err());
+        assert_eq!(annotated.len(), 1);
+        assert_eq!(annotated[0].0, track);
+
+        let entry = |directives: Vec<String>| playlist::Entry {
+            path: line.clone(),
+            directives,
+            line: 1,
+            region: None,
+        };
+        let fingerprint = format!(
+            "{}{}",
+            playlist::FINGERPRINT_PREFIX,
+            playlist::fingerprint(track).unwrap()
+        );
+        let mut entries = [entry(vec![fingerprint]), entry(Vec::new())];
+        let skip = update_skips(&mut entries);
+        assert_eq!(skip, [Some(String::from("unchanged")), None]);
+        // the one analysed again goes by the file
+        assert_eq!(entries[1].path, track);
+    }
+
+    #[test]
+    fn drive_letter_paths_read_back() {
+        for path in ["C:\\Music\\a.flac", "D:\\Live: Café\\b c.flac"] {
+            let uri = output::annotate_uri(path);
+            assert!(uri.starts_with("file:///"), "{}", uri);
+            let line = format!(
+                "annotate:liq_cue_in=\"1.000\",liq_amplify=\"-2.000dB\":{}",
+                uri
+            );
+            let annotation = playlist::parse_annotate(&line).unwrap();
+            assert_eq!(annotation.path, uri);
+            assert_eq!(annotation.get("liq_cue_in"), Some("1.000"));
+            assert_eq!(playlist::played_path(&line), path);
+        }
+    }
+
+    #[test]
+    fn password_from_a_file() {
+        let dir = TempDir::new("password");
+        let file = dir.join("password");
+        std::fs::write(&file, "s3cret pass\r\nignored\n").unwrap();
+        assert_eq!(read_password(&file).unwrap(), "s3cret pass");
+        std::fs::write(&file, "\n").unwrap();
+        assert!(read_password(&file).is_err());
+        assert!(read_password(&dir.join("missing")).is_err());
+
+        let argv = [
+            env!("CARGO_PKG_NAME"),
+            "playlist.m3u8",
+            "--push-to",
+            "localhost:1234",
+            "--push-password",
+            "x",
+            "--push-password-file",
+            file.to_str().unwrap(),
+        ];
+        assert!(Args::try_parse_from(argv).is_err());
+    }
+
+    #[test]
+    fn appending_skips_what_the_output_has() {
+        let dir = TempDir::new("existing");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        let file = |name: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, "").unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let (a, b, c) = (file("a.flac"), file("b.flac"), file("c.flac"));
+
+        let out = dir.join("out.m3u8");
+        assert!(existing_paths(&out).unwrap().is_empty());
+        std::fs::write(
+            &out,
+            format!("#EXTM3U\nannotate:liq_cue_in=\"1.000\":{}\n{}\n", a, b),
+        )
+        .unwrap();
+        let existing = existing_paths(&out).unwrap();
+
+        // the same files under other paths are in it too
+        let other_b = dir.join("sub").join("..").join("b.flac");
+        let mut entries: Vec<playlist::Entry> = [a.as_str(), other_b.to_str().unwrap(), &c]
+            .iter()
+            .map(|path| playlist::Entry {
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(skip_existing(&mut entries, &existing), 2);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, c);
+    }
+
+    #[test]
+    fn single_stdin_output() {
+        let args = |extra: &[&'static str]| {
+            let mut argv = vec![env!("CARGO_PKG_NAME"), "--single-stdin"];
+            argv.extend(extra);
+            Args::parse_from(argv)
+        };
+        let stderr = ffmpeg_output(Some(60.), &track(60.));
+        let json = single_stdin(&args(&[]), |opts| {
+            // ffmpeg reads the pipe, and nothing opens the file again
+            assert!(!opts.trust_tags);
+            Ok(stderr.clone())
+        })
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let track = &json[0];
+        assert_eq!(track["path"], STDIN);
+        assert!(
+            (track["cue_in"].as_f64().unwrap() - 1.7).abs() < 0.01,
+            "{}",
+            track
+        );
+        assert_eq!(track["duration"], 60.);
+
+        let m3u = single_stdin(&args(&["--format", "m3u"]), |_| Ok(stderr)).unwrap();
+        assert!(m3u.lines().any(|l| l.ends_with(":pipe:0")), "{}", m3u);
+        let e = single_stdin(&args(&[]), |_| Ok(String::new())).unwrap_err();
+        assert!(e.contains("couldn't measure"), "{}", e);
+    }
+
+    // counts the tracks being analysed at once, for large_playlist_streams_flat
+    static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+    static MOST_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+    // stands in for ffmpeg: some tracks take a while, and every 10000th fails
+    fn counted_analysis(
+        path: &str,
+        _: Option<Region>,
+        _: &AnalyzeOptions,
+        _: Option<&mut String>,
+    ) -> Result<AnalyzeResult, String> {
+        let now = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+        MOST_IN_FLIGHT.fetch_max(now, Ordering::SeqCst);
+        let n: usize = path.trim_end_matches(".flac").parse().unwrap();
+        if n.is_multiple_of(1009) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        if n.is_multiple_of(10_000) {
+            return Err(String::from("couldn't measure"));
+        }
+        Ok(AnalyzeResult {
+            cue_point: 1.,
+            start_next: 2.,
+            duration: 60.,
+            loudness: -23.,
+            target: -23.,
+            path: path.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn large_playlist_streams_flat() {
+        const ENTRIES: usize = 100_000;
+        let dir = TempDir::new("flat");
+        let playlist = dir.join("playlist.m3u8");
+        let mut m3u = String::from("#EXTM3U\n");
+        for i in 0..ENTRIES {
+            m3u.push_str(&format!("#EXTINF:60,Track {}\n{}.flac\n", i, i));
+        }
+        std::fs::write(&playlist, m3u).unwrap();
+        let out = dir.join("out.m3u8");
+
+        let args = testing::args(&["--stream", "--no-header"]);
+        assert!(streams_flat(&args, InputFormat::M3u));
+        assert!(!streams_flat(
+            &testing::args(&["--stream", "--report", "report.json"]),
+            InputFormat::M3u
+        ));
+        let opts = testing::render_options(&["--no-header"]);
+        let stream =
+            Mutex::new(stream::Stream::create(&out, opts, output::LineEnding::Lf).unwrap());
+        let mut analysis = Analysis::new(&args, InputFormat::M3u, Format::M3u);
+        analysis.analyze = counted_analysis;
+        let (entries, results, failures, played) = stream_playlist(analysis, &playlist, &stream);
+        stream.into_inner().unwrap().close().unwrap();
+
+        // all that's left of the playlist is the failed tracks the end lists
+        assert_eq!(played.tracks, ENTRIES);
+        assert_eq!(failures, ENTRIES / 10_000);
+        assert_eq!((entries.len(), results.len()), (failures, failures));
+        assert!(results.iter().all(|o| matches!(o, Outcome::Failed(_))));
+        // and what's being analysed is never more than the queue takes on
+        let bound = rayon::current_num_threads() * queue::DEPTH;
+        assert!(MOST_IN_FLIGHT.load(Ordering::SeqCst) <= bound);
+
+        let written = std::fs::read_to_string(&out).unwrap();
+        let paths: Vec<&str> = written
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| l.rsplit(':').next().unwrap())
+            .collect();
+        assert_eq!(paths.len(), ENTRIES);
+        assert!(paths
+            .iter()
+            .enumerate()
+            .all(|(i, p)| *p == format!("{}.flac", i)));
+    }
+
+    #[test]
+    fn missing_output_directory() {
+        let dir = TempDir::new("mkdir");
+        let output = dir.join("new").join("out.m3u8");
+
+        let e = output_dir(&output, false).unwrap_err();
+        assert!(e.contains("does not exist (--mkdir creates it)"), "{}", e);
+        assert!(!dir.join("new").exists());
+
+        output_dir(&output, true).unwrap();
+        assert!(output.parent().unwrap().is_dir());
+        // there now, and a bare file name goes next to us
+        output_dir(&output, false).unwrap();
+        output_dir(Path::new("out.m3u8"), false).unwrap();
+    }
+
+    #[test]
+    fn trusted_tags_skip_the_measurement() {
+        let opts = options(&[
+            "--trust-tags",
+            "--no-cue",
+            "--no-cross",
+            "--cross-default",
+            "3",
+        ]);
+        let result = analyze_from("a.flac", None, &opts, Some(r128(Some(200.))), || {
+            panic!("measured although the tags were enough")
+        })
+        .unwrap();
+        assert_eq!(result.loudness, -14.);
+        assert_eq!(result.loudness_source, LoudnessSource::R128);
+        assert_eq!(result.duration, 200.);
+        assert_eq!(result.start_next, 3.);
+        assert_eq!(result.cue_point, 0.);
+        assert!(result.cue_disabled);
+        assert!(!result.cross_disabled);
+    }
+
+    #[test]
+    fn tags_dont_suffice_for_boundaries() {
+        let measured = |args: &[&str], region, tagged| {
+            let opts = options(args);
+            let mut ran = false;
+            let _ = analyze_from("a.flac", region, &opts, tagged, || {
+                ran = true;
+                Err(String::from("not measured in tests"))
+            });
+            ran
+        };
+        let region = Region {
+            start: 0.,
+            end: Some(60.),
+            raw: false,
+        };
+        assert!(measured(&["--trust-tags"], None, Some(r128(Some(200.)))));
+        assert!(measured(
+            &["--trust-tags", "--no-cue"],
+            None,
+            Some(r128(Some(200.)))
+        ));
+        assert!(measured(
+            &["--trust-tags", "--no-cue", "--no-cross", "--qc"],
+            None,
+            Some(r128(Some(200.)))
+        ));
+        assert!(measured(
+            &["--trust-tags", "--no-cue", "--no-cross"],
+            Some(region),
+            Some(r128(Some(200.)))
+        ));
+        // no duration in the container, or no tags at all
+        assert!(measured(
+            &["--trust-tags", "--no-cue", "--no-cross"],
+            None,
+            Some(r128(None))
+        ));
+        assert!(measured(
+            &["--trust-tags", "--no-cue", "--no-cross"],
+            None,
+            None
+        ));
+    }
 }
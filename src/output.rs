@@ -0,0 +1,1166 @@
+use crate::albums;
+use crate::chapters::ChapterStyle;
+use crate::playlist::{self, Outcome, Track};
+use crate::random::Rng;
+use crate::runinfo::{self, RunInfo};
+use crate::tags::LoudnessSource;
+use crate::timeline;
+use crate::{AnalyzeResult, FadeType, Flag, PeakMode, Profile};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Formats the processed playlist can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Extended m3u with Liquidsoap annotate: prefixes
+    M3u,
+    /// JSON array with one object per track
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// PLS playlist (Version 2)
+    Pls,
+    /// XSPF playlist, analysis values as <meta> elements
+    Xspf,
+    /// Chapters at the quiet points of each track (WebVTT or ffmetadata)
+    Chapters,
+}
+
+/// How m3u entries carry their values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AnnotationStyle {
+    /// Liquidsoap annotate: prefixes on the path line
+    Annotate,
+    /// A comment above the unchanged path line, for players that don't know annotate:
+    Comment,
+}
+
+/*
+The crossfade convention a playout system reads, for --preset. The gain and
+the cue-in are written the same way for all of them, under --gain-key and
+--gain-format.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Liquidsoap's own annotations, the defaults
+    Liquidsoap,
+    /// AzuraCast, which takes the crossfade as where in the track the next one starts
+    Azuracast,
+}
+
+impl Preset {
+    // the annotation the crossfade is written as
+    fn cross_key(self) -> &'static str {
+        match self {
+            Preset::Liquidsoap => "liq_cross_duration",
+            Preset::Azuracast => "liq_cross_start_next",
+        }
+    }
+
+    /*
+    The crossfade's value: Liquidsoap takes how long it is, AzuraCast where in
+    the file the next track starts, that long before the track ends.
+    */
+    fn cross_value(self, cross: f32, end: f32) -> f32 {
+        match self {
+            Preset::Liquidsoap => cross,
+            Preset::Azuracast => f32::max(end - cross, 0.),
+        }
+    }
+}
+
+/// What an analysed entry's #EXTINF gets as its duration
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExtinfDuration {
+    /// The one it had
+    Keep,
+    /// The measured one
+    Measured,
+    /// Whichever is longer
+    Max,
+}
+
+/*
+An #EXTINF line with its duration, the whole seconds before the title, as
+`mode` says. Lines that don't have one to replace are left as they are; -1
+(unknown) counts as shorter than anything measured.
+*/
+fn extinf(directive: &str, mode: ExtinfDuration, measured: f32) -> String {
+    let Some(rest) = directive.strip_prefix("#EXTINF:") else {
+        return directive.to_string();
+    };
+    let end = rest.find([',', ' ']).unwrap_or(rest.len());
+    let Ok(given) = rest[..end].trim().parse::<f32>() else {
+        return directive.to_string();
+    };
+    let duration = match mode {
+        ExtinfDuration::Keep => return directive.to_string(),
+        ExtinfDuration::Measured => measured,
+        ExtinfDuration::Max => f32::max(given, measured),
+    };
+    format!("#EXTINF:{}{}", duration.round() as i64, &rest[end..])
+}
+
+/// How the gain is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GainFormat {
+    /// In dB, like -2.100dB
+    Db,
+    /// As the amplitude multiplier, like 0.785
+    Linear,
+}
+
+impl GainFormat {
+    /*
+    A gain of `db` the way it's written. Liquidsoap reads a value without the
+    dB as a multiplier, so both work with liq_amplify.
+    */
+    pub fn value(self, db: f32) -> String {
+        match self {
+            GainFormat::Db => format!("{:.3}dB", db),
+            GainFormat::Linear => format!("{:.6}", 10f32.powf(db / 20.)),
+        }
+    }
+}
+
+/// How the lines of the written playlist end
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LineEnding {
+    Lf,
+    /// \r\n, for Windows playout systems
+    Crlf,
+    /// The same as the input playlist
+    Auto,
+}
+
+impl LineEnding {
+    /// The endings `text` has, going by its first line, None for a single line
+    pub fn of(text: &str) -> Option<LineEnding> {
+        let end = text.find('\n')?;
+        Some(if text[..end].ends_with('\r') {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        })
+    }
+
+    /// `text`, rendered with \n, with these line endings. Auto is resolved before, see LineEnding::of.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+            LineEnding::Lf | LineEnding::Auto => Cow::Borrowed(text),
+        }
+    }
+}
+
+/// Which way times are rounded to the millisecond they're written with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Rounding {
+    Nearest,
+    /// To the millisecond before
+    Down,
+    /// To the millisecond after
+    Up,
+}
+
+impl Rounding {
+    /*
+    The f32 is taken at its shortest decimal form first: 1.234 is stored as
+    1.2339999, which rounded down would come out as 1.233.
+    */
+    fn apply(self, x: f32) -> f32 {
+        if !x.is_finite() {
+            return x;
+        }
+        let ms = x.to_string().parse::<f64>().unwrap_or(x as f64) * 1000.;
+        // what's left is the multiplication's own error
+        let ms = (ms * 1e6).round() / 1e6;
+        let ms = match self {
+            Rounding::Nearest => return round3(x),
+            Rounding::Down => ms.floor(),
+            Rounding::Up => ms.ceil(),
+        };
+        (ms / 1000.) as f32
+    }
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::M3u => "m3u8",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Pls => "pls",
+            Format::Xspf => "xspf",
+            Format::Chapters => "vtt",
+        }
+    }
+}
+
+/*
+An explicit --format always wins. Otherwise the extension of the output path
+decides, and anything we don't recognise (or no --output at all) keeps the
+annotated m3u default, which is what the tool has always written.
+*/
+pub fn select_format(explicit: Option<Format>, output: Option<&Path>, verbose: bool) -> Format {
+    if let Some(format) = explicit {
+        return format;
+    }
+
+    let output = match output {
+        Some(p) => p,
+        None => return Format::M3u,
+    };
+
+    let extension = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "json" => Format::Json,
+        "csv" => Format::Csv,
+        "pls" => Format::Pls,
+        "xspf" => Format::Xspf,
+        "vtt" => Format::Chapters,
+        "m3u" | "m3u8" => Format::M3u,
+        other => {
+            if verbose {
+                if other.is_empty() {
+                    say!("Output path has no extension, writing annotated m3u");
+                } else {
+                    say!("Unrecognised output extension '.{other}', writing annotated m3u");
+                }
+            }
+            Format::M3u
+        }
+    }
+}
+
+/// Writer settings that don't change what was measured
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// Write the leading #EXTM3U line. Only applies to m3u, the other formats
+    /// are always complete documents.
+    pub header: bool,
+    /// Write a fingerprint comment above every analysed m3u entry
+    pub fingerprints: bool,
+    /// Write a comment with the on-air start time above every analysed m3u entry
+    pub timeline_comments: bool,
+    /// Random shift of up to this many seconds either way for m3u crossfades
+    pub cross_jitter: f32,
+    pub seed: u64,
+    pub chapter_style: ChapterStyle,
+    /// Leave liq_cue_in out for tracks analysed without cue detection
+    pub omit_cue: bool,
+    pub annotation_style: AnnotationStyle,
+    /// Name of the comment with --annotation-style comment, without # and :
+    pub comment_key: String,
+    /// How every time is rounded, None for each field's own default
+    pub round: Option<Rounding>,
+    pub extinf_duration: ExtinfDuration,
+    pub gain_format: GainFormat,
+    /// Annotation the gain is written as, liq_amplify unless --gain-key
+    pub gain_key: String,
+    /// The crossfade's key and value, with --preset
+    pub preset: Preset,
+    /// Write liq_longtail for the tracks it applies to
+    pub emit_longtail: bool,
+    /// Comments on the run below the #EXTM3U line, None with --no-header
+    pub run_info: Option<RunInfo>,
+}
+
+/*
+Unless --round says otherwise, cue-ins are rounded down and cue-outs up, so
+neither cuts into the audio they were placed before or after, even by a
+fraction of a millisecond that can hold a transient. Every other time goes to
+the nearest millisecond.
+*/
+impl RenderOptions {
+    fn cue_in(&self, x: f32) -> f32 {
+        self.round.unwrap_or(Rounding::Down).apply(x)
+    }
+
+    fn cue_out(&self, x: f32) -> f32 {
+        self.round.unwrap_or(Rounding::Up).apply(x)
+    }
+
+    fn time(&self, x: f32) -> f32 {
+        self.round.unwrap_or(Rounding::Nearest).apply(x)
+    }
+}
+
+/// Renders the results in the given format
+pub fn render(format: Format, tracks: &[Track], opts: &RenderOptions) -> String {
+    match format {
+        Format::M3u => render_m3u(tracks, opts),
+        Format::Json => render_json(tracks, opts),
+        Format::Csv => render_csv(tracks, opts),
+        Format::Pls => render_pls(tracks),
+        Format::Xspf => render_xspf(tracks, opts),
+        Format::Chapters => render_chapters(tracks, opts.chapter_style),
+    }
+}
+
+fn render_m3u(tracks: &[Track], opts: &RenderOptions) -> String {
+    let mut m3u = M3uWriter::new(opts.clone());
+    let mut s = m3u.header();
+    for track in tracks {
+        s.push_str(&m3u.entry(track));
+    }
+    s
+}
+
+/// Renders m3u one entry at a time, in playlist order
+pub struct M3uWriter {
+    opts: RenderOptions,
+    clock: timeline::Clock,
+    rng: Rng,
+}
+
+impl M3uWriter {
+    pub fn new(opts: RenderOptions) -> M3uWriter {
+        M3uWriter {
+            clock: timeline::Clock::default(),
+            rng: Rng::new(opts.seed),
+            opts,
+        }
+    }
+
+    pub fn header(&self) -> String {
+        let mut s = String::new();
+        if self.opts.header {
+            s.push_str("#EXTM3U\n");
+        }
+        if let Some(info) = &self.opts.run_info {
+            s.push_str(&info.render());
+        }
+        s
+    }
+
+    /// The lines of the next entry, directives included
+    pub fn entry(&mut self, track: &Track) -> String {
+        let mut s = String::new();
+        let opts = &self.opts;
+        let start = self.clock.advance(track);
+
+        let result = match &track.outcome {
+            Outcome::Analyzed(r) => r,
+            // skipped and failed tracks still get played, just as they were
+            Outcome::Skipped(_) | Outcome::Failed(_) => {
+                // the run comments of the playlist this one was made from are stale
+                let directives = track.entry.directives.iter();
+                for directive in directives.filter(|d| !d.starts_with(runinfo::PREFIX)) {
+                    s.push_str(directive);
+                    s.push('\n');
+                }
+                s.push_str(&track.entry.path);
+                s.push('\n');
+                return s;
+            }
+        };
+        // #EXTINF and other comments go right above their track, like in the input.
+        // Our own comments from an earlier run are stale now that it was analysed again.
+        let comment_prefix = format!("#{}:", opts.comment_key);
+        // a CUE sheet track's duration counts from its start in the file
+        let length = result.duration - track.entry.region.map_or(0., |r| r.start);
+        for directive in &track.entry.directives {
+            if !playlist::is_generated(directive) && !directive.starts_with(&comment_prefix) {
+                s.push_str(&extinf(directive, opts.extinf_duration, length));
+                s.push('\n');
+            }
+        }
+        if opts.timeline_comments {
+            if let Some(start) = start {
+                s.push_str(playlist::START_PREFIX);
+                s.push_str(&timeline::hms(start));
+                s.push('\n');
+            }
+        }
+        if opts.fingerprints {
+            if let Some(fingerprint) = playlist::fingerprint(&result.path) {
+                s.push_str(playlist::FINGERPRINT_PREFIX);
+                s.push_str(&fingerprint);
+                s.push('\n');
+            }
+        }
+
+        // --no-cue tracks get a cue-in of 0, or none at all with --omit-cue
+        let cue_in = if result.cue_disabled && opts.omit_cue {
+            None
+        } else {
+            Some(result.cue_point)
+        };
+
+        // --no-cross leaves the crossfade to the playout
+        let cross = if result.cross_disabled {
+            None
+        } else {
+            Some(jitter(
+                result.start_next,
+                result,
+                opts.cross_jitter,
+                &mut self.rng,
+            ))
+        };
+
+        // a split hidden track plays as a second entry right after the main one
+        match &result.hidden {
+            Some(h) if h.split => {
+                // the long tail is the hidden track's, at the end of the file
+                let main = AnalyzeResult {
+                    long_tail: false,
+                    ..result.clone()
+                };
+                s.push_str(&annotate(
+                    opts,
+                    &main,
+                    cue_in,
+                    Some(h.main_cross),
+                    Some(h.main_end),
+                    None,
+                    None,
+                ));
+                s.push_str(&annotate(
+                    opts,
+                    result,
+                    Some(h.start),
+                    cross,
+                    result.cue_out,
+                    result.fade_out,
+                    result.fade_type,
+                ));
+            }
+            _ => s.push_str(&annotate(
+                opts,
+                result,
+                cue_in,
+                cross,
+                result.cue_out,
+                result.fade_out,
+                result.fade_type,
+            )),
+        }
+
+        s
+    }
+}
+
+/*
+Rounds to the same 3 decimals the annotate line uses. Going through the same
+formatting keeps JSON in line with the text formats where multiplying by 1000
+would round a value like 2.0005 the other way.
+*/
+pub fn round3(x: f32) -> f32 {
+    format!("{:.3}", x).parse().unwrap_or(x)
+}
+
+/*
+Only the written crossfade is shifted, the results keep what was measured. It
+stays positive and short enough to leave some of the track heard.
+*/
+fn jitter(cross: f32, result: &AnalyzeResult, amount: f32, rng: &mut Rng) -> f32 {
+    if amount <= 0. {
+        return cross;
+    }
+    let heard = f32::max(result.duration - result.cue_point, 0.);
+    (cross + rng.next_signed() * amount).clamp(0., heard)
+}
+
+/*
+The entry's line, or lines, for the m3u: its values as Liquidsoap annotations
+on the path, or with --annotation-style comment as a comment above the path as
+it was, shorter names and no quotes:
+
+#EXT-BOUNDARIES:cue_in=1.234,cross=5.678,duration=241.200,amplify=-2.100dB
+/music/track.flac
+
+Keys left out (None) are up to the playout's defaults.
+*/
+fn annotate(
+    opts: &RenderOptions,
+    result: &AnalyzeResult,
+    cue_in: Option<f32>,
+    cross: Option<f32>,
+    cue_out: Option<f32>,
+    fade_out: Option<f32>,
+    fade_type: Option<FadeType>,
+) -> String {
+    // annotate name, comment name, value
+    let values = [
+        (
+            "liq_cue_in",
+            "cue_in",
+            cue_in.map(|c| format!("{:.3}", opts.cue_in(c))),
+        ),
+        (
+            opts.preset.cross_key(),
+            "cross",
+            // the comment keeps its own name, and with it the length
+            cross.map(|c| match opts.annotation_style {
+                AnnotationStyle::Annotate => {
+                    let end = cue_out.unwrap_or(result.duration);
+                    format!("{:.3}", opts.time(opts.preset.cross_value(c, end)))
+                }
+                AnnotationStyle::Comment => format!("{:.3}", opts.time(c)),
+            }),
+        ),
+        (
+            "duration",
+            "duration",
+            Some(format!("{:.3}", opts.time(result.duration))),
+        ),
+        (
+            opts.gain_key.as_str(),
+            "amplify",
+            Some(opts.gain_format.value(result.amplify())),
+        ),
+        (
+            "liq_cue_out",
+            "cue_out",
+            cue_out.map(|c| format!("{:.3}", opts.cue_out(c))),
+        ),
+        (
+            "liq_fade_out",
+            "fade_out",
+            fade_out.map(|f| format!("{:.3}", opts.time(f))),
+        ),
+        (
+            "liq_fade_out_type",
+            "fade_type",
+            fade_type.map(|f| f.name().to_string()),
+        ),
+        (
+            "liq_longtail",
+            "longtail",
+            (opts.emit_longtail && result.long_tail).then(|| String::from("true")),
+        ),
+    ];
+    let values = values
+        .iter()
+        .filter_map(|(liq, short, value)| Some((*liq, *short, value.as_ref()?)));
+
+    match opts.annotation_style {
+        AnnotationStyle::Annotate => format!(
+            "annotate:{}:{}\n",
+            values
+                .map(|(key, _, value)| format!("{}=\"{}\"", key, value))
+                .collect::<Vec<_>>()
+                .join(","),
+            annotate_uri(&result.path)
+        ),
+        AnnotationStyle::Comment => format!(
+            "#{}:{}\n{}\n",
+            opts.comment_key,
+            values
+                .map(|(_, key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(","),
+            result.path
+        ),
+    }
+}
+
+/*
+Liquidsoap reads an annotate line up to the first colon after the last
+annotation, so a Windows path (C:\Music\...) or an absolute one with a colon
+of its own doesn't come through. Those are written as file:// URIs with
+everything outside the unreserved set percent-encoded, which Liquidsoap decodes
+back into the path. URIs are left alone, they're already written the way
+Liquidsoap expects them, and so are relative paths, which a file:// URI can't
+express. playlist::played_path reads them back, a drive letter path with
+backslashes.
+*/
+pub fn annotate_uri(path: &str) -> String {
+    let drive = path.as_bytes().get(1) == Some(&b':') && path.as_bytes()[0].is_ascii_alphabetic();
+    if !path.contains(':') || path.contains("://") || !(drive || path.starts_with('/')) {
+        return path.to_string();
+    }
+
+    let path = path.replace('\\', "/");
+    let mut uri = String::from("file://");
+    // a drive letter path needs the root slash of an absolute one
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+#[derive(Serialize)]
+struct JsonTrack<'a> {
+    path: &'a str,
+    cue_in: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cue_in_loudness: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cue_out: Option<f32>,
+    leading_silence: f32,
+    cross_duration: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    own_cross_duration: Option<f32>,
+    duration: f32,
+    loudness: f32,
+    loudness_source: LoudnessSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak: Option<f32>,
+    peak_mode: PeakMode,
+    lra: f32,
+    crest: f32,
+    speechlike: bool,
+    profile: Profile,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    noise_floor: Option<f32>,
+    applause: bool,
+    cold_ending: bool,
+    long_tail: bool,
+    overlap_clamped: bool,
+    truncated_measurement: bool,
+    grouped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flag: Option<Flag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hidden_track_start: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_out: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_type: Option<FadeType>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    channel_levels: Vec<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_imbalance: Option<f32>,
+    imbalanced: bool,
+    dual_mono: bool,
+    /// The #EXTALB or directory --album-report groups the track by
+    album: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dc_offset: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak_count: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flat_factor: Option<f32>,
+    clipping: bool,
+    dc_offset_high: bool,
+    amplify: f32,
+    start_time: f32,
+}
+
+fn render_json(tracks: &[Track], opts: &RenderOptions) -> String {
+    let timeline = timeline::timeline(tracks);
+    let json: Vec<JsonTrack> = tracks
+        .iter()
+        .zip(&timeline.starts)
+        .filter_map(|(t, start)| Some((t, t.analyzed()?, (*start)?)))
+        .map(|(t, r, start)| JsonTrack {
+            path: &r.path,
+            cue_in: opts.cue_in(r.cue_point),
+            cue_in_loudness: r.loudness_cue.map(round3),
+            cue_out: r.cue_out.map(|c| opts.cue_out(c)),
+            leading_silence: round3(r.leading_silence),
+            cross_duration: opts.time(r.start_next),
+            own_cross_duration: r.own_cross.map(|c| opts.time(c)),
+            duration: opts.time(r.duration),
+            loudness: round3(r.loudness),
+            loudness_source: r.loudness_source,
+            peak: r.peak.map(round3),
+            peak_mode: r.peak_mode,
+            lra: round3(r.lra),
+            crest: round3(r.crest),
+            speechlike: r.speechlike,
+            profile: r.profile,
+            noise_floor: r.noise_floor.map(round3),
+            applause: r.applause,
+            cold_ending: r.cold_ending,
+            long_tail: r.long_tail,
+            overlap_clamped: r.overlap_clamped,
+            truncated_measurement: r.truncated,
+            grouped: r.grouped,
+            flag: r.flag,
+            hidden_track_start: r.hidden.as_ref().map(|h| opts.cue_in(h.start)),
+            fade_out: r.fade_out.map(|f| opts.time(f)),
+            fade_type: r.fade_type,
+            channel_levels: r.channel_levels.iter().cloned().map(round3).collect(),
+            channel_imbalance: r.channel_imbalance().map(round3),
+            imbalanced: r.imbalanced,
+            dual_mono: r.dual_mono,
+            album: albums::key(&t.entry),
+            // a fraction of full scale, 3 decimals would round most of them away
+            dc_offset: r.qc.as_ref().map(|q| q.dc_offset),
+            peak_count: r.qc.as_ref().map(|q| q.peak_count),
+            flat_factor: r.qc.as_ref().map(|q| round3(q.flat_factor)),
+            clipping: r.clipping,
+            dc_offset_high: r.dc_offset_high,
+            amplify: round3(r.amplify()),
+            start_time: round3(start),
+        })
+        .collect();
+
+    let mut s = serde_json::to_string_pretty(&json).unwrap();
+    s.push('\n');
+    s
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn render_csv(tracks: &[Track], opts: &RenderOptions) -> String {
+    let mut s = String::from(
+        "path,cue_in,leading_silence,cross_duration,duration,loudness,amplify,lra,crest,speechlike,channel_imbalance,start_time\n",
+    );
+
+    let timeline = timeline::timeline(tracks);
+    for (r, start) in tracks
+        .iter()
+        .zip(&timeline.starts)
+        .filter_map(|(t, start)| Some((t.analyzed()?, (*start)?)))
+    {
+        s.push_str(&format!(
+            "{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{},{},{:.3}\n",
+            csv_field(&r.path),
+            opts.cue_in(r.cue_point),
+            r.leading_silence,
+            opts.time(r.start_next),
+            opts.time(r.duration),
+            r.loudness,
+            r.amplify(),
+            r.lra,
+            r.crest,
+            r.speechlike,
+            // left empty when channels weren't measured
+            r.channel_imbalance()
+                .map(|i| format!("{:.3}", i))
+                .unwrap_or_default(),
+            start
+        ));
+    }
+
+    s
+}
+
+fn render_pls(tracks: &[Track]) -> String {
+    let mut s = String::from("[playlist]\n");
+
+    for (i, track) in tracks.iter().enumerate() {
+        let n = i + 1;
+        let path = &track.entry.path;
+        let title = Path::new(path)
+            .file_stem()
+            .map(|t| t.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.clone());
+        // PLS lengths are whole seconds, -1 means unknown
+        let length = match track.analyzed() {
+            Some(r) => r.duration.round() as i64,
+            None => -1,
+        };
+        s.push_str(&format!("File{n}={}\n", path));
+        s.push_str(&format!("Title{n}={title}\n"));
+        s.push_str(&format!("Length{n}={}\n", length));
+    }
+
+    s.push_str(&format!("NumberOfEntries={}\nVersion=2\n", tracks.len()));
+    s
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// XSPF wants URIs in <location>, so plain paths become percent-encoded file:// URIs
+fn location_uri(path: &str) -> String {
+    if path.contains("://") {
+        return path.to_string();
+    }
+
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.replace('\\', "/").bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+fn render_xspf(tracks: &[Track], opts: &RenderOptions) -> String {
+    let mut s = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+
+    for track in tracks {
+        s.push_str("    <track>\n");
+        s.push_str(&format!(
+            "      <location>{}</location>\n",
+            xml_escape(&location_uri(&track.entry.path))
+        ));
+        let r = match track.analyzed() {
+            Some(r) => r,
+            None => {
+                s.push_str("    </track>\n");
+                continue;
+            }
+        };
+        // XSPF durations are milliseconds
+        s.push_str(&format!(
+            "      <duration>{}</duration>\n",
+            (r.duration * 1000.).round() as i64
+        ));
+        for (rel, value) in [
+            ("liq_cue_in", format!("{:.3}", opts.cue_in(r.cue_point))),
+            (
+                opts.preset.cross_key(),
+                format!(
+                    "{:.3}",
+                    opts.time(
+                        opts.preset
+                            .cross_value(r.start_next, r.cue_out.unwrap_or(r.duration))
+                    )
+                ),
+            ),
+            (opts.gain_key.as_str(), opts.gain_format.value(r.amplify())),
+        ] {
+            s.push_str(&format!("      <meta rel=\"{rel}\">{value}</meta>\n"));
+        }
+        s.push_str("    </track>\n");
+    }
+
+    s.push_str("  </trackList>\n</playlist>\n");
+    s
+}
+
+/*
+Chapters of all analysed tracks on one timeline, as they'd play one after the
+other, so a playlist of parts comes out as one programme. A single file gets
+its own chapters. Times before a track's cue-in are cut, so its first chapter
+starts where the track is heard.
+*/
+fn render_chapters(tracks: &[Track], style: ChapterStyle) -> String {
+    let timeline = timeline::timeline(tracks);
+
+    let mut chapters: Vec<(f32, f32)> = Vec::new();
+    for (r, start) in tracks
+        .iter()
+        .zip(&timeline.starts)
+        .filter_map(|(t, start)| Some((t.analyzed()?, (*start)?)))
+    {
+        for &c in &r.chapters {
+            chapters.push((start + f32::max(c - r.cue_point, 0.), 0.));
+        }
+    }
+    // each chapter runs until the next, the last one until the end
+    for i in 0..chapters.len() {
+        chapters[i].1 = chapters.get(i + 1).map_or(timeline.total, |c| c.0);
+    }
+
+    let mut s = String::new();
+    match style {
+        ChapterStyle::Webvtt => {
+            s.push_str("WEBVTT\n");
+            for (i, (start, end)) in chapters.iter().enumerate() {
+                s.push_str(&format!(
+                    "\n{}\n{} --> {}\nChapter {}\n",
+                    i + 1,
+                    timeline::hms(*start),
+                    timeline::hms(*end),
+                    i + 1
+                ));
+            }
+        }
+        ChapterStyle::Ffmetadata => {
+            s.push_str(";FFMETADATA1\n");
+            for (i, (start, end)) in chapters.iter().enumerate() {
+                s.push_str(&format!(
+                    "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle=Chapter {}\n",
+                    (start * 1000.).round() as u64,
+                    (end * 1000.).round() as u64,
+                    i + 1
+                ));
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::Entry;
+    use crate::testing::{self, render_options as options};
+
+    fn entry(path: &str, directives: &[&str]) -> Entry {
+        Entry {
+            path: path.to_string(),
+            directives: directives.iter().map(|d| d.to_string()).collect(),
+            line: 1,
+            region: None,
+        }
+    }
+
+    // a track with its cue-in and crossfade, 200s long at the target loudness
+    fn analyzed(path: &str, cue_point: f32, start_next: f32) -> Track {
+        Track {
+            entry: entry(path, &[]),
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                cue_point,
+                start_next,
+                duration: 200.,
+                loudness: -23.,
+                target: -23.,
+                path: path.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn format_of(output: &str) -> Format {
+        select_format(None, Some(Path::new(output)), false)
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(format_of("out.json"), Format::Json);
+        assert_eq!(format_of("out.csv"), Format::Csv);
+        assert_eq!(format_of("out.pls"), Format::Pls);
+        assert_eq!(format_of("out.xspf"), Format::Xspf);
+        assert_eq!(format_of("out.vtt"), Format::Chapters);
+        assert_eq!(format_of("out.m3u"), Format::M3u);
+        assert_eq!(format_of("out.m3u8"), Format::M3u);
+        assert_eq!(format_of("OUT.JSON"), Format::Json);
+    }
+
+    #[test]
+    fn unknown_extension_is_m3u() {
+        assert_eq!(format_of("out.txt"), Format::M3u);
+        assert_eq!(format_of("out"), Format::M3u);
+        assert_eq!(select_format(None, None, false), Format::M3u);
+    }
+
+    #[test]
+    fn explicit_format_wins() {
+        let output = Some(Path::new("out.json"));
+        assert_eq!(select_format(Some(Format::Csv), output, false), Format::Csv);
+    }
+
+    #[test]
+    fn header() {
+        let tracks = [analyzed("a.flac", 1., 3.)];
+        let mut opts = options(&[]);
+        opts.run_info = Some(RunInfo::new(Path::new("playlist.m3u8"), false));
+        let m3u = render(Format::M3u, &tracks, &opts);
+        assert!(m3u.starts_with("#EXTM3U\n#BOUNDARIES-RUN:"), "{}", m3u);
+        assert_eq!(m3u.matches("#EXTM3U").count(), 1);
+    }
+
+    #[test]
+    fn no_header() {
+        let tracks = [analyzed("a.flac", 1., 3.)];
+        let opts = crate::render_options(
+            &testing::args(&["--no-header"]),
+            Path::new("playlist.m3u8"),
+            0,
+        );
+        let m3u = render(Format::M3u, &tracks, &opts);
+        assert!(m3u.starts_with("annotate:"), "{}", m3u);
+        assert!(!m3u.contains("#EXTM3U") && !m3u.contains(runinfo::PREFIX));
+        // appended lines go after a header that's already there
+        assert!(!options(&["--append"]).header);
+    }
+
+    #[test]
+    fn header_absent_from_the_input() {
+        // an input without #EXTM3U still gets one, and a skipped entry's stale run comments go
+        let skipped = Track {
+            entry: entry("b.flac", &["#BOUNDARIES-RUN:date=x", "#EXTINF:-1,B"]),
+            outcome: Outcome::Skipped(String::from("not analysed")),
+        };
+        let tracks = [analyzed("a.flac", 1., 3.), skipped];
+        let m3u = render(Format::M3u, &tracks, &options(&[]));
+        assert!(m3u.starts_with("#EXTM3U\nannotate:"), "{}", m3u);
+        assert!(m3u.ends_with("\n#EXTINF:-1,B\nb.flac\n"), "{}", m3u);
+        assert!(!m3u.contains("#BOUNDARIES-RUN"));
+    }
+
+    #[test]
+    fn cue_out_is_written_rounded_up() {
+        let mut track = analyzed("a.flac", 1., 3.);
+        if let Some(r) = track.analyzed_mut() {
+            r.cue_out = Some(30.0004);
+        }
+        let m3u = render(Format::M3u, &[track], &options(&["--no-header"]));
+        assert!(m3u.contains("liq_cue_out=\"30.001\""), "{}", m3u);
+
+        // none for a track that plays to its end
+        let m3u = render(Format::M3u, &[analyzed("a.flac", 1., 3.)], &options(&[]));
+        assert!(!m3u.contains("liq_cue_out"), "{}", m3u);
+    }
+
+    #[test]
+    fn linear_gain() {
+        assert_eq!(GainFormat::Linear.value(0.), "1.000000");
+        assert_eq!(GainFormat::Linear.value(-20.), "0.100000");
+        assert_eq!(GainFormat::Linear.value(-6.), "0.501187");
+        assert_eq!(GainFormat::Linear.value(6.), "1.995262");
+        assert_eq!(GainFormat::Db.value(-6.), "-6.000dB");
+
+        // and it reads back as the same gain in dB
+        let mut track = analyzed("a.flac", 1., 3.);
+        if let Some(r) = track.analyzed_mut() {
+            r.loudness = -17.;
+        }
+        let m3u = render(
+            Format::M3u,
+            &[track],
+            &options(&["--no-header", "--gain-format", "linear"]),
+        );
+        assert!(m3u.contains("liq_amplify=\"0.501187\""), "{}", m3u);
+        let line = m3u.lines().find(|l| l.starts_with("annotate:")).unwrap();
+        let annotation = crate::playlist::parse_annotate(line).unwrap();
+        assert!((annotation.get_db("liq_amplify").unwrap() + 6.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn line_endings_byte_for_byte() {
+        assert_eq!(
+            LineEnding::of("#EXTM3U\r\na.flac\n"),
+            Some(LineEnding::Crlf)
+        );
+        assert_eq!(LineEnding::of("#EXTM3U\na.flac\r\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::of("a.flac"), None);
+
+        let tracks = [analyzed("a.flac", 1., 3.), analyzed("b.flac", 0.5, 2.)];
+        let m3u = render(Format::M3u, &tracks, &options(&["--no-header"]));
+        let lf = "annotate:liq_cue_in=\"1.000\",liq_cross_duration=\"3.000\",duration=\"200.000\",liq_amplify=\"0.000dB\":a.flac\n\
+                  annotate:liq_cue_in=\"0.500\",liq_cross_duration=\"2.000\",duration=\"200.000\",liq_amplify=\"0.000dB\":b.flac\n";
+        assert_eq!(LineEnding::Lf.apply(&m3u).as_bytes(), lf.as_bytes());
+        assert_eq!(
+            LineEnding::Crlf.apply(&m3u).as_bytes(),
+            lf.replace('\n', "\r\n").as_bytes()
+        );
+        // every line, the last one too, and no \n left on its own
+        let crlf = LineEnding::Crlf.apply(&m3u);
+        assert_eq!(crlf.matches("\r\n").count(), 2);
+        assert_eq!(crlf.matches('\n').count(), 2);
+    }
+
+    #[test]
+    fn rounding_modes() {
+        assert_eq!(Rounding::Nearest.apply(1.2345), 1.235);
+        assert_eq!(Rounding::Down.apply(1.2349), 1.234);
+        assert_eq!(Rounding::Up.apply(1.2341), 1.235);
+        // already on a millisecond, stored a hair below it, stays there
+        assert_eq!(Rounding::Down.apply(1.234), 1.234);
+        assert_eq!(Rounding::Up.apply(1.234), 1.234);
+        assert!(Rounding::Down.apply(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn rounding_per_field() {
+        // cue-ins down, cue-outs up, everything else to the nearest millisecond
+        let opts = options(&[]);
+        assert_eq!(opts.cue_in(1.2346), 1.234);
+        assert_eq!(opts.cue_out(1.2341), 1.235);
+        assert_eq!(opts.time(1.2346), 1.235);
+        assert_eq!(opts.time(1.2344), 1.234);
+
+        // --round applies to all of them
+        let opts = options(&["--round", "nearest"]);
+        assert_eq!(opts.cue_in(1.2346), 1.235);
+        assert_eq!(opts.cue_out(1.2341), 1.234);
+        let opts = options(&["--round", "up"]);
+        assert_eq!(opts.cue_in(1.2341), 1.235);
+        assert_eq!(opts.time(1.2341), 1.235);
+
+        let mut track = analyzed("a.flac", 1.2346, 3.0004);
+        if let Some(r) = track.analyzed_mut() {
+            r.cue_out = Some(30.0001);
+        }
+        let m3u = render(Format::M3u, &[track], &options(&[]));
+        assert!(m3u.contains("liq_cue_in=\"1.234\""), "{}", m3u);
+        assert!(m3u.contains("liq_cue_out=\"30.001\""), "{}", m3u);
+        assert!(m3u.contains("=\"3.000\""), "{}", m3u);
+    }
+
+    #[test]
+    fn extinf_modes() {
+        let line = "#EXTINF:200,Artist - Title";
+        assert_eq!(extinf(line, ExtinfDuration::Keep, 212.6), line);
+        assert_eq!(
+            extinf(line, ExtinfDuration::Measured, 212.6),
+            "#EXTINF:213,Artist - Title"
+        );
+        assert_eq!(
+            extinf(line, ExtinfDuration::Measured, 180.2),
+            "#EXTINF:180,Artist - Title"
+        );
+        assert_eq!(extinf(line, ExtinfDuration::Max, 180.2), line);
+        assert_eq!(
+            extinf(line, ExtinfDuration::Max, 212.6),
+            "#EXTINF:213,Artist - Title"
+        );
+        // -1 is unknown, shorter than anything measured
+        assert_eq!(
+            extinf("#EXTINF:-1,Stream", ExtinfDuration::Max, 30.),
+            "#EXTINF:30,Stream"
+        );
+    }
+
+    #[test]
+    fn extinf_keeps_what_it_cant_read() {
+        // attributes after the duration stay, and so does a line without a number
+        assert_eq!(
+            extinf(
+                "#EXTINF:200 tvg-id=\"x\",Title",
+                ExtinfDuration::Measured,
+                10.
+            ),
+            "#EXTINF:10 tvg-id=\"x\",Title"
+        );
+        assert_eq!(
+            extinf("#EXTINF:,Title", ExtinfDuration::Measured, 10.),
+            "#EXTINF:,Title"
+        );
+        assert_eq!(
+            extinf("#EXTVLCOPT:x", ExtinfDuration::Measured, 10.),
+            "#EXTVLCOPT:x"
+        );
+    }
+
+    #[test]
+    fn extinf_of_a_cue_sheet_track_counts_from_its_start() {
+        let mut track = analyzed("disc.flac", 121., 3.);
+        track.entry.directives = vec![String::from("#EXTINF:-1,Track 2")];
+        track.entry.region = Some(playlist::Region {
+            start: 120.,
+            end: Some(200.),
+            raw: false,
+        });
+        let m3u = render(
+            Format::M3u,
+            &[track],
+            &options(&["--extinf-duration", "measured"]),
+        );
+        assert!(m3u.contains("#EXTINF:80,Track 2\n"), "{}", m3u);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::output::round3;
+use crate::playlist::Track;
+use crate::timeline;
+use crate::AnalyzeResult;
+use serde::Serialize;
+
+/*
+An overlap further than this many standard deviations from the mean of the
+rotation's is listed as unusual, with at least MIN_TRANSITIONS to tell what
+usual is.
+*/
+const OUTLIER_SIGMA: f32 = 2.;
+const MIN_TRANSITIONS: usize = 5;
+
+/// A transition whose overlap stands out from the rest of the rotation
+#[derive(Clone, Serialize)]
+pub struct Unusual {
+    pub from: String,
+    pub to: String,
+    /// Seconds both tracks play at once
+    pub overlap: f32,
+    /// "long" or "short"
+    pub kind: &'static str,
+}
+
+/// What --overlap-report finds, for the run summary
+#[derive(Clone, Serialize)]
+pub struct Report {
+    pub tracks: usize,
+    pub transitions: usize,
+    /// Seconds of every track from cue-in to cue-out, added up
+    pub played: f32,
+    /// Seconds taken off that by the crossfades
+    pub overlapped: f32,
+    /// What's left, the length of the rotation on air
+    pub length: f32,
+    pub mean_overlap: f32,
+    pub unusual: Vec<Unusual>,
+}
+
+// a track's result and how long it plays, from cue-in to cue-out
+fn heard(track: &Track) -> Option<(&AnalyzeResult, f32)> {
+    let r = track.analyzed()?;
+    Some((
+        r,
+        f32::max(r.cue_out.unwrap_or(r.duration) - r.cue_point, 0.),
+    ))
+}
+
+/*
+Adds up the rotation in its final order: what every analysed track plays, and
+how much of that the crossfades take off, which is what a fixed-length program
+has to plan around. A transition is between two analysed tracks next to each
+other, its overlap the outgoing track's crossfade, cut to what it plays. The
+last track's crossfade has nothing to overlap with. Tracks that failed or were
+skipped are left out, and so are the transitions on either side of them.
+*/
+pub fn report(tracks: &[Track]) -> Report {
+    let played = tracks.iter().filter_map(heard).map(|(_, h)| h).sum();
+    let mut overlaps = Vec::new();
+    for pair in tracks.windows(2) {
+        let (Some((prev, heard)), Some((next, _))) = (heard(&pair[0]), heard(&pair[1])) else {
+            continue;
+        };
+        overlaps.push((prev, next, prev.start_next.clamp(0., heard)));
+    }
+
+    let transitions = overlaps.len();
+    let overlapped: f32 = overlaps.iter().map(|(_, _, o)| o).sum();
+    let mean_overlap = if transitions > 0 {
+        overlapped / transitions as f32
+    } else {
+        0.
+    };
+    let deviation = (overlaps
+        .iter()
+        .map(|(_, _, o)| (o - mean_overlap).powi(2))
+        .sum::<f32>()
+        / transitions.max(1) as f32)
+        .sqrt();
+
+    let unusual = overlaps
+        .iter()
+        .filter(|_| transitions >= MIN_TRANSITIONS && deviation > 0.)
+        .filter_map(|(prev, next, overlap)| {
+            let kind = match (overlap - mean_overlap) / deviation {
+                z if z > OUTLIER_SIGMA => "long",
+                z if z < -OUTLIER_SIGMA => "short",
+                _ => return None,
+            };
+            Some(Unusual {
+                from: prev.path.clone(),
+                to: next.path.clone(),
+                overlap: round3(*overlap),
+                kind,
+            })
+        })
+        .collect();
+
+    Report {
+        tracks: tracks.iter().filter(|t| t.analyzed().is_some()).count(),
+        transitions,
+        played: round3(played),
+        overlapped: round3(overlapped),
+        length: round3(played - overlapped),
+        mean_overlap: round3(mean_overlap),
+        unusual,
+    }
+}
+
+/// The report as text, for stderr
+pub fn render(report: &Report) -> String {
+    let mut s = format!(
+        "Rotation of {} tracks: {} played, {} overlapped, {} on air\n",
+        report.tracks,
+        timeline::hms(report.played),
+        timeline::hms(report.overlapped),
+        timeline::hms(report.length)
+    );
+    s.push_str(&format!(
+        "{} transitions, {:.2}s of overlap on average\n",
+        report.transitions, report.mean_overlap
+    ));
+    if !report.unusual.is_empty() {
+        s.push_str("Unusual overlaps:\n");
+        for u in &report.unusual {
+            s.push_str(&format!(
+                "  {:<5}  {:6.2}s  {} -> {}\n",
+                u.kind, u.overlap, u.from, u.to
+            ));
+        }
+    }
+    s
+}
@@ -0,0 +1,248 @@
+use crate::csvinput;
+use crate::playlist::Track;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A row of the overrides file, the values it has
+pub struct Override {
+    /// The path as written in the file
+    pub path: String,
+    /// Line of the file the row starts on
+    pub line: usize,
+    /// The real file, for rows naming one that exists
+    real: PathBuf,
+    cue_in: Option<f32>,
+    cross: Option<f32>,
+    amplify: Option<f32>,
+}
+
+/// What applying the overrides did
+pub struct Applied<'a> {
+    /// How many tracks had a row
+    pub count: usize,
+    /// The rows no track matched, likely stale
+    pub unmatched: Vec<&'a Override>,
+    /// A warning for every row whose values had to be brought in line
+    pub warnings: Vec<String>,
+}
+
+pub struct Overrides {
+    rows: Vec<Override>,
+    /// Rows match any track whose path ends with theirs
+    suffix: bool,
+}
+
+// forward slashes, so a spreadsheet from Windows matches paths here and the other way round
+fn normalize(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/*
+Reads an overrides CSV: a header row with a `path` column and any of `cue_in`,
+`cross` and `amplify`, then a row per track. An empty value leaves that one to
+the analysis. Paths relative to the file are taken from its directory, like
+the ones in a playlist.
+*/
+pub fn load(path: &Path, suffix: bool) -> io::Result<Overrides> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut records = csvinput::records(&contents).into_iter();
+
+    let (_, header) = records
+        .next()
+        .ok_or_else(|| invalid(String::from("no header row")))?;
+    let mut columns = [None; 4];
+    for (i, name) in header.iter().enumerate() {
+        let slot = match name.trim().to_lowercase().as_str() {
+            "path" => 0,
+            "cue_in" => 1,
+            "cross" => 2,
+            "amplify" => 3,
+            other => return Err(invalid(format!("unknown column '{}'", other))),
+        };
+        columns[slot] = Some(i);
+    }
+    let path_column = columns[0].ok_or_else(|| invalid(String::from("no path column")))?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut rows = Vec::new();
+    for (line, fields) in records {
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| fields.get(c))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let value = |column: Option<usize>| -> io::Result<Option<f32>> {
+            field(column)
+                // a gain can be written with its unit, like liq_amplify
+                .map(|f| f.trim_end_matches("dB").trim().parse::<f32>())
+                .transpose()
+                .map_err(|e| invalid(format!("line {}: {}", line, e)))
+        };
+
+        let Some(path) = field(Some(path_column)) else {
+            continue;
+        };
+        let joined = base.join(path);
+        rows.push(Override {
+            path: path.to_string(),
+            line,
+            real: fs::canonicalize(&joined).unwrap_or(joined),
+            cue_in: value(columns[1])?,
+            cross: value(columns[2])?,
+            amplify: value(columns[3])?,
+        });
+    }
+
+    Ok(Overrides { rows, suffix })
+}
+
+impl Overrides {
+    /*
+    The row for a track: the one naming the same real file, or with --overrides-suffix
+    the one whose path is the end of the track's, whole components only. The
+    first row wins when several match.
+    */
+    fn find(&self, path: &str) -> Option<usize> {
+        let real = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        self.rows.iter().position(|row| {
+            if self.suffix {
+                let real = normalize(&real);
+                let tail = normalize(Path::new(&row.path));
+                let tail = tail.trim_start_matches("./");
+                real.strip_suffix(tail)
+                    .is_some_and(|head| head.is_empty() || head.ends_with('/'))
+            } else {
+                row.real == real
+            }
+        })
+    }
+
+    /*
+    Replaces the analysed values of every track that has a row with the ones
+    the row gives. Values that don't fit the track, a cue-in past its end or a
+    crossfade leaving less than `min_playable` seconds, are brought in line
+    with a warning, the way the analysis would have.
+    */
+    pub fn apply(&self, tracks: &mut [Track], min_playable: f32) -> Applied<'_> {
+        let mut used = vec![false; self.rows.len()];
+        let mut count = 0;
+        let mut warnings = Vec::new();
+
+        for r in tracks.iter_mut().filter_map(Track::analyzed_mut) {
+            let Some(i) = self.find(&r.path) else {
+                continue;
+            };
+            let row = &self.rows[i];
+            if let Some(cue_in) = row.cue_in {
+                r.cue_point = cue_in;
+                r.cue_disabled = false;
+                r.loudness_cue = None;
+            }
+            if let Some(cross) = row.cross {
+                r.start_next = cross;
+                r.cross_disabled = false;
+            }
+            if let Some(amplify) = row.amplify {
+                r.amplify_override = Some(amplify);
+            }
+            if (row.cue_in.is_some() || row.cross.is_some()) && r.keep_consistent(min_playable) {
+                warnings.push(format!(
+                    "line {}: the override doesn't fit {}, using cue-in {:.3}s and crossfade {:.3}s",
+                    row.line, r.path, r.cue_point, r.start_next
+                ));
+            }
+            used[i] = true;
+            count += 1;
+        }
+
+        let unmatched = self
+            .rows
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(row, _)| row)
+            .collect();
+        Applied {
+            count,
+            unmatched,
+            warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::{Entry, Outcome};
+    use crate::testing::TempDir;
+    use crate::AnalyzeResult;
+
+    fn track(path: &str) -> Track {
+        Track {
+            entry: Entry {
+                path: path.to_string(),
+                directives: Vec::new(),
+                line: 1,
+                region: None,
+            },
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                cue_point: 0.5,
+                start_next: 2.,
+                duration: 10.,
+                path: path.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn load_csv(csv: &str) -> Overrides {
+        let dir = TempDir::new("overrides");
+        let path = dir.join("overrides.csv");
+        fs::write(&path, csv).unwrap();
+        load(&path, false).unwrap()
+    }
+
+    #[test]
+    fn overrides_are_kept_consistent() {
+        let overrides = load_csv(
+            "path,cue_in,cross\n\
+             /nowhere/fits.flac,1,3\n\
+             /nowhere/late.flac,9.5,\n\
+             /nowhere/past.flac,12,\n\
+             /nowhere/negative.flac,,-3\n",
+        );
+        let mut tracks: Vec<Track> = ["fits", "late", "past", "negative", "none"]
+            .iter()
+            .map(|name| track(&format!("/nowhere/{}.flac", name)))
+            .collect();
+        let applied = overrides.apply(&mut tracks, 1.);
+        assert_eq!((applied.count, applied.unmatched.len()), (4, 0));
+        // late and past had to give way to the track, negative to no crossfade at all
+        assert_eq!(applied.warnings.len(), 3);
+        assert!(
+            applied.warnings[0].starts_with("line 3: the override doesn't fit /nowhere/late.flac"),
+            "{:?}",
+            applied.warnings
+        );
+
+        let values: Vec<(f32, f32, bool)> = tracks
+            .iter()
+            .filter_map(Track::analyzed)
+            .map(|r| (r.cue_point, r.start_next, r.overlap_clamped))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (1., 3., false),
+                // the crossfade gives way to --min-playable, then there's none left
+                (9.5, 0., true),
+                (10., 0., true),
+                (0.5, 0., false),
+                (0.5, 2., false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::playlist::Track;
+
+/*
+The next track's opening, in LU relative to its loudness, at and above which
+the crossfade gets the shortest, and at and below which it's left alone.
+*/
+const LOUD_OPEN: f32 = -2.;
+const QUIET_OPEN: f32 = -12.;
+
+/// A crossfade --pairwise-cross shortened
+pub struct Adjustment {
+    pub from: String,
+    pub to: String,
+    /// The crossfade found from the outgoing track alone
+    pub own: f32,
+    pub adjusted: f32,
+}
+
+/*
+Each track's crossfade is found from how it ends, but what the overlap sounds
+like also depends on how the next one starts. Its cue-in already skips any
+silence, so what's left is how loud it opens: intro_level, the first seconds
+after the cue-in relative to its own loudness. A track that opens at full level
+covers the one going out within a second or two, so a long overlap only muddies
+both. One that fades in or opens quietly needs all the crossfade it gets.
+
+So for every pair of analysed tracks next to each other, the outgoing track's
+crossfade is scaled down by the next one's opening: kept as it is for an
+opening at QUIET_OPEN or quieter, down to `min_share` of it at LOUD_OPEN or
+louder, and in proportion in between. It's only ever shortened. Tracks with
+no crossfade, or whose crossfade was set with --no-cross, are left alone, as
+are pairs with a track in between that wasn't analysed.
+
+The crossfade each track had on its own stays in own_cross. Returns the pairs
+that changed.
+*/
+pub fn adjust(tracks: &mut [Track], min_share: f32) -> Vec<Adjustment> {
+    let mut adjusted = Vec::new();
+
+    for i in 1..tracks.len() {
+        let (before, after) = tracks.split_at_mut(i);
+        let (prev, next) = match (before[i - 1].analyzed_mut(), after[0].analyzed()) {
+            (Some(p), Some(n)) => (p, n),
+            _ => continue,
+        };
+        let intro = match next.intro_level {
+            Some(intro) if prev.start_next > 0. && !prev.cross_disabled => intro,
+            _ => continue,
+        };
+
+        let loudness = ((intro - QUIET_OPEN) / (LOUD_OPEN - QUIET_OPEN)).clamp(0., 1.);
+        let share = 1. - loudness * (1. - min_share.clamp(0., 1.));
+        if share >= 1. {
+            continue;
+        }
+
+        let own = prev.start_next;
+        prev.start_next = own * share;
+        prev.own_cross = Some(own);
+        adjusted.push(Adjustment {
+            from: prev.path.clone(),
+            to: next.path.clone(),
+            own,
+            adjusted: prev.start_next,
+        });
+    }
+
+    adjusted
+}
@@ -0,0 +1,783 @@
+use crate::AnalyzeResult;
+use clap::ValueEnum;
+use flate2::bufread::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+/// A track line from the input playlist, together with the comment lines
+/// (#EXTINF and friends) that came right before it
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub directives: Vec<String>,
+    /// Line of the playlist the path is on, counting from 1
+    pub line: usize,
+    /// The part of the file the entry is, for tracks of a CUE sheet
+    pub region: Option<Region>,
+}
+
+/// Where a track sits inside a bigger file, in seconds
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Region {
+    pub start: f32,
+    /// None for a track that runs to the end of the file
+    pub end: Option<f32>,
+    /// Headerless CD audio, which ffmpeg has to be told the format of
+    pub raw: bool,
+}
+
+/// What happened to an entry
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Analyzed(AnalyzeResult),
+    /// Deliberately not analysed, the entry is written back as it was
+    Skipped(String),
+    /// Analysis failed with this reason, the entry is written back unannotated
+    Failed(String),
+}
+
+/// An entry of the playlist after analysis
+#[derive(Clone)]
+pub struct Track {
+    pub entry: Entry,
+    pub outcome: Outcome,
+}
+
+impl Track {
+    pub fn analyzed(&self) -> Option<&AnalyzeResult> {
+        match &self.outcome {
+            Outcome::Analyzed(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn analyzed_mut(&mut self) -> Option<&mut AnalyzeResult> {
+        match &mut self.outcome {
+            Outcome::Analyzed(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    /// The track with the paths it's written with, see rewrite_path
+    pub fn rewritten(&self, rules: &[PathRewrite]) -> Track {
+        let mut track = self.clone();
+        track.entry.path = rewrite_path(&track.entry.path, rules);
+        if let Some(r) = track.analyzed_mut() {
+            r.path = rewrite_path(&r.path, rules);
+        }
+        track
+    }
+}
+
+/// A FROM=TO swap of a leading path prefix, for --path-rewrite
+#[derive(Clone, Debug)]
+pub struct PathRewrite {
+    from: String,
+    to: String,
+}
+
+pub fn parse_path_rewrite(s: &str) -> Result<PathRewrite, String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok(PathRewrite {
+            from: from.to_string(),
+            to: to.to_string(),
+        }),
+        _ => Err(String::from("expected FROM=TO")),
+    }
+}
+
+/*
+Swaps the prefix of the first rule that matches, keeping the rest of the path
+as it is. A prefix only matches whole directories, so /mnt/music doesn't turn
+/mnt/musicals into /media/als. Paths no rule matches stay the same.
+*/
+pub fn rewrite_path(path: &str, rules: &[PathRewrite]) -> String {
+    for rule in rules {
+        let rest = match path.strip_prefix(&rule.from) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let whole =
+            rest.is_empty() || rest.starts_with(['/', '\\']) || rule.from.ends_with(['/', '\\']);
+        if whole {
+            return format!("{}{}", rule.to, rest);
+        }
+    }
+    path.to_string()
+}
+
+/// Where a symlink that doesn't lead to a file points, None for anything else
+pub fn broken_link(path: &str) -> Option<String> {
+    let link = std::fs::symlink_metadata(path).ok()?;
+    if !link.file_type().is_symlink() || std::fs::metadata(path).is_ok() {
+        return None;
+    }
+    std::fs::read_link(path)
+        .ok()
+        .map(|t| t.to_string_lossy().to_string())
+}
+
+// the first bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn has_gz_extension(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"))
+}
+
+/// Whether an input is gzipped, by its .gz extension or by its first bytes
+pub fn is_gzipped(path: &Path) -> bool {
+    let mut magic = [0; 2];
+    has_gz_extension(path)
+        || File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok_and(|_| magic == GZIP_MAGIC)
+}
+
+/// The input's path without the .gz, what its format and the output's name go by
+pub fn without_gz(path: &Path) -> PathBuf {
+    if has_gz_extension(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+// decoding errors of a gzipped input, saying that's what they are
+struct Gunzip<R>(MultiGzDecoder<R>);
+
+impl<R: BufRead> Read for Gunzip<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("damaged gzip data: {}", e),
+            )
+        })
+    }
+}
+
+/*
+Opens an input file, decompressing it on the way when it's gzipped, so an
+archived show.m3u.gz can be read as it is. A damaged or cut off stream is an
+error when the reading gets to it, and never ends the playlist early without
+one.
+*/
+pub fn open_input(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if has_gz_extension(path) || reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(Gunzip(MultiGzDecoder::new(
+            reader,
+        )))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// The whole of an input file, see open_input
+pub fn read_input(path: &Path) -> io::Result<String> {
+    let mut contents = String::new();
+    open_input(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/*
+Comment lines belong to the track that follows them, so they're collected and
+attached to the next path line. That way they stay paired with their track
+whatever happens to the order later on. The #EXTM3U header isn't a directive,
+we write our own.
+*/
+pub fn read_playlist(path: &Path) -> io::Result<Vec<Entry>> {
+    entries(path)?.collect()
+}
+
+/// The entries of a playlist as its lines are read, see read_playlist
+pub struct Entries<R> {
+    lines: io::Lines<R>,
+    n: usize,
+    directives: Vec<String>,
+}
+
+impl<R: BufRead> Iterator for Entries<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            self.n += 1;
+            let line = line.trim_end_matches('\r');
+
+            if line.trim().is_empty() || line.starts_with("#EXTM3U") {
+                continue;
+            }
+
+            if line.starts_with('#') {
+                self.directives.push(line.to_string());
+                continue;
+            }
+
+            return Some(Ok(Entry {
+                path: line.to_string(),
+                directives: std::mem::take(&mut self.directives),
+                line: self.n,
+                region: None,
+            }));
+        }
+    }
+}
+
+/// Opens a playlist to read its entries one at a time, for --stream
+pub fn entries(path: &Path) -> io::Result<Entries<Box<dyn BufRead + Send>>> {
+    Ok(Entries {
+        lines: open_input(path)?.lines(),
+        n: 0,
+        directives: Vec::new(),
+    })
+}
+
+// characters that editors and copy and paste leave in paths, where they can't be seen
+const INVISIBLE: [(char, &str); 8] = [
+    ('\u{00A0}', "no-break space"),
+    ('\u{202F}', "narrow no-break space"),
+    ('\u{200B}', "zero width space"),
+    ('\u{200C}', "zero width non-joiner"),
+    ('\u{200D}', "zero width joiner"),
+    ('\u{2060}', "word joiner"),
+    ('\u{FEFF}', "byte order mark"),
+    ('\u{200E}', "left-to-right mark"),
+];
+
+/*
+Trims spaces, tabs and the like from both ends of every entry's path, and takes
+the characters of INVISIBLE out of it, unless a file by the name with them
+exists. Returns the line and the names of what was taken out for every entry
+that had any, the trimmed whitespace is too common to mention.
+*/
+pub fn clean_paths(entries: &mut [Entry]) -> Vec<(usize, Vec<&'static str>)> {
+    let mut cleaned = Vec::new();
+    for entry in entries {
+        let trimmed = entry.path.trim_matches(|c: char| c.is_ascii_whitespace());
+        let found: Vec<&str> = INVISIBLE
+            .iter()
+            .filter(|(c, _)| trimmed.contains(*c))
+            .map(|&(_, name)| name)
+            .collect();
+        if found.is_empty() || Path::new(trimmed).exists() {
+            entry.path = trimmed.to_string();
+            continue;
+        }
+        entry.path = trimmed
+            .chars()
+            .filter(|c| !INVISIBLE.iter().any(|(i, _)| i == c))
+            .collect::<String>()
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_string();
+        cleaned.push((entry.line, found));
+    }
+    cleaned
+}
+
+/*
+macOS keeps file names decomposed (NFD), an é as e and a combining accent,
+where Linux keeps whatever it was given, mostly composed (NFC). A playlist
+written on one then names files the other can't find, though the paths look
+the same. So a path that doesn't exist is looked up again one component at a
+time, each as written, composed or decomposed, whichever exists. Returns the
+path found and which form it took.
+*/
+fn other_form(path: &str) -> Option<(String, &'static str)> {
+    if path.is_ascii() || crate::validate::is_remote(path) || Path::new(path).exists() {
+        return None;
+    }
+    let mut found = PathBuf::new();
+    for component in Path::new(path).iter() {
+        let name = component.to_string_lossy();
+        let forms = [name.to_string(), name.nfc().collect(), name.nfd().collect()];
+        let name = forms.iter().find(|f| found.join(f).exists())?;
+        found.push(name);
+    }
+    let found = found.to_string_lossy().to_string();
+    let form = if found == path.nfc().collect::<String>() {
+        "NFC"
+    } else if found == path.nfd().collect::<String>() {
+        "NFD"
+    } else {
+        "NFC and NFD"
+    };
+    Some((found, form))
+}
+
+/*
+Points every entry whose file only exists under the other Unicode form of its
+path at that one, see other_form. Returns the line and the form for each.
+*/
+pub fn normalize_paths(entries: &mut [Entry]) -> Vec<(usize, &'static str)> {
+    let mut changed = Vec::new();
+    for entry in entries {
+        if let Some((path, form)) = other_form(&entry.path) {
+            entry.path = path;
+            changed.push((entry.line, form));
+        }
+    }
+    changed
+}
+
+/// What the input file is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// m3u or plain list of paths
+    M3u,
+    /// CUE sheet of a disc image
+    Cue,
+    /// CSV export with a path column, see --path-column
+    Csv,
+    /// PLS playlist
+    Pls,
+}
+
+impl InputFormat {
+    // .cue and .csv by their extension, under any .gz, anything else is a playlist
+    pub fn detect(path: &Path) -> InputFormat {
+        match without_gz(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+        {
+            Some(e) if e == "cue" => InputFormat::Cue,
+            Some(e) if e == "csv" => InputFormat::Csv,
+            Some(e) if e == "pls" => InputFormat::Pls,
+            _ => InputFormat::M3u,
+        }
+    }
+}
+
+/// Keys the output can be sorted by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Keep the playlist order
+    None,
+    Path,
+    Loudness,
+    Duration,
+    /// Crossfade duration
+    Cross,
+    /// Crest factor, most compressed first
+    Crest,
+}
+
+// tracks without results have nothing to compare, they go after the analysed ones
+fn sort_by_value(tracks: &mut [Track], value: impl Fn(&AnalyzeResult) -> f32) {
+    tracks.sort_by(|a, b| match (a.analyzed(), b.analyzed()) {
+        (Some(a), Some(b)) => value(a).total_cmp(&value(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// Sorts the analysed tracks. The sort is stable, so ties keep their playlist
+/// order, and --reverse on its own just flips the playlist.
+pub fn sort_tracks(tracks: &mut [Track], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::None => {}
+        SortKey::Path => tracks.sort_by(|a, b| a.entry.path.cmp(&b.entry.path)),
+        SortKey::Loudness => sort_by_value(tracks, |r| r.loudness),
+        SortKey::Duration => sort_by_value(tracks, |r| r.duration),
+        SortKey::Cross => sort_by_value(tracks, |r| r.start_next),
+        SortKey::Crest => sort_by_value(tracks, |r| r.crest),
+    }
+
+    if reverse {
+        tracks.reverse();
+    }
+}
+
+/// A parsed `annotate:key="value",...:path` line
+pub struct Annotation {
+    pub values: Vec<(String, String)>,
+    pub path: String,
+}
+
+impl Annotation {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // numeric values, ignoring a unit suffix like the "dB" on liq_amplify
+    pub fn get_f32(&self, key: &str) -> Option<f32> {
+        self.get(key)?
+            .trim()
+            .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    // a gain in dB, one written without the unit is a multiplier as Liquidsoap reads it
+    pub fn get_db(&self, key: &str) -> Option<f32> {
+        let value = self.get(key)?.trim();
+        if value.to_lowercase().ends_with("db") {
+            return self.get_f32(key);
+        }
+        let linear: f32 = value.parse().ok()?;
+        (linear > 0.).then(|| 20. * linear.log10())
+    }
+}
+
+/*
+Parses a Liquidsoap annotate line. Values are double quoted and may contain
+backslash escaped quotes, so we can't just split on commas and colons: the
+metadata ends at the first colon that isn't inside a value, and everything
+after it is the path, colons and all.
+*/
+pub fn parse_annotate(line: &str) -> Option<Annotation> {
+    let mut rest = line.strip_prefix("annotate:")?;
+    let mut values = Vec::new();
+
+    loop {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim().to_string();
+        rest = &rest[eq + 1..];
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = Some(i);
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[end? + 1..];
+            value
+        } else {
+            // unquoted values run until the next separator
+            let end = rest.find([',', ':'])?;
+            let value = rest[..end].to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        values.push((key, value));
+
+        if let Some(r) = rest.strip_prefix(',') {
+            rest = r;
+        } else {
+            let path = rest.strip_prefix(':')?;
+            return Some(Annotation {
+                values,
+                path: path.to_string(),
+            });
+        }
+    }
+}
+
+// undoes the percent-encoding of a file:// URI, bytes that don't decode are kept as they are
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/*
+The file a line of a processed playlist plays: the path after the annotations,
+with the file:// URIs written for paths with colons turned back into paths.
+A drive letter path gets its backslashes back, the URI has forward ones.
+Plain paths are returned as they are.
+*/
+pub fn played_path(line: &str) -> String {
+    let path = parse_annotate(line).map_or_else(|| line.to_string(), |a| a.path);
+    match path.strip_prefix("file://") {
+        Some(rest) => {
+            let decoded = percent_decode(rest);
+            // a drive letter path was written with the root slash of an absolute one
+            let drive = decoded.as_bytes().get(2) == Some(&b':');
+            if drive {
+                decoded[1..].replace('/', "\\")
+            } else {
+                decoded
+            }
+        }
+        None => path,
+    }
+}
+
+/*
+What a path comes down to, to tell whether two are the same file: the real
+path with symlinks, ./ and ../ resolved, or for a file that isn't there, the
+path as given in its composed Unicode form.
+*/
+pub fn resolve(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.nfc().collect())
+}
+
+/// Comment prefix for the file fingerprints --update relies on
+pub const FINGERPRINT_PREFIX: &str = "#BOUNDARIES-FINGERPRINT:";
+/// Comment prefix for the on-air start times of --timeline-comments
+pub const START_PREFIX: &str = "#BOUNDARIES-START:";
+
+/// Comment prefix for per-track options, e.g. `#BOUNDARIES-OPTS:no-cue`
+pub const OPTS_PREFIX: &str = "#BOUNDARIES-OPTS:";
+
+/// The per-track options of an entry, from all its OPTS_PREFIX lines. Options
+/// are separated by commas or spaces.
+pub fn track_options(entry: &Entry) -> Vec<&str> {
+    entry
+        .directives
+        .iter()
+        .filter_map(|d| d.strip_prefix(OPTS_PREFIX))
+        .flat_map(|o| o.split([',', ' ']))
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+/// Whether a comment line is one we write ourselves, and so gets replaced
+/// rather than carried over when a track is analysed again
+pub fn is_generated(directive: &str) -> bool {
+    [FINGERPRINT_PREFIX, START_PREFIX, crate::runinfo::PREFIX]
+        .iter()
+        .any(|p| directive.starts_with(p))
+}
+
+/// Size and modification time of a file, as stored after FINGERPRINT_PREFIX
+pub fn fingerprint(path: &str) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(format!("size={},mtime={}", metadata.len(), mtime))
+}
+
+/// Reads the annotate lines of a processed playlist, skipping anything else
+pub fn read_annotations(path: &Path) -> io::Result<Vec<Annotation>> {
+    Ok(read_playlist(path)?
+        .iter()
+        .filter_map(|e| parse_annotate(&e.path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn rules(rules: &[&str]) -> Vec<PathRewrite> {
+        rules
+            .iter()
+            .map(|r| parse_path_rewrite(r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn path_rewrite_parses() {
+        assert!(parse_path_rewrite("/mnt/music=/media").is_ok());
+        // an empty TO drops the prefix
+        assert!(parse_path_rewrite("/mnt/music=").is_ok());
+        assert!(parse_path_rewrite("=/media").is_err());
+        assert!(parse_path_rewrite("/mnt/music").is_err());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = rules(&["/mnt/music/live=/srv/live", "/mnt/music=/media", "/mnt=/x"]);
+        assert_eq!(
+            rewrite_path("/mnt/music/live/a.flac", &rules),
+            "/srv/live/a.flac"
+        );
+        assert_eq!(rewrite_path("/mnt/music/b.flac", &rules), "/media/b.flac");
+        assert_eq!(rewrite_path("/mnt/other/c.flac", &rules), "/x/other/c.flac");
+        assert_eq!(rewrite_path("/home/d.flac", &rules), "/home/d.flac");
+    }
+
+    #[test]
+    fn rewrite_matches_whole_directories() {
+        let rules = rules(&["/mnt/music=/media", "C:\\Music\\=D:\\"]);
+        assert_eq!(
+            rewrite_path("/mnt/musicals/a.flac", &rules),
+            "/mnt/musicals/a.flac"
+        );
+        assert_eq!(rewrite_path("/mnt/music", &rules), "/media");
+        assert_eq!(rewrite_path("C:\\Music\\a.flac", &rules), "D:\\a.flac");
+    }
+
+    #[test]
+    fn rewritten_track() {
+        let track = Track {
+            entry: Entry {
+                path: String::from("/mnt/music/a.flac"),
+                directives: Vec::new(),
+                line: 1,
+                region: None,
+            },
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                path: String::from("/mnt/music/a.flac"),
+                ..Default::default()
+            }),
+        };
+        let rewritten = track.rewritten(&rules(&["/mnt/music=/media"]));
+        assert_eq!(rewritten.entry.path, "/media/a.flac");
+        assert_eq!(rewritten.analyzed().unwrap().path, "/media/a.flac");
+        // without rules nothing changes
+        assert_eq!(track.rewritten(&[]).entry.path, "/mnt/music/a.flac");
+    }
+
+    fn analyzed(path: &str, loudness: f32, intro_level: f32) -> Track {
+        Track {
+            entry: Entry {
+                path: path.to_string(),
+                directives: Vec::new(),
+                line: 1,
+                region: None,
+            },
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                start_next: 4.,
+                duration: 100. + loudness.abs(),
+                loudness,
+                intro_level: Some(intro_level),
+                path: path.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn paths(tracks: &[Track]) -> Vec<&str> {
+        tracks.iter().map(|t| t.entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn reverse_after_sorting() {
+        let mut tracks = vec![
+            analyzed("a", -10., 0.),
+            analyzed("b", -20., 0.),
+            analyzed("c", -10., 0.),
+        ];
+        sort_tracks(&mut tracks, SortKey::None, true);
+        assert_eq!(paths(&tracks), ["c", "b", "a"]);
+        // ties keep their order before the flip
+        sort_tracks(&mut tracks, SortKey::Loudness, false);
+        assert_eq!(paths(&tracks), ["b", "c", "a"]);
+        sort_tracks(&mut tracks, SortKey::Loudness, true);
+        assert_eq!(paths(&tracks), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn reversed_neighbours() {
+        // a loud opening into b shortens a's crossfade, in the reversed order it's into c's
+        let mut tracks = vec![
+            analyzed("a", -10., -20.),
+            analyzed("b", -11., 0.),
+            analyzed("c", -12., -20.),
+        ];
+        sort_tracks(&mut tracks, SortKey::None, true);
+        let adjusted = crate::pairwise::adjust(&mut tracks, 0.5);
+        let pairs: Vec<(&str, &str)> = adjusted
+            .iter()
+            .map(|a| (a.from.as_str(), a.to.as_str()))
+            .collect();
+        assert_eq!(pairs, [("c", "b")]);
+
+        // start times follow the written order too
+        let starts = crate::timeline::timeline(&tracks).starts;
+        let c = tracks[0].analyzed().unwrap();
+        assert_eq!(starts[0], Some(0.));
+        assert_eq!(starts[1], Some(c.duration - c.start_next));
+    }
+
+    fn entries(paths: &[&str]) -> Vec<Entry> {
+        paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| Entry {
+                path: path.to_string(),
+                directives: Vec::new(),
+                line: i + 1,
+                region: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trailing_tab_is_trimmed_quietly() {
+        let mut entries = entries(&["/nowhere/a.flac\t", " \t/nowhere/b.flac \r"]);
+        assert!(clean_paths(&mut entries).is_empty());
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/nowhere/a.flac", "/nowhere/b.flac"]);
+    }
+
+    #[test]
+    fn no_break_space_is_taken_out() {
+        let mut entries = entries(&[
+            "/nowhere/a.flac\u{00A0}",
+            "/nowhere/b\u{00A0}c.flac\t",
+            "\u{FEFF}/nowhere/d.flac\u{00A0} ",
+        ]);
+        let cleaned = clean_paths(&mut entries);
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["/nowhere/a.flac", "/nowhere/bc.flac", "/nowhere/d.flac"]
+        );
+        assert_eq!(
+            cleaned,
+            [
+                (1, vec!["no-break space"]),
+                (2, vec!["no-break space"]),
+                (3, vec!["no-break space", "byte order mark"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_break_space_in_a_real_name_stays() {
+        let dir = TempDir::new("nbsp");
+        let file = dir.join("a\u{00A0}b.flac");
+        std::fs::write(&file, "").unwrap();
+        let path = file.to_string_lossy().to_string();
+
+        let mut entries = entries(&[&format!("{}\t", path)]);
+        assert!(clean_paths(&mut entries).is_empty());
+        assert_eq!(entries[0].path, path);
+    }
+}
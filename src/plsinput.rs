@@ -0,0 +1,62 @@
+use crate::playlist::{self, Entry};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/*
+Reads a PLS playlist: File1=..., with an optional Title1= and Length1= for the
+same number. Entries come in the order of their numbers, whatever order the
+lines are in, each with the line of its File key. A title becomes an #EXTINF
+line above the entry, like an m3u would have had, so the written playlist keeps
+it. Keys are case insensitive, as players read them.
+*/
+pub fn read_pls(path: &Path) -> io::Result<Vec<Entry>> {
+    let contents = playlist::read_input(path)?;
+
+    #[derive(Default)]
+    struct Item {
+        file: Option<(String, usize)>,
+        title: Option<String>,
+        length: Option<String>,
+    }
+    let mut items: BTreeMap<u64, Item> = BTreeMap::new();
+
+    for (n, line) in contents.trim_start_matches('\u{feff}').lines().enumerate() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let digits = key.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        let Ok(number) = digits.parse::<u64>() else {
+            continue;
+        };
+        let item = items.entry(number).or_default();
+        let value = value.trim().to_string();
+        match &key[..key.len() - digits.len()] {
+            "file" => item.file = Some((value, n + 1)),
+            "title" => item.title = Some(value),
+            "length" => item.length = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(items
+        .into_values()
+        .filter_map(|item| {
+            let (path, line) = item.file.filter(|(p, _)| !p.is_empty())?;
+            let directives = match item.title {
+                Some(title) => {
+                    let length = item.length.unwrap_or_else(|| String::from("-1"));
+                    vec![format!("#EXTINF:{},{}", length, title)]
+                }
+                None => Vec::new(),
+            };
+            Some(Entry {
+                path,
+                directives,
+                line,
+                region: None,
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,258 @@
+use crate::jobs;
+use crate::playlist::Track;
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// below this a crossfade is taken for a cut, acrossfade can't do 0
+const MIN_CROSS: f32 = 0.01;
+
+/// A stretch of one file, and the gain it gets
+struct Excerpt<'a> {
+    path: &'a str,
+    /// Headerless CD audio from a CUE sheet, which ffmpeg has to be told the format of
+    raw: bool,
+    start: f32,
+    length: f32,
+    gain: f32,
+}
+
+/// The end of one track, the start of the next and how long they overlap
+struct Transition<'a> {
+    from: Excerpt<'a>,
+    to: Excerpt<'a>,
+    cross: f32,
+}
+
+/*
+One transition of the preview: the last `excerpt` seconds of a track before its
+crossfade, then the crossfade into the next one, then its first `excerpt`
+seconds after the crossfade. Both sides are read around the points the
+playlist gives Liquidsoap. A CUE sheet track's times are already in the time
+of its file, so they're read from there like any other.
+*/
+fn transition<'a>(from: &'a Track, to: &'a Track, excerpt: f32) -> Option<Transition<'a>> {
+    let (a, b) = (from.analyzed()?, to.analyzed()?);
+    let raw = |t: &Track| t.entry.region.is_some_and(|r| r.raw);
+    let end = a.cue_out.unwrap_or(a.duration);
+    let cross = if a.cross_disabled {
+        0.
+    } else {
+        f32::min(a.start_next, end - a.cue_point)
+    };
+    let out_start = f32::max(end - cross - excerpt, a.cue_point);
+    Some(Transition {
+        from: Excerpt {
+            path: &a.path,
+            raw: raw(from),
+            start: out_start,
+            length: end - out_start,
+            gain: a.amplify(),
+        },
+        to: Excerpt {
+            path: &b.path,
+            raw: raw(to),
+            start: b.cue_point,
+            length: cross + excerpt,
+            gain: b.amplify(),
+        },
+        cross,
+    })
+}
+
+// the ffmpeg rendering one transition to `out`, two inputs and nothing more
+fn transition_command(t: &Transition, out: &Path) -> Command {
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg.args(["-hide_banner", "-nostdin", "-y"]);
+    let mut filter = String::new();
+    for (side, e) in [(0, &t.from), (1, &t.to)] {
+        if e.raw {
+            ffmpeg.args(["-f", "s16le", "-ar", "44100", "-ac", "2"]);
+        }
+        ffmpeg
+            .arg("-ss")
+            .arg(format!("{:.3}", e.start))
+            .arg("-t")
+            .arg(format!("{:.3}", e.length))
+            .arg("-i")
+            .arg(e.path);
+        // the same format everywhere, or the parts can't be joined
+        filter.push_str(&format!(
+            "[{side}:a]volume={:.3}dB,aformat=sample_fmts=fltp:sample_rates=44100:channel_layouts=stereo[s{side}];",
+            e.gain
+        ));
+    }
+    if t.cross < MIN_CROSS {
+        filter.push_str("[s0][s1]concat=n=2:v=0:a=1[out]");
+    } else {
+        filter.push_str(&format!("[s0][s1]acrossfade=d={:.3}[out]", t.cross));
+    }
+    ffmpeg
+        .arg("-filter_complex")
+        .arg(filter)
+        .args(["-map", "[out]", "-c:a", "pcm_f32le"])
+        .arg(out);
+    ffmpeg
+}
+
+// the ffmpeg joining the rendered transitions listed in `list` into `out`
+fn concat_command(list: &Path, out: &Path) -> Command {
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg
+        .args(["-hide_banner", "-nostdin", "-y"])
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(list)
+        .arg(out);
+    ffmpeg
+}
+
+fn run(ffmpeg: &mut Command) -> io::Result<()> {
+    let output = jobs::output(ffmpeg)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "ffmpeg failed: {}",
+            stderr.lines().last().unwrap_or("no output")
+        )));
+    }
+    Ok(())
+}
+
+// a line of ffmpeg's concat list, quoted the way it reads them
+fn concat_line(part: &Path) -> String {
+    format!("file '{}'\n", part.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/*
+Renders a file to listen through the playlist's transitions without a
+Liquidsoap: for every pair of neighbouring analysed tracks, `excerpt` seconds
+of the first, the crossfade, and `excerpt` seconds of the second, one
+transition after the other. Each side gets its liq_amplify, so the levels are
+the ones the stream would have.
+
+Every transition is an ffmpeg of its own, with acrossfade for the crossfade,
+run like the analysis under --jobs and stopped with it. They're written to a
+scratch directory and joined at the end, so a long playlist neither opens all
+its files in one process nor decodes them all at once. The output format
+follows the file extension. Returns how many transitions it has.
+*/
+pub fn render(tracks: &[Track], excerpt: f32, out: &Path) -> io::Result<usize> {
+    let transitions: Vec<_> = tracks
+        .windows(2)
+        .filter_map(|pair| transition(&pair[0], &pair[1], excerpt))
+        .collect();
+    if transitions.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no two analysed tracks in a row",
+        ));
+    }
+
+    let scratch = std::env::temp_dir().join(format!("boundaries-preview-{}", std::process::id()));
+    fs::create_dir_all(&scratch)?;
+    let rendered = render_parts(&transitions, &scratch, out);
+    let _ = fs::remove_dir_all(&scratch);
+    rendered.map(|_| transitions.len())
+}
+
+fn render_parts(transitions: &[Transition], scratch: &Path, out: &Path) -> io::Result<()> {
+    let parts: Vec<PathBuf> = (0..transitions.len())
+        .map(|i| scratch.join(format!("{:05}.wav", i)))
+        .collect();
+    transitions
+        .par_iter()
+        .zip(&parts)
+        .try_for_each(|(t, part)| run(&mut transition_command(t, part)))?;
+
+    let list = scratch.join("parts.txt");
+    fs::write(
+        &list,
+        parts.iter().map(|p| concat_line(p)).collect::<String>(),
+    )?;
+    run(&mut concat_command(&list, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::{Entry, Outcome, Region};
+    use crate::AnalyzeResult;
+
+    fn track(path: &str, region: Option<Region>) -> Track {
+        Track {
+            entry: Entry {
+                path: path.to_string(),
+                directives: Vec::new(),
+                line: 1,
+                region,
+            },
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                cue_point: 1.,
+                start_next: 3.,
+                duration: 60.,
+                loudness: -23.,
+                target: -23.,
+                path: path.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn one_transition_two_inputs() {
+        let tracks = [track("a.flac", None), track("b.flac", None)];
+        let t = transition(&tracks[0], &tracks[1], 5.).unwrap();
+        let args = args(&transition_command(&t, Path::new("part.wav")));
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 2);
+        // 5s before the crossfade to the end, and the crossfade and 5s of the next from its cue-in
+        assert!(args
+            .windows(4)
+            .any(|w| w == ["-ss", "52.000", "-t", "8.000"]));
+        assert!(args
+            .windows(4)
+            .any(|w| w == ["-ss", "1.000", "-t", "8.000"]));
+        assert!(args.iter().any(|a| a.ends_with("acrossfade=d=3.000[out]")));
+        assert!(!args.contains(&String::from("s16le")));
+    }
+
+    #[test]
+    fn raw_regions_are_read_as_cd_audio() {
+        let region = Region {
+            start: 120.,
+            end: Some(180.),
+            raw: true,
+        };
+        let mut from = track("disc.bin", Some(region));
+        if let Outcome::Analyzed(r) = &mut from.outcome {
+            // a CUE sheet track's times are in its file's
+            r.cue_point = 120.;
+            r.duration = 180.;
+            r.cue_out = Some(180.);
+        }
+        let tracks = [from, track("b.flac", None)];
+        let t = transition(&tracks[0], &tracks[1], 5.).unwrap();
+        let args = args(&transition_command(&t, Path::new("part.wav")));
+        let raw = ["-f", "s16le", "-ar", "44100", "-ac", "2", "-ss", "172.000"];
+        assert!(args.windows(raw.len()).any(|w| w == raw), "{:?}", args);
+        // only the side from the image
+        assert_eq!(args.iter().filter(|a| *a == "s16le").count(), 1);
+    }
+
+    #[test]
+    fn concat_list_quotes_paths() {
+        assert_eq!(concat_line(Path::new("/tmp/a.wav")), "file '/tmp/a.wav'\n");
+        assert_eq!(
+            concat_line(Path::new("/tmp/it's.wav")),
+            "file '/tmp/it'\\''s.wav'\n"
+        );
+    }
+}
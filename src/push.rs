@@ -0,0 +1,107 @@
+use std::io::{self, prelude::*, BufReader};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// how long to wait for Liquidsoap to connect or answer a command
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What Liquidsoap answered to one request
+pub struct Reply {
+    pub uri: String,
+    /// The answer, a request id on success
+    pub answer: String,
+    pub ok: bool,
+}
+
+/*
+A connection to Liquidsoap's command server (the telnet one). Every command is
+a line, and the answer is any number of lines closed by one saying END.
+*/
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    pub fn open(address: &str) -> io::Result<Connection> {
+        let addr = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("can't resolve {}", address),
+            )
+        })?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    pub fn command(&mut self, command: &str) -> io::Result<String> {
+        writeln!(self.writer, "{}", command)?;
+        let mut answer = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before END",
+                ));
+            }
+            let line = line.trim_end();
+            if line == "END" {
+                return Ok(answer.join("\n"));
+            }
+            answer.push(line.to_string());
+        }
+    }
+}
+
+// Liquidsoap answers errors in prose rather than with a status
+fn is_error(answer: &str) -> bool {
+    let answer = answer.to_lowercase();
+    answer.contains("error") || answer.contains("unknown command") || answer.is_empty()
+}
+
+/*
+Pushes every URI, in order, with `template` filled in: {queue} becomes `queue`
+and {uri} the annotated URI. With a password, `auth PASSWORD` is sent first,
+for command servers behind something that asks for one. A refused request
+doesn't stop the others, but a connection that drops does.
+*/
+pub fn push(
+    address: &str,
+    password: Option<&str>,
+    template: &str,
+    queue: &str,
+    uris: &[String],
+) -> io::Result<Vec<Reply>> {
+    let mut connection = Connection::open(address)?;
+
+    if let Some(password) = password {
+        let answer = connection.command(&format!("auth {}", password))?;
+        if is_error(&answer) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("authentication refused: {}", answer),
+            ));
+        }
+    }
+
+    let mut replies = Vec::with_capacity(uris.len());
+    for uri in uris {
+        let command = template.replace("{queue}", queue).replace("{uri}", uri);
+        let answer = connection.command(&command)?;
+        replies.push(Reply {
+            uri: uri.clone(),
+            ok: !is_error(&answer),
+            answer,
+        });
+    }
+
+    // it's polite to say goodbye, but the requests are in either way
+    let _ = writeln!(connection.writer, "quit");
+    Ok(replies)
+}
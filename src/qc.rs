@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+/// Added after ebur128 in the analysis pass with --qc, it passes the audio through untouched
+pub const FILTER: &str = "astats";
+
+/*
+A track whose peak is at least this close to full scale, in dBFS, and reached
+more often than --max-peak-count, is taken for clipped. A quieter one hitting
+its own peak over and over is more likely limited than clipped.
+*/
+pub const CLIP_LEVEL: f32 = -0.1;
+
+/// Default of --max-peak-count
+pub const MAX_PEAK_COUNT: f32 = 100.;
+
+/// Default of --max-dc-offset, as a fraction of full scale
+pub const MAX_DC_OFFSET: f32 = 0.005;
+
+/// What astats says about the whole track, over all its channels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Mean sample value, as a fraction of full scale, 0 for a healthy converter
+    pub dc_offset: f32,
+    /// Highest sample in dBFS
+    pub peak_level: f32,
+    /// Samples at the highest (or lowest) level
+    pub peak_count: f32,
+    /// How flat the waveform runs at its peak, above 0 for runs of samples stuck there
+    pub flat_factor: f32,
+}
+
+impl Stats {
+    pub fn clipping(&self, max_peak_count: f32) -> bool {
+        self.peak_level >= CLIP_LEVEL && self.peak_count > max_peak_count
+    }
+
+    pub fn dc_offset_high(&self, max_dc_offset: f32) -> bool {
+        self.dc_offset.abs() > max_dc_offset
+    }
+}
+
+/*
+Reads the "Overall" block astats prints at the end, after one per channel, from
+the same stderr as ebur128's lines, none of which start like astats' do. None
+when it isn't all there: the track's loudness is still good without it.
+*/
+pub fn parse_astats(stderr: &str) -> Option<Stats> {
+    let mut stats = Stats::default();
+    let mut found = 0;
+    let mut overall = false;
+
+    for line in stderr.lines() {
+        if !line.starts_with("[Parsed_astats") {
+            continue;
+        }
+        // strip the "[Parsed_astats_1 @ 0x...] " prefix
+        let Some(field) = line.find("] ").map(|i| &line[i + 2..]) else {
+            continue;
+        };
+        if field.starts_with("Overall") {
+            overall = true;
+            continue;
+        }
+        if !overall {
+            continue;
+        }
+        let Some((name, value)) = field.split_once(':') else {
+            continue;
+        };
+        let slot = match name {
+            "DC offset" => &mut stats.dc_offset,
+            "Peak level dB" => &mut stats.peak_level,
+            "Peak count" => &mut stats.peak_count,
+            "Flat factor" => &mut stats.flat_factor,
+            _ => continue,
+        };
+        *slot = value.trim().parse().ok()?;
+        found += 1;
+    }
+
+    (found == 4).then_some(stats)
+}
@@ -0,0 +1,146 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+// how many entries per thread can be taken on before the earliest one is done
+pub const DEPTH: usize = 4;
+
+// the entries taken on and not yet handed to the sink
+struct Window {
+    taken: Mutex<usize>,
+    freed: Condvar,
+    bound: usize,
+}
+
+impl Window {
+    fn take(&self) {
+        let mut taken = self.taken.lock().unwrap();
+        while *taken >= self.bound {
+            taken = self.freed.wait(taken).unwrap();
+        }
+        *taken += 1;
+    }
+
+    fn free(&self) {
+        *self.taken.lock().unwrap() -= 1;
+        self.freed.notify_one();
+    }
+}
+
+/*
+Runs `work` over `items` on rayon's threads and hands every result to `sink`
+in the order of the items, with its index. Items are only taken from the
+iterator while fewer than `bound` of them are being worked on or waiting for
+one before them to finish, so a slow track holds the ones after it back
+instead of letting them pile up, and an iterator that reads its items as it
+goes is never more than `bound` ahead.
+
+The sink runs on a thread of its own, the calling one works along with
+rayon's, so this is safe to call from inside the pool, with one thread too.
+*/
+pub fn in_order<T, R>(
+    items: impl Iterator<Item = T> + Send,
+    bound: usize,
+    work: impl Fn(usize, T) -> R + Sync,
+    mut sink: impl FnMut(usize, R) + Send,
+) where
+    T: Send,
+    R: Send,
+{
+    let window = Window {
+        taken: Mutex::new(0),
+        freed: Condvar::new(),
+        bound: bound.max(1),
+    };
+    let (done, finished) = mpsc::channel::<(usize, R)>();
+
+    thread::scope(|s| {
+        let window = &window;
+        s.spawn(move || {
+            let mut waiting = BTreeMap::new();
+            let mut next = 0;
+            for (i, result) in finished {
+                waiting.insert(i, result);
+                while let Some(result) = waiting.remove(&next) {
+                    sink(next, result);
+                    window.free();
+                    next += 1;
+                }
+            }
+        });
+
+        items
+            .enumerate()
+            .inspect(|_| window.take())
+            .par_bridge()
+            .for_each_with(done, |done, (i, item)| {
+                // the sink only goes away if it panicked, which the scope passes on
+                let _ = done.send((i, work(i, item)));
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn large_playlist_in_order_and_bounded() {
+        const ENTRIES: usize = 100_000;
+        const BOUND: usize = 16;
+        let taken = AtomicUsize::new(0);
+        let sunk = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+
+        // an iterator that counts how far ahead of the sink it's been read
+        let items = (0..ENTRIES).inspect(|_| {
+            let ahead = taken.fetch_add(1, Ordering::SeqCst) + 1 - sunk.load(Ordering::SeqCst);
+            most.fetch_max(ahead, Ordering::SeqCst);
+        });
+        let mut order = Vec::with_capacity(ENTRIES);
+        in_order(
+            items,
+            BOUND,
+            |i, item| {
+                assert_eq!(i, item);
+                // some entries are slow, the ones after them have to wait
+                if item % 997 == 0 {
+                    thread::sleep(Duration::from_millis(2));
+                }
+                item * 2
+            },
+            |i, result| {
+                assert_eq!(result, i * 2);
+                order.push(i);
+                sunk.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        assert_eq!(order.len(), ENTRIES);
+        assert!(order.iter().enumerate().all(|(i, &j)| i == j));
+        assert!(most.load(Ordering::SeqCst) <= BOUND + 1);
+    }
+
+    #[test]
+    fn empty_and_single_thread() {
+        let mut sunk = Vec::new();
+        in_order(
+            std::iter::empty::<usize>(),
+            4,
+            |_, x| x,
+            |_, x| sunk.push(x),
+        );
+        assert!(sunk.is_empty());
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        pool.install(|| in_order(0..100, 1, |_, x| x + 1, |_, x| sunk.push(x)));
+        assert_eq!(sunk, (1..101).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,52 @@
+/*
+A small seedable generator (SplitMix64), plenty for picking jitter or a sample. The
+standard library doesn't have one and the output only needs to be repeatable
+for a given --seed, not unpredictable.
+*/
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    /// Seeds from the clock, for when no seed was given
+    pub fn seed_from_time() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in 0..n, n > 0. The modulo bias is negligible for playlist sizes.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// `k` distinct indices out of 0..n, in ascending order
+    pub fn sample(&mut self, n: usize, k: usize) -> Vec<usize> {
+        // the first k steps of a Fisher-Yates shuffle
+        let mut indices: Vec<usize> = (0..n).collect();
+        for i in 0..k.min(n) {
+            let j = i + self.below(n - i);
+            indices.swap(i, j);
+        }
+        indices.truncate(k);
+        indices.sort_unstable();
+        indices
+    }
+
+    /// Uniform in [-1, 1)
+    pub fn next_signed(&mut self) -> f32 {
+        // the top 24 bits fill an f32's mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u64 << 23) as f32 - 1.
+    }
+}
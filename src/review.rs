@@ -0,0 +1,213 @@
+use crate::playlist::Track;
+use std::io::{self, BufRead, Write};
+
+/// A change made in --review, for the run summary
+pub struct Edit {
+    pub path: String,
+    pub line: usize,
+    /// cue_in, cross, amplify, or drop for a track taken out
+    pub field: &'static str,
+    /// The value set, None for a dropped track
+    pub value: Option<f32>,
+}
+
+/// How a review ended
+pub enum Reviewed {
+    Done(Vec<Edit>),
+    /// Stopped with q or the end of the input, nothing is to be written
+    Aborted,
+}
+
+const HELP: &str = "Enter accepts, c/x/g NUMBER sets the cue-in, crossfade or gain (dB), \
+d drops the track, a accepts the rest, q aborts";
+
+/*
+Goes through the analysed tracks one at a time with their values, asking what
+to do with each before anything is written. Values typed in replace the
+analysed ones the way --overrides does, held to the track and --min-playable
+the same way, and dropped tracks are taken out of the playlist. Tracks that
+failed or were skipped are passed over, there's nothing to review. Returns
+every change, in the order they were made.
+*/
+pub fn review(
+    tracks: &mut Vec<Track>,
+    min_playable: f32,
+    mut input: impl BufRead,
+    mut out: impl Write,
+) -> io::Result<Reviewed> {
+    let total = tracks.iter().filter(|t| t.analyzed().is_some()).count();
+    let mut edits = Vec::new();
+    let mut dropped = vec![false; tracks.len()];
+    let mut n = 0;
+
+    writeln!(out, "{}", HELP)?;
+    'tracks: for (track, dropped) in tracks.iter_mut().zip(dropped.iter_mut()) {
+        let line = track.entry.line;
+        let Some(r) = track.analyzed_mut() else {
+            continue;
+        };
+        n += 1;
+        loop {
+            writeln!(out, "\n[{}/{}] {}", n, total, r.path)?;
+            writeln!(
+                out,
+                "  duration {:.3}  loudness {:.1} LUFS  cue-in {:.3}  cross {:.3}  gain {:.3} dB",
+                r.duration,
+                r.loudness,
+                r.cue_point,
+                r.start_next,
+                r.amplify()
+            )?;
+            write!(out, "> ")?;
+            out.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                return Ok(Reviewed::Aborted);
+            }
+            let mut words = answer.split_whitespace();
+            let (command, value) = (words.next(), words.next());
+            let value = match value.map(str::parse::<f32>) {
+                Some(Ok(v)) if v.is_finite() => Some(v),
+                Some(_) => {
+                    writeln!(out, "Not a number. {}", HELP)?;
+                    continue;
+                }
+                None => None,
+            };
+
+            let before = (r.cue_point, r.start_next);
+            let field = match (command, value) {
+                (None, _) => continue 'tracks,
+                (Some("a"), None) => break 'tracks,
+                (Some("q"), None) => return Ok(Reviewed::Aborted),
+                (Some("d"), None) => {
+                    *dropped = true;
+                    edits.push(Edit {
+                        path: r.path.clone(),
+                        line,
+                        field: "drop",
+                        value: None,
+                    });
+                    continue 'tracks;
+                }
+                (Some("c"), Some(v)) if v >= 0. => {
+                    r.cue_point = v;
+                    r.cue_disabled = false;
+                    r.loudness_cue = None;
+                    "cue_in"
+                }
+                (Some("x"), Some(v)) if v >= 0. => {
+                    r.start_next = v;
+                    r.cross_disabled = false;
+                    "cross"
+                }
+                (Some("g"), Some(v)) => {
+                    r.amplify_override = Some(v);
+                    "amplify"
+                }
+                _ => {
+                    writeln!(out, "{}", HELP)?;
+                    continue;
+                }
+            };
+            if field == "amplify" {
+                edits.push(Edit {
+                    path: r.path.clone(),
+                    line,
+                    field,
+                    value,
+                });
+                continue;
+            }
+
+            if r.keep_consistent(min_playable) {
+                writeln!(
+                    out,
+                    "That doesn't fit the track, using cue-in {:.3}s and crossfade {:.3}s",
+                    r.cue_point, r.start_next
+                )?;
+            }
+            // the value typed in as it ended up, and the other one if it had to move
+            for (name, was, now) in [
+                ("cue_in", before.0, r.cue_point),
+                ("cross", before.1, r.start_next),
+            ] {
+                if name == field || now != was {
+                    edits.push(Edit {
+                        path: r.path.clone(),
+                        line,
+                        field: name,
+                        value: Some(now),
+                    });
+                }
+            }
+        }
+    }
+
+    let mut dropped = dropped.into_iter();
+    tracks.retain(|_| !dropped.next().unwrap());
+    Ok(Reviewed::Done(edits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::{Entry, Outcome};
+    use crate::AnalyzeResult;
+
+    fn track() -> Track {
+        Track {
+            entry: Entry {
+                path: String::from("a.flac"),
+                line: 3,
+                ..Default::default()
+            },
+            outcome: Outcome::Analyzed(AnalyzeResult {
+                cue_point: 1.,
+                start_next: 5.,
+                duration: 60.,
+                path: String::from("a.flac"),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn reviewed(answers: &str, tracks: &mut Vec<Track>) -> Vec<(&'static str, Option<f32>)> {
+        match review(tracks, 10., answers.as_bytes(), io::sink()).unwrap() {
+            Reviewed::Done(edits) => edits.iter().map(|e| (e.field, e.value)).collect(),
+            Reviewed::Aborted => panic!("aborted"),
+        }
+    }
+
+    #[test]
+    fn edits_are_held_to_the_track() {
+        // a cue-in past the end, and a crossfade that leaves less than 10s playing
+        let mut tracks = vec![track()];
+        let edits = reviewed("c 90\n\n", &mut tracks);
+        let r = tracks[0].analyzed().unwrap();
+        assert_eq!(r.cue_point, 60.);
+        assert_eq!(r.start_next, 0.);
+        assert_eq!(edits, [("cue_in", Some(60.)), ("cross", Some(0.))]);
+
+        let mut tracks = vec![track()];
+        let edits = reviewed("x 55\n\n", &mut tracks);
+        let r = tracks[0].analyzed().unwrap();
+        assert_eq!(r.cue_point, 1.);
+        assert_eq!(r.start_next, 49.);
+        assert!(r.overlap_clamped);
+        assert_eq!(edits, [("cross", Some(49.))]);
+
+        // values that fit are taken as they are
+        let mut tracks = vec![track()];
+        let edits = reviewed("c 2\nx 4\ng -3\n\n", &mut tracks);
+        assert_eq!(
+            edits,
+            [
+                ("cue_in", Some(2.)),
+                ("cross", Some(4.)),
+                ("amplify", Some(-3.))
+            ]
+        );
+    }
+}
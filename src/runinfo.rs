@@ -0,0 +1,133 @@
+use crate::playlist;
+use std::collections::BTreeMap;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Start of the comment lines recording the run that wrote a playlist
+pub const PREFIX: &str = "#BOUNDARIES-RUN:";
+
+// the line between the blocks of --append runs, no key so it isn't read back
+const SEPARATOR: &str = "#BOUNDARIES-RUN:----";
+
+// every block starts with this one, it's how a new one is told apart
+const FIRST_KEY: &str = "generator";
+
+/// What a run writes at the top of its m3u, or of what it appends to one
+#[derive(Clone)]
+pub struct RunInfo {
+    values: Vec<(&'static str, String)>,
+    /// Start with SEPARATOR, for a block appended after another run's
+    separator: bool,
+}
+
+// days since 1970-01-01 to a year, month and day, Howard Hinnant's civil_from_days
+fn civil(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// the current time in UTC, like 2024-03-01T18:30:00Z
+fn now_utc() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+impl RunInfo {
+    /// The program and the time now, and where the playlist came from
+    pub fn new(source: &Path, separator: bool) -> RunInfo {
+        let source = source
+            .canonicalize()
+            .unwrap_or_else(|_| source.to_path_buf());
+        RunInfo {
+            values: vec![
+                (
+                    FIRST_KEY,
+                    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+                ),
+                ("date", now_utc()),
+                ("source", source.to_string_lossy().to_string()),
+            ],
+            separator,
+        }
+    }
+
+    /// Sets a value, replacing the one by that name
+    pub fn set(&mut self, key: &'static str, value: String) {
+        match self.values.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.values.push((key, value)),
+        }
+    }
+
+    /*
+    The comment lines, one value each:
+
+    #BOUNDARIES-RUN:generator=rust-boundaries 0.1.0
+    #BOUNDARIES-RUN:date=2024-03-01T18:30:00Z
+    #BOUNDARIES-RUN:source=/radio/show.m3u
+    #BOUNDARIES-RUN:level=8
+    */
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        if self.separator {
+            s.push_str(SEPARATOR);
+            s.push('\n');
+        }
+        for (key, value) in &self.values {
+            // a line break in a path would end the comment
+            s.push_str(&format!("{}{}={}\n", PREFIX, key, value.replace('\n', " ")));
+        }
+        s
+    }
+}
+
+/// The values of one block, by name
+pub type Block = BTreeMap<String, String>;
+
+/*
+Reads back the blocks of a processed playlist, one per run that wrote to it:
+a single one, or one for every --append. Lines that aren't ours are skipped.
+*/
+pub fn read(path: &Path) -> io::Result<Vec<Block>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in playlist::open_input(path)?.lines() {
+        let line = line?;
+        let Some((key, value)) = line
+            .trim_end_matches('\r')
+            .strip_prefix(PREFIX)
+            .and_then(|l| l.split_once('='))
+        else {
+            continue;
+        };
+        if key == FIRST_KEY || blocks.is_empty() {
+            blocks.push(Block::new());
+        }
+        blocks
+            .last_mut()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+    }
+    Ok(blocks)
+}
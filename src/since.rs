@@ -0,0 +1,212 @@
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// a year, month and day to days since 1970-01-01, Howard Hinnant's days_from_civil
+fn days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// "7d", "36h", "90m", "30s" or "2w", as a length of time
+fn relative(s: &str) -> Option<Duration> {
+    let unit = s.chars().last()?;
+    let n: f64 = s[..s.len() - unit.len_utf8()].trim().parse().ok()?;
+    let seconds = match unit {
+        's' => 1.,
+        'm' => 60.,
+        'h' => 3600.,
+        'd' => 86400.,
+        'w' => 7. * 86400.,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(n * seconds).ok()
+}
+
+// the "+02:00", "-0500" or "Z" at the end of an ISO 8601 time, as seconds east of UTC
+fn offset(zone: &str) -> Option<i64> {
+    if zone.is_empty() || zone == "Z" {
+        return Some(0);
+    }
+    let sign = match zone.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|&c| c != ':').collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/*
+An ISO 8601 date, 2024-03-01, or date and time, 2024-03-01T02:00,
+2024-03-01 02:00:00+01:00. A time without a zone is taken as UTC, like the
+dates in the run header.
+*/
+fn absolute(s: &str) -> Option<SystemTime> {
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, time),
+        None => (s, "00:00"),
+    };
+    let mut ymd = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (ymd.next()??, ymd.next()??, ymd.next()??);
+    if ymd.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let zone_at = time.find(['Z', '+', '-']).unwrap_or(time.len());
+    let mut hms = time[..zone_at].split(':').map(|p| p.parse::<f64>().ok());
+    let (hours, minutes) = (hms.next()??, hms.next()??);
+    let seconds = hms.next().unwrap_or(Some(0.))?;
+    if hms.next().is_some() || hours >= 24. || minutes >= 60. || seconds >= 61. {
+        return None;
+    }
+
+    let secs = days(year, month, day) * 86400 - offset(&time[zone_at..])?;
+    let secs = secs as f64 + hours * 3600. + minutes * 60. + seconds;
+    UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(secs).ok()?)
+}
+
+/// Parses --since: an ISO 8601 date or time, or how long ago, like 24h or 7d
+pub fn parse(s: &str) -> Result<SystemTime, String> {
+    let s = s.trim();
+    if let Some(ago) = relative(s) {
+        return SystemTime::now()
+            .checked_sub(ago)
+            .ok_or_else(|| format!("{} is too long ago", s));
+    }
+    absolute(s).ok_or_else(|| {
+        format!(
+            "{} is neither an ISO 8601 time (2024-03-01, 2024-03-01T02:00:00Z) \
+             nor how long ago (90m, 24h, 7d, 2w)",
+            s
+        )
+    })
+}
+
+/*
+Whether the file at `path` was modified after `since`. One whose time can't be
+read counts as modified, so the analysis finds out what's wrong with it.
+*/
+pub fn modified_after(path: &str, since: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_or(true, |modified| modified > since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn civil_days() {
+        assert_eq!(days(1970, 1, 1), 0);
+        assert_eq!(days(1969, 12, 31), -1);
+        assert_eq!(days(2000, 3, 1), 11017);
+        // the leap day, and the day after it
+        assert_eq!(days(2024, 2, 29), 19782);
+        assert_eq!(days(2024, 3, 1), 19783);
+    }
+
+    #[test]
+    fn relative_times() {
+        assert_eq!(relative("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(relative("90m"), Some(Duration::from_secs(5400)));
+        assert_eq!(relative("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(relative("7d"), Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(relative("2 w"), Some(Duration::from_secs(14 * 86400)));
+        assert_eq!(relative("0d"), Some(Duration::ZERO));
+        assert_eq!(relative("-1d"), None);
+        assert_eq!(relative("7y"), None);
+        assert_eq!(relative("d"), None);
+        assert_eq!(relative("7é"), None);
+        assert_eq!(relative(""), None);
+    }
+
+    #[test]
+    fn zones() {
+        assert_eq!(offset(""), Some(0));
+        assert_eq!(offset("Z"), Some(0));
+        assert_eq!(offset("+02:00"), Some(7200));
+        assert_eq!(offset("-0530"), Some(-19800));
+        assert_eq!(offset("+2"), None);
+        assert_eq!(offset("02:00"), None);
+    }
+
+    #[test]
+    fn absolute_times() {
+        let march = days(2024, 3, 1) as u64 * 86400;
+        assert_eq!(absolute("2024-03-01"), Some(at(march)));
+        assert_eq!(absolute("2024-03-01T02:00"), Some(at(march + 7200)));
+        assert_eq!(absolute("2024-03-01 02:00:30Z"), Some(at(march + 7230)));
+        assert_eq!(
+            absolute("2024-03-01T02:00:00+01:00"),
+            Some(at(march + 3600))
+        );
+        assert_eq!(absolute("2024-03-01T00:00-0100"), Some(at(march + 3600)));
+        assert_eq!(
+            absolute("2024-03-01T00:00:00.5Z"),
+            Some(at(march) + Duration::from_millis(500))
+        );
+        // a leap second is let through
+        assert_eq!(absolute("2024-03-01T00:00:60"), Some(at(march + 60)));
+
+        for wrong in [
+            "2024-13-01",
+            "2024-03-00",
+            "2024-03",
+            "2024-03-01-02",
+            "2024-03-01T24:00",
+            "2024-03-01T02:60",
+            "2024-03-01T02",
+            "2024-03-01T02:00:00:00",
+            "2024-03-01T02:00+2",
+            "1969-12-31",
+        ] {
+            assert_eq!(absolute(wrong), None, "{}", wrong);
+        }
+    }
+
+    #[test]
+    fn parses_either() {
+        let ago = SystemTime::now() - Duration::from_secs(86400);
+        let since = parse(" 24h ").unwrap();
+        assert!(since.duration_since(ago).unwrap() < Duration::from_secs(60));
+        assert_eq!(parse("1970-01-02"), Ok(at(86400)));
+        let e = parse("yesterday").unwrap_err();
+        assert!(e.starts_with("yesterday is neither"), "{}", e);
+    }
+
+    #[test]
+    fn modified_after_is_strict() {
+        let dir = TempDir::new("since");
+        let path = dir.join("a.flac");
+        let file = fs::File::create(&path).unwrap();
+        let mtime = at(1_700_000_000);
+        file.set_modified(mtime).unwrap();
+        let path = path.to_str().unwrap();
+
+        assert!(modified_after(path, mtime - Duration::from_secs(1)));
+        // modified at the very moment isn't after it
+        assert!(!modified_after(path, mtime));
+        assert!(!modified_after(path, mtime + Duration::from_secs(1)));
+        // a file that can't be read is taken for modified
+        assert!(modified_after(
+            dir.join("missing.flac").to_str().unwrap(),
+            SystemTime::now()
+        ));
+    }
+}
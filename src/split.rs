@@ -0,0 +1,51 @@
+use crate::playlist::Track;
+use crate::timeline;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// How --split-every-tracks and --split-every-seconds cut the playlist
+#[derive(Clone, Copy)]
+pub enum Split {
+    Tracks(usize),
+    Seconds(f32),
+}
+
+/*
+The entries of every part, in order, together all of them once. A part takes
+entries until the next one would make it longer than the split allows, by
+count or by its time on air, the way timeline adds it up. An entry is never cut
+or left out to make a part fit: one that's longer on its own than the split
+gets a part of its own. Entries without analysis count as a track but add no
+time.
+*/
+pub fn parts(tracks: &[Track], split: Split) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 0..tracks.len() {
+        let fits = match split {
+            Split::Tracks(n) => i - start < n,
+            Split::Seconds(s) => timeline::timeline(&tracks[start..=i]).total <= s,
+        };
+        if !fits && i > start {
+            parts.push(start..i);
+            start = i;
+        }
+    }
+    if start < tracks.len() {
+        parts.push(start..tracks.len());
+    }
+    parts
+}
+
+/// The file part `n` (from 1) goes to, out-processed-001.m3u8 for out-processed.m3u8
+pub fn part_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:03}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}-{:03}", stem, n),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,150 @@
+use crate::playlist::Entry;
+use crate::AnalyzeResult;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// one row per file, or per CUE track (region is empty for whole files)
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS tracks (
+    path TEXT NOT NULL,
+    region TEXT NOT NULL DEFAULT '',
+    measured_at INTEGER NOT NULL,
+    cue_in REAL NOT NULL,
+    cue_out REAL,
+    leading_silence REAL NOT NULL,
+    cross_duration REAL NOT NULL,
+    duration REAL NOT NULL,
+    loudness REAL NOT NULL,
+    loudness_source TEXT NOT NULL,
+    target REAL NOT NULL,
+    amplify REAL NOT NULL,
+    peak REAL,
+    lra REAL NOT NULL,
+    crest REAL NOT NULL,
+    speechlike INTEGER NOT NULL,
+    profile TEXT NOT NULL,
+    noise_floor REAL,
+    applause INTEGER NOT NULL,
+    cold_ending INTEGER NOT NULL,
+    hidden_track_start REAL,
+    fade_out REAL,
+    fade_type TEXT,
+    channel_imbalance REAL,
+    imbalanced INTEGER NOT NULL,
+    PRIMARY KEY (path, region)
+)";
+
+// the names the JSON output uses for the enums
+fn name<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_value(value).ok()?.as_str().map(String::from)
+}
+
+/// A catalog of analysis results in an SQLite database, written to and never read back, see --cache for that
+pub struct Catalog {
+    conn: Connection,
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> rusqlite::Result<Catalog> {
+        let conn = Connection::open(path)?;
+        // the inputs of --per-input-output each store their tracks, one waits for the other
+        conn.busy_timeout(Duration::from_secs(60))?;
+        conn.execute(SCHEMA, [])?;
+        Ok(Catalog { conn })
+    }
+
+    /// Upserts every track in one transaction, so a failed run leaves the database as it was
+    pub fn store<'a>(
+        &mut self,
+        tracks: impl Iterator<Item = (&'a Entry, &'a AnalyzeResult, bool)>,
+    ) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        for (entry, result, measured) in tracks {
+            upsert(&tx, entry, result, measured)?;
+        }
+        tx.commit()
+    }
+}
+
+/*
+Inserts the track, or updates its row if it's already there. `measured` is
+false for results that came from --cache, which keep the time they were
+measured at when the row exists.
+*/
+fn upsert(
+    conn: &Connection,
+    entry: &Entry,
+    result: &AnalyzeResult,
+    measured: bool,
+) -> rusqlite::Result<()> {
+    let region = match &entry.region {
+        Some(r) => format!(
+            "{}-{}",
+            r.start,
+            r.end.map(|e| e.to_string()).unwrap_or_default()
+        ),
+        None => String::new(),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+
+    conn.execute(
+        "INSERT INTO tracks VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+                ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
+            ON CONFLICT (path, region) DO UPDATE SET
+                measured_at = CASE WHEN ?26 THEN excluded.measured_at ELSE measured_at END,
+                cue_in = excluded.cue_in,
+                cue_out = excluded.cue_out,
+                leading_silence = excluded.leading_silence,
+                cross_duration = excluded.cross_duration,
+                duration = excluded.duration,
+                loudness = excluded.loudness,
+                loudness_source = excluded.loudness_source,
+                target = excluded.target,
+                amplify = excluded.amplify,
+                peak = excluded.peak,
+                lra = excluded.lra,
+                crest = excluded.crest,
+                speechlike = excluded.speechlike,
+                profile = excluded.profile,
+                noise_floor = excluded.noise_floor,
+                applause = excluded.applause,
+                cold_ending = excluded.cold_ending,
+                hidden_track_start = excluded.hidden_track_start,
+                fade_out = excluded.fade_out,
+                fade_type = excluded.fade_type,
+                channel_imbalance = excluded.channel_imbalance,
+                imbalanced = excluded.imbalanced",
+        params![
+            entry.path,
+            region,
+            now,
+            result.cue_point,
+            result.cue_out,
+            result.leading_silence,
+            result.start_next,
+            result.duration,
+            result.loudness,
+            name(&result.loudness_source),
+            result.target,
+            result.amplify(),
+            result.peak,
+            result.lra,
+            result.crest,
+            result.speechlike,
+            name(&result.profile),
+            result.noise_floor,
+            result.applause,
+            result.cold_ending,
+            result.hidden.as_ref().map(|h| h.start),
+            result.fade_out,
+            result.fade_type.as_ref().and_then(name),
+            result.channel_imbalance(),
+            result.imbalanced,
+            measured,
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::output::{LineEnding, M3uWriter, RenderOptions};
+use crate::playlist::Track;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufWriter};
+use std::path::{Path, PathBuf};
+
+/*
+--stream writes the m3u while the analysis is still running. Tracks finish in
+any order, but an entry can only be written once everything before it is, so
+each finished track writes the run of finished entries after the last one
+written. It all goes to a .partial file next to the output, which replaces the
+output once everything is written, so an aborted run leaves the old playlist
+alone.
+*/
+pub struct Stream {
+    path: PathBuf,
+    partial: PathBuf,
+    writer: BufWriter<File>,
+    m3u: M3uWriter,
+    ending: LineEnding,
+    // finished entries waiting for one before them, none when they come in order
+    done: BTreeSet<usize>,
+    next: usize,
+}
+
+impl Stream {
+    pub fn create(path: &Path, opts: RenderOptions, ending: LineEnding) -> io::Result<Stream> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let m3u = M3uWriter::new(opts);
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        writer.write_all(ending.apply(&m3u.header()).as_bytes())?;
+
+        Ok(Stream {
+            path: path.to_path_buf(),
+            partial,
+            writer,
+            m3u,
+            ending,
+            done: BTreeSet::new(),
+            next: 0,
+        })
+    }
+
+    /// Marks entry `i` as finished and writes whatever can be written now.
+    /// `track` gives the finished track at an index.
+    pub fn finish_entry(&mut self, i: usize, track: impl Fn(usize) -> Track) -> io::Result<()> {
+        self.done.insert(i);
+        while self.done.remove(&self.next) {
+            let entry = self.m3u.entry(&track(self.next));
+            self.writer
+                .write_all(self.ending.apply(&entry).as_bytes())?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes out what's left and puts the playlist in place
+    pub fn close(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        fs::rename(&self.partial, &self.path)
+    }
+
+    /// Throws away what was written, for an aborted run
+    pub fn discard(&self) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::{Entry, Outcome};
+    use crate::testing::{self, TempDir};
+
+    #[test]
+    fn entries_are_written_in_order() {
+        let dir = TempDir::new("stream");
+        let out = dir.join("out.m3u8");
+        let opts = testing::render_options(&["--no-header"]);
+        let mut stream = Stream::create(&out, opts, LineEnding::Crlf).unwrap();
+
+        let track = |i: usize| Track {
+            entry: Entry {
+                path: format!("{}.flac", i),
+                line: i + 1,
+                ..Default::default()
+            },
+            outcome: Outcome::Skipped(String::from("unchanged")),
+        };
+        // nothing can be written before the first one is done
+        for i in [2, 1, 4] {
+            stream.finish_entry(i, track).unwrap();
+        }
+        assert!(!out.exists());
+        let partial = dir.join("out.m3u8.partial");
+        stream.writer.flush().unwrap();
+        assert_eq!(fs::read_to_string(&partial).unwrap(), "");
+        stream.finish_entry(0, track).unwrap();
+        stream.finish_entry(3, track).unwrap();
+        stream.close().unwrap();
+
+        assert!(!partial.exists());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "0.flac\r\n1.flac\r\n2.flac\r\n3.flac\r\n4.flac\r\n"
+        );
+    }
+
+    #[test]
+    fn header_with_lf() {
+        let dir = TempDir::new("stream-lf");
+        let out = dir.join("out.m3u8");
+        let opts = testing::render_options(&[]);
+        let mut stream = Stream::create(&out, opts, LineEnding::Lf).unwrap();
+        let track = |_| Track {
+            entry: Entry {
+                path: String::from("a.flac"),
+                directives: vec![String::from("#EXTINF:200,A")],
+                line: 2,
+                ..Default::default()
+            },
+            outcome: Outcome::Skipped(String::from("unchanged")),
+        };
+        stream.finish_entry(0, track).unwrap();
+        stream.close().unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"#EXTM3U\n#EXTINF:200,A\na.flac\n");
+    }
+}
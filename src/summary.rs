@@ -0,0 +1,454 @@
+use crate::albums;
+use crate::output::round3;
+use crate::overlaps;
+use crate::playlist::{Entry, Outcome};
+use crate::review;
+use clap::ArgMatches;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+Version of the --summary-file format. Bumped whenever a field changes meaning
+or goes away; new fields can be added without one.
+*/
+const SCHEMA_VERSION: u64 = 1;
+
+/*
+What the run did, for orchestration that shouldn't have to scrape stdout. It's
+filled in as the run goes and written by `exit`, wherever the run ends, so a
+run that stops early still leaves one. Kept globally since the exits are spread
+all over main and the analysis.
+*/
+static SUMMARY: Mutex<Option<Summary>> = Mutex::new(None);
+
+thread_local! {
+    /*
+    With --per-input-output every input is run on a thread of its own, see
+    batch::run_all, and records its summary there instead.
+    */
+    static INPUT: RefCell<Option<Summary>> = const { RefCell::new(None) };
+}
+
+fn in_input() -> bool {
+    INPUT.with_borrow(Option::is_some)
+}
+
+// the summary being recorded, this thread's input's or the run's
+fn with(f: impl FnOnce(&mut Summary)) {
+    if in_input() {
+        INPUT.with_borrow_mut(|s| f(s.as_mut().unwrap()));
+    } else if let Some(summary) = SUMMARY.lock().unwrap().as_mut() {
+        f(summary);
+    }
+}
+
+#[derive(Serialize)]
+struct Failure {
+    path: String,
+    line: usize,
+    reason: String,
+}
+
+/// A value set by hand in --review
+#[derive(Serialize)]
+struct ManualOverride {
+    path: String,
+    line: usize,
+    /// cue_in, cross, amplify, or drop for a track taken out
+    field: &'static str,
+    value: Option<f32>,
+}
+
+/// One of the files of --split-every-tracks or --split-every-seconds
+#[derive(Serialize)]
+struct Part {
+    path: PathBuf,
+    tracks: usize,
+    /// Seconds on air, with the crossfades
+    duration: f32,
+}
+
+#[derive(Default, Serialize)]
+struct Counts {
+    tracks: usize,
+    analyzed: usize,
+    failed: usize,
+    skipped: usize,
+    cached: usize,
+    /// Tracks with values from --overrides
+    overridden: usize,
+    /// Tracks --qc found clipped
+    clipping: usize,
+    /// Tracks --qc found with a DC offset
+    dc_offset: usize,
+    /// Tracks crossfaded further down for their long tail
+    long_tail: usize,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    #[serde(skip)]
+    file: Option<PathBuf>,
+    schema_version: u64,
+    generator: String,
+    playlist: PathBuf,
+    /// Every option as it applied, given or default, named with underscores for dashes
+    settings: BTreeMap<String, Value>,
+    started_at: u64,
+    finished_at: u64,
+    exit_code: i32,
+    counts: Counts,
+    outputs: Vec<PathBuf>,
+    /// The playlist's parts, when it was split
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parts: Vec<Part>,
+    failures: Vec<Failure>,
+    /// What was printed as a warning, see --warnings-as-errors
+    warnings: Vec<String>,
+    manual_overrides: Vec<ManualOverride>,
+    /// With --overlap-report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overlap_report: Option<overlaps::Report>,
+    /// With --album-report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    albums: Option<Vec<albums::Album>>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// options whose values don't go into the summary, only that they were given
+const SECRETS: &[&str] = &["push_password"];
+
+// the raw values of every option, a list for the ones that take several
+fn settings(matches: &ArgMatches) -> BTreeMap<String, Value> {
+    matches
+        .ids()
+        .filter_map(|id| {
+            if SECRETS.contains(&id.as_str()) {
+                let given = matches.try_get_raw(id.as_str()).ok()?.is_some();
+                return given.then(|| (id.to_string(), Value::from("(redacted)")));
+            }
+            let values: Vec<Value> = matches
+                .try_get_raw(id.as_str())
+                .ok()??
+                .map(|v| Value::from(v.to_string_lossy()))
+                .collect();
+            let value = match <[Value; 1]>::try_from(values) {
+                Ok([one]) => one,
+                Err(many) => Value::from(many),
+            };
+            Some((id.to_string(), value))
+        })
+        .collect()
+}
+
+fn new(file: Option<&Path>, playlist: &Path, matches: &ArgMatches) -> Summary {
+    Summary {
+        file: file.map(Path::to_path_buf),
+        schema_version: SCHEMA_VERSION,
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        playlist: playlist.to_path_buf(),
+        settings: settings(matches),
+        started_at: now(),
+        finished_at: 0,
+        exit_code: 0,
+        counts: Counts::default(),
+        outputs: Vec::new(),
+        parts: Vec::new(),
+        failures: Vec::new(),
+        warnings: Vec::new(),
+        manual_overrides: Vec::new(),
+        overlap_report: None,
+        albums: None,
+    }
+}
+
+/*
+Starts recording a run, to be written to `file` when it ends. Recorded without
+one as well, --warnings-as-errors goes by the warnings in it.
+*/
+pub fn start(file: Option<&Path>, playlist: &Path, matches: &ArgMatches) {
+    // an input's summary is already there from start_input, the file is for all of them
+    if !in_input() {
+        *SUMMARY.lock().unwrap() = Some(new(file, playlist, matches));
+    }
+}
+
+/// Starts recording the run of one of the inputs of --per-input-output, on its thread
+pub fn start_input(playlist: &Path, matches: &ArgMatches) {
+    INPUT.set(Some(new(None, playlist, matches)));
+}
+
+/// Records what became of every entry, `cached` of them from --cache
+pub fn tracks(entries: &[Entry], results: &[Outcome], cached: usize) {
+    with(|summary| {
+        summary.counts = Counts {
+            cached,
+            overridden: summary.counts.overridden,
+            ..Counts::default()
+        };
+        summary.failures.clear();
+        for (entry, outcome) in entries.iter().zip(results) {
+            record(summary, entry, outcome);
+        }
+        // an aborted run has results for only some of them
+        summary.counts.tracks = entries.len();
+    });
+}
+
+/// Records what became of one more entry, for a playlist that isn't kept, see tracks
+pub fn track(entry: &Entry, outcome: &Outcome, cached: bool) {
+    with(|summary| {
+        summary.counts.cached += usize::from(cached);
+        record(summary, entry, outcome);
+    });
+}
+
+fn record(summary: &mut Summary, entry: &Entry, outcome: &Outcome) {
+    let counts = &mut summary.counts;
+    counts.tracks += 1;
+    match outcome {
+        Outcome::Analyzed(r) => {
+            counts.analyzed += 1;
+            counts.clipping += usize::from(r.clipping);
+            counts.dc_offset += usize::from(r.dc_offset_high);
+            counts.long_tail += usize::from(r.long_tail);
+            for warning in r.warnings() {
+                summary
+                    .warnings
+                    .push(format!("{}: {}", entry.path, warning));
+            }
+        }
+        Outcome::Failed(reason) => {
+            counts.failed += 1;
+            summary.failures.push(Failure {
+                path: entry.path.clone(),
+                line: entry.line,
+                reason: reason.clone(),
+            });
+        }
+        Outcome::Skipped(_) => counts.skipped += 1,
+    }
+}
+
+/// Records a warning that was printed
+pub fn warning(message: String) {
+    with(|summary| summary.warnings.push(message));
+}
+
+/// The warnings recorded so far
+pub fn warnings() -> Vec<String> {
+    let mut warnings = Vec::new();
+    with(|summary| warnings = summary.warnings.clone());
+    warnings
+}
+
+/// Records how many tracks had values from --overrides
+pub fn overridden(count: usize) {
+    with(|summary| summary.counts.overridden = count);
+}
+
+/// Records the changes made in --review
+pub fn reviewed(edits: &[review::Edit]) {
+    with(|summary| {
+        summary.manual_overrides = edits
+            .iter()
+            .map(|e| ManualOverride {
+                path: e.path.clone(),
+                line: e.line,
+                field: e.field,
+                value: e.value,
+            })
+            .collect();
+    });
+}
+
+/// Records the --overlap-report
+pub fn overlaps(report: overlaps::Report) {
+    with(|summary| summary.overlap_report = Some(report));
+}
+
+/// Records the --album-report
+pub fn albums(albums: Vec<albums::Album>) {
+    with(|summary| summary.albums = Some(albums));
+}
+
+/// Records a file the run wrote
+pub fn output(path: &Path) {
+    with(|summary| summary.outputs.push(path.to_path_buf()));
+}
+
+/// Records a part of a split playlist, written to `path` as well
+pub fn part(path: &Path, tracks: usize, duration: f32) {
+    with(|summary| {
+        summary.parts.push(Part {
+            path: path.to_path_buf(),
+            tracks,
+            duration: round3(duration),
+        })
+    });
+}
+
+// writes a summary, or whatever else serializes, to `file`
+fn write(file: &Path, value: &impl Serialize) {
+    let written = serde_json::to_string_pretty(value)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(file, json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("Couldn't write the summary {}: {}", file.display(), e);
+    }
+}
+
+/// How the run of one of the inputs of --per-input-output ended
+pub struct Finished {
+    pub code: i32,
+    summary: Summary,
+}
+
+impl Finished {
+    /// One line for the input, its exit code and what became of its tracks
+    pub fn describe(&self) -> String {
+        let counts = &self.summary.counts;
+        format!(
+            "exit {}  {} analyzed, {} failed, {} skipped  {}",
+            self.code,
+            counts.analyzed,
+            counts.failed,
+            counts.skipped,
+            self.summary.playlist.display()
+        )
+    }
+}
+
+/// Ends the recording of this thread's input, with `code`
+pub fn finish(code: i32) -> Finished {
+    let mut summary = INPUT.take().expect("started with start_input");
+    summary.finished_at = now();
+    summary.exit_code = code;
+    Finished { code, summary }
+}
+
+/*
+The --summary-file of --per-input-output: the counts of all the inputs added
+up, the worst exit code, and under `inputs` every input's own summary, as it
+would be on its own.
+*/
+#[derive(Serialize)]
+struct PerInput<'a> {
+    schema_version: u64,
+    generator: String,
+    settings: BTreeMap<String, Value>,
+    started_at: u64,
+    finished_at: u64,
+    exit_code: i32,
+    counts: Counts,
+    inputs: Vec<&'a Summary>,
+}
+
+/// Writes the summary of a --per-input-output run to `file`
+pub fn write_per_input(
+    file: &Path,
+    matches: &ArgMatches,
+    started_at: u64,
+    finished: &[Finished],
+    code: i32,
+) {
+    let summary = PerInput {
+        schema_version: SCHEMA_VERSION,
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        settings: settings(matches),
+        started_at,
+        finished_at: now(),
+        exit_code: code,
+        counts: total(finished),
+        inputs: finished.iter().map(|f| &f.summary).collect(),
+    };
+    write(file, &summary);
+}
+
+// the counts of all the inputs added up
+fn total(finished: &[Finished]) -> Counts {
+    let mut counts = Counts::default();
+    for c in finished.iter().map(|f| &f.summary.counts) {
+        counts.tracks += c.tracks;
+        counts.analyzed += c.analyzed;
+        counts.failed += c.failed;
+        counts.skipped += c.skipped;
+        counts.cached += c.cached;
+        counts.overridden += c.overridden;
+        counts.clipping += c.clipping;
+        counts.dc_offset += c.dc_offset;
+        counts.long_tail += c.long_tail;
+    }
+    counts
+}
+
+/// One line for all the inputs of --per-input-output together
+pub fn describe_total(finished: &[Finished]) -> String {
+    let counts = total(finished);
+    format!(
+        "{} tracks in {} playlists: {} analyzed, {} failed, {} skipped, {} from the cache",
+        counts.tracks,
+        finished.len(),
+        counts.analyzed,
+        counts.failed,
+        counts.skipped,
+        counts.cached
+    )
+}
+
+/*
+Writes the summary, if one is being recorded, and exits with `code`. On the
+thread of an input of --per-input-output only that input's run ends: this
+unwinds back to batch::run_all with its summary, without the panic hook, so
+nothing is printed for it.
+*/
+pub fn exit(code: i32) -> ! {
+    if in_input() {
+        panic::resume_unwind(Box::new(finish(code)));
+    }
+    if let Some(summary) = SUMMARY.lock().unwrap().as_mut() {
+        summary.finished_at = now();
+        summary.exit_code = code;
+        if let Some(file) = &summary.file {
+            write(file, summary);
+        }
+    }
+    std::process::exit(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn passwords_are_redacted() {
+        let matches = crate::Args::command().get_matches_from([
+            env!("CARGO_PKG_NAME"),
+            "playlist.m3u8",
+            "--push-to",
+            "localhost:1234",
+            "--push-password",
+            "hunter2",
+        ]);
+        let settings = settings(&matches);
+        assert_eq!(settings["push_password"], "(redacted)");
+        assert_eq!(settings["push_to"], "localhost:1234");
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("hunter2"));
+    }
+}
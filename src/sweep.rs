@@ -0,0 +1,85 @@
+use serde::Serialize;
+
+/// How sweep results are printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SweepFormat {
+    /// A table per threshold
+    Table,
+    Json,
+}
+
+/// Threshold values to try, from `from` to `to` inclusive
+#[derive(Clone, Copy, Debug)]
+pub struct SweepRange {
+    from: f32,
+    to: f32,
+}
+
+/// Parses FROM..TO, like 4..14
+pub fn parse_range(s: &str) -> Result<SweepRange, String> {
+    let (from, to) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected FROM..TO, got '{}'", s))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f32>()
+            .map_err(|_| format!("'{}' is not a number", v))
+    };
+    let (from, to) = (parse(from)?, parse(to)?);
+    if from > to {
+        return Err(format!("{} is past {}", from, to));
+    }
+    Ok(SweepRange { from, to })
+}
+
+impl SweepRange {
+    /*
+    Every value from `from` up to `to` in `step`s. Each one is counted from
+    `from` rather than added to the last, so 0.1 steps don't drift and the end
+    is included when the range is a whole number of steps.
+    */
+    pub fn values(self, step: f32) -> Vec<f32> {
+        let steps = ((self.to - self.from) / step + 1e-4).floor() as usize;
+        (0..=steps).map(|i| self.from + i as f32 * step).collect()
+    }
+}
+
+/// What one threshold value gives
+#[derive(Serialize)]
+pub struct Point {
+    pub threshold: f32,
+    /// Seconds, the crossfade for --level and the cue-in for --cue
+    pub value: f32,
+}
+
+#[derive(Serialize)]
+pub struct SweepReport {
+    pub path: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub level: Vec<Point>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cue: Vec<Point>,
+}
+
+pub fn render_table(report: &SweepReport) -> String {
+    let mut s = format!("{}\n", report.path);
+    for (points, threshold, value) in [
+        (&report.level, "level", "cross"),
+        (&report.cue, "cue", "cue_in"),
+    ] {
+        if points.is_empty() {
+            continue;
+        }
+        s.push_str(&format!("\n{:>8}  {:>9}\n", threshold, value));
+        for p in points {
+            s.push_str(&format!("{:>8.2}  {:>9.3}\n", p.threshold, p.value));
+        }
+    }
+    s
+}
+
+pub fn render_json(report: &SweepReport) -> String {
+    let mut s = serde_json::to_string_pretty(report).unwrap();
+    s.push('\n');
+    s
+}
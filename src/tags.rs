@@ -0,0 +1,151 @@
+use crate::jobs;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::process::Command;
+
+/// Where a track's integrated loudness came from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoudnessSource {
+    /// Measured by ffmpeg's ebur128 filter
+    #[default]
+    Measured,
+    /// Taken from an R128_TRACK_GAIN tag
+    R128,
+    /// Taken from a REPLAYGAIN_TRACK_GAIN tag
+    ReplayGain,
+}
+
+pub struct TaggedLoudness {
+    pub loudness: f32,
+    pub peak: Option<f32>,
+    pub source: LoudnessSource,
+    /// The container's duration, when ffprobe knows it
+    pub duration: Option<f32>,
+}
+
+// ReplayGain 2.0 gains are relative to -18 LUFS, R128 gains to -23 LUFS
+const REPLAYGAIN_REFERENCE: f32 = -18.;
+const R128_REFERENCE: f32 = -23.;
+
+fn find_tag<'a>(tags: &'a [&Value], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .filter_map(|t| t.as_object())
+        .flat_map(|t| t.iter())
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .and_then(|(_, v)| v.as_str())
+}
+
+/*
+Reads the loudness tags of a file with ffprobe. Containers disagree on where
+tags live (FLAC/MP3 keep them on the format, Opus/Ogg on the stream), so both
+places are searched. R128_TRACK_GAIN wins over REPLAYGAIN_TRACK_GAIN when a
+file has both, since it uses the same reference as our own measurement.
+Returns None when there's no usable gain tag. The duration comes along in the
+same call, so a track that needs nothing but its loudness isn't decoded at all.
+*/
+pub fn read_loudness_tags(path: &str) -> Option<TaggedLoudness> {
+    let probe = jobs::output(
+        Command::new("ffprobe")
+            .arg("-v")
+            .arg("error")
+            .arg("-show_entries")
+            .arg("format=duration:format_tags:stream_tags")
+            .arg("-of")
+            .arg("json")
+            .arg(path),
+    )
+    .ok()?;
+
+    parse_probe(&serde_json::from_slice(&probe.stdout).ok()?)
+}
+
+fn parse_probe(probe: &Value) -> Option<TaggedLoudness> {
+    let mut tags: Vec<&Value> = Vec::new();
+    if let Some(t) = probe.pointer("/format/tags") {
+        tags.push(t);
+    }
+    if let Some(streams) = probe.get("streams").and_then(|s| s.as_array()) {
+        tags.extend(streams.iter().filter_map(|s| s.get("tags")));
+    }
+
+    // REPLAYGAIN_TRACK_PEAK is a linear sample peak, we keep peaks in dBFS
+    let peak = find_tag(&tags, "REPLAYGAIN_TRACK_PEAK")
+        .and_then(|p| p.trim().parse::<f32>().ok())
+        .filter(|p| *p > 0.)
+        .map(|p| 20. * p.log10());
+
+    // ffprobe prints numbers as strings, and "N/A" for a duration it doesn't know
+    let duration = probe
+        .pointer("/format/duration")
+        .and_then(|d| d.as_str())
+        .and_then(|d| d.trim().parse::<f32>().ok())
+        .filter(|d| d.is_finite() && *d > 0.);
+
+    // R128_TRACK_GAIN is a Q7.8 fixed point integer
+    if let Some(gain) =
+        find_tag(&tags, "R128_TRACK_GAIN").and_then(|g| g.trim().parse::<i32>().ok())
+    {
+        return Some(TaggedLoudness {
+            loudness: R128_REFERENCE - gain as f32 / 256.,
+            peak,
+            source: LoudnessSource::R128,
+            duration,
+        });
+    }
+
+    // formatted like "-6.20 dB"
+    let gain = find_tag(&tags, "REPLAYGAIN_TRACK_GAIN")?
+        .trim()
+        .trim_end_matches("dB")
+        .trim()
+        .parse::<f32>()
+        .ok()?;
+
+    Some(TaggedLoudness {
+        loudness: REPLAYGAIN_REFERENCE - gain,
+        peak,
+        source: LoudnessSource::ReplayGain,
+        duration,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn r128_wins_over_replaygain() {
+        let probe = json!({
+            "format": {
+                "duration": "215.040000",
+                "tags": {"REPLAYGAIN_TRACK_GAIN": "-6.20 dB", "REPLAYGAIN_TRACK_PEAK": "0.5"}
+            },
+            "streams": [{"tags": {"R128_TRACK_GAIN": "-512"}}]
+        });
+        let tagged = parse_probe(&probe).unwrap();
+        assert_eq!(tagged.source, LoudnessSource::R128);
+        assert_eq!(tagged.loudness, -21.);
+        assert!((tagged.peak.unwrap() + 6.0206).abs() < 1e-3);
+        assert_eq!(tagged.duration, Some(215.04));
+    }
+
+    #[test]
+    fn replaygain_relative_to_minus_18() {
+        let probe = json!({
+            "format": {"duration": "N/A", "tags": {"replaygain_track_gain": " -6.20 dB"}}
+        });
+        let tagged = parse_probe(&probe).unwrap();
+        assert_eq!(tagged.source, LoudnessSource::ReplayGain);
+        assert!((tagged.loudness + 11.8).abs() < 1e-4);
+        assert_eq!(tagged.peak, None);
+        assert_eq!(tagged.duration, None);
+    }
+
+    #[test]
+    fn no_gain_tag() {
+        let probe = json!({"format": {"duration": "10.0", "tags": {"TITLE": "x"}}});
+        assert!(parse_probe(&probe).is_none());
+    }
+}
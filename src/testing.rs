@@ -0,0 +1,45 @@
+use crate::output::RenderOptions;
+use crate::Args;
+use clap::Parser;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+A directory of a test's own, named after it and the process so tests running
+at the same time don't share one. It starts out empty and is removed when it's
+dropped, so it's cleaned up as well when an assertion fails.
+*/
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("boundaries-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The arguments of a run on playlist.m3u8 with these options
+pub fn args(options: &[&str]) -> Args {
+    let mut argv = vec![env!("CARGO_PKG_NAME"), "playlist.m3u8"];
+    argv.extend(options);
+    Args::parse_from(argv)
+}
+
+/// The render options of a run with these options, without the dated run comments
+pub fn render_options(options: &[&str]) -> RenderOptions {
+    let mut opts = crate::render_options(&args(options), Path::new("playlist.m3u8"), 0);
+    opts.run_info = None;
+    opts
+}
@@ -0,0 +1,92 @@
+use crate::playlist::Track;
+
+pub struct Timeline {
+    /// When each track starts on air, in seconds from the start of the first
+    /// one. None for tracks that weren't analysed.
+    pub starts: Vec<Option<f32>>,
+    /// When the last track ends
+    pub total: f32,
+    /// How many tracks had to be left out of the sum
+    pub missing: usize,
+}
+
+/*
+A track is heard from its cue-in point to its cue-out (or end), and the next one
+starts when `start_next` seconds of it are left, so each track occupies
+cue_out - cue_in - start_next seconds of air before handing over. The last
+track plays out in full. The values used are the final ones that get written,
+so any clamping is already accounted for.
+
+Tracks without results (failed or skipped) have no known length. They're left
+out of the sum, which makes every later start time too early; callers should
+warn about `missing`.
+*/
+pub fn timeline(tracks: &[Track]) -> Timeline {
+    let mut clock = Clock::default();
+    let starts = tracks.iter().map(|t| clock.advance(t)).collect();
+
+    Timeline {
+        starts,
+        total: clock.total,
+        missing: clock.missing,
+    }
+}
+
+/// The timeline one track at a time, for output written as it goes
+#[derive(Default)]
+pub struct Clock {
+    now: f32,
+    total: f32,
+    missing: usize,
+}
+
+impl Clock {
+    /// Adds the next track, returning when it starts
+    pub fn advance(&mut self, track: &Track) -> Option<f32> {
+        let r = match track.analyzed() {
+            Some(r) => r,
+            None => {
+                self.missing += 1;
+                return None;
+            }
+        };
+
+        let start = self.now;
+        // a split off hidden track is a second entry, queued after the main part
+        let (cue_in, heard_before) = match &r.hidden {
+            Some(h) if h.split => (
+                h.start,
+                f32::max(h.main_end - r.cue_point - h.main_cross, 0.),
+            ),
+            _ => (r.cue_point, 0.),
+        };
+        self.now += heard_before;
+        let heard = f32::max(r.cue_out.unwrap_or(r.duration) - cue_in, 0.);
+        self.total = self.total.max(self.now + heard);
+        self.now += f32::max(heard - r.start_next, 0.);
+
+        Some(start)
+    }
+
+    /// When the last track added ends
+    pub fn total(&self) -> f32 {
+        self.total
+    }
+
+    /// How many of the tracks added had to be left out
+    pub fn missing(&self) -> usize {
+        self.missing
+    }
+}
+
+/// Formats seconds as HH:MM:SS.mmm
+pub fn hms(seconds: f32) -> String {
+    let millis = (seconds.max(0.) * 1000.).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/*
+The command as it would be typed, ready to paste into a shell. Arguments with
+anything a shell would take for its own, spaces, quotes, globs, brackets of a
+filter graph, are single quoted.
+*/
+pub fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|a| {
+            let a = a.to_string_lossy();
+            let plain = |c: char| c.is_alphanumeric() || "-_./:=,+@%".contains(c);
+            if a.is_empty() || !a.chars().all(plain) {
+                format!("'{}'", a.replace('\'', r"'\''"))
+            } else {
+                a.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/*
+Writes a track's log to `dir` as <index>-<stem>.log, the index being its
+position in the playlist from 1, since tracks of a CUE sheet all share a stem.
+The directory is only created once there's something to put in it.
+*/
+pub fn write(dir: &Path, index: usize, path: &str, contents: &str) -> io::Result<()> {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(format!("{}-{}.log", index, stem)), contents)
+}
@@ -0,0 +1,99 @@
+use crate::playlist::Entry;
+use std::fs::File;
+use std::process::Command;
+
+/// An entry that won't get far in an analysis run
+pub struct Problem {
+    pub line: usize,
+    pub path: String,
+    pub reason: String,
+}
+
+pub fn is_remote(path: &str) -> bool {
+    ["http://", "https://"].iter().any(|p| path.starts_with(p))
+}
+
+// curl's exit code when --max-time ran out
+const CURL_TIMED_OUT: i32 = 28;
+
+// why a HEAD request failed, from curl's exit code
+fn curl_failure(code: Option<i32>, timeout: f32) -> String {
+    match code {
+        Some(CURL_TIMED_OUT) => format!("no answer within {}s", timeout),
+        _ => String::from("HEAD request failed"),
+    }
+}
+
+/*
+A HEAD request through curl, so we don't need an HTTP client of our own. It
+gets `timeout` seconds all in all, so a host that doesn't answer costs that
+much and not curl's minutes-long connect timeout.
+*/
+fn check_remote(url: &str, timeout: f32) -> Result<(), String> {
+    let status = Command::new("curl")
+        .arg("--head")
+        .arg("--silent")
+        .arg("--fail")
+        .arg("--location")
+        .arg("--max-time")
+        .arg(timeout.to_string())
+        .arg("--output")
+        .arg(if cfg!(windows) { "NUL" } else { "/dev/null" })
+        .arg(url)
+        .status()
+        .map_err(|e| format!("couldn't run curl: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(curl_failure(status.code(), timeout))
+    }
+}
+
+/*
+Checks that every entry's file is there and can be opened, without decoding
+anything, so a broken playlist shows up in seconds instead of halfway through a
+long run. Remote URIs are only checked with `remote`, every one of them costs a
+request of up to `timeout` seconds.
+*/
+pub fn validate(entries: &[Entry], remote: bool, timeout: f32) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for entry in entries {
+        let result = if is_remote(&entry.path) {
+            if !remote {
+                continue;
+            }
+            check_remote(&entry.path, timeout)
+        } else {
+            match File::open(&entry.path) {
+                Ok(f) => match f.metadata() {
+                    Ok(m) if m.is_dir() => Err(String::from("is a directory")),
+                    _ => Ok(()),
+                },
+                Err(e) => Err(e.to_string()),
+            }
+        };
+
+        if let Err(reason) = result {
+            problems.push(Problem {
+                line: entry.line,
+                path: entry.path.clone(),
+                reason,
+            });
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_are_told_apart() {
+        assert_eq!(curl_failure(Some(28), 10.), "no answer within 10s");
+        assert_eq!(curl_failure(Some(22), 2.5), "HEAD request failed");
+        assert_eq!(curl_failure(None, 10.), "HEAD request failed");
+    }
+}
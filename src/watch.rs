@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Set in the environment of the runs --watch starts, so they don't watch in turn
+pub const CHILD_VAR: &str = "BOUNDARIES_WATCH_RUN";
+
+// how often the files are looked at
+const POLL: Duration = Duration::from_secs(1);
+
+// size and modification time of every file watched
+type Snapshot = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+fn scan(path: &Path, snapshot: &mut Snapshot) {
+    let Ok(meta) = fs::metadata(path) else {
+        return;
+    };
+    if meta.is_dir() {
+        let Ok(dir) = fs::read_dir(path) else {
+            return;
+        };
+        for entry in dir.flatten() {
+            scan(&entry.path(), snapshot);
+        }
+    } else {
+        snapshot.insert(path.to_path_buf(), (meta.len(), meta.modified().ok()));
+    }
+}
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in paths {
+        scan(path, &mut snapshot);
+    }
+    snapshot
+}
+
+/*
+Runs this same command again, as a child process, every time one of `paths`
+(the playlist, and the track directories of --watch-dir, with everything in
+them) changes. There's no notifier in the standard library, so the files are
+polled every second, which is cheap next to an analysis. A change starts a run
+once nothing has changed for `debounce`, so saving a playlist several times in
+a row, or copying in a folder of tracks, makes one run and not one per file.
+
+Each run is a process of its own, so one that fails or exits early leaves the
+watch going, and what a run writes itself is part of the snapshot taken after
+it, so an output next to the playlist doesn't set off another. Never returns:
+it runs until it's interrupted.
+*/
+pub fn watch(paths: &[PathBuf], debounce: Duration) -> ! {
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Couldn't find the program to run again: {}", e);
+        std::process::exit(crate::EXIT_FATAL);
+    });
+    let args: Vec<_> = env::args_os().skip(1).collect();
+
+    loop {
+        match Command::new(&exe).args(&args).env(CHILD_VAR, "1").status() {
+            Ok(status) => match status.code() {
+                Some(0) => say!("Watching for changes (Ctrl-C to stop)"),
+                Some(code) => say!(
+                    "The run exited with {}, watching for changes (Ctrl-C to stop)",
+                    code
+                ),
+                // killed by a signal, most likely the Ctrl-C meant for us too
+                None => std::process::exit(crate::EXIT_FATAL),
+            },
+            Err(e) => {
+                eprintln!("Couldn't start a run: {}", e);
+                std::process::exit(crate::EXIT_FATAL);
+            }
+        }
+
+        let mut last = snapshot(paths);
+        loop {
+            thread::sleep(POLL);
+            if snapshot(paths) != last {
+                break;
+            }
+        }
+        // wait for the edits to settle
+        loop {
+            last = snapshot(paths);
+            thread::sleep(debounce);
+            if snapshot(paths) == last {
+                break;
+            }
+        }
+        say!("Changes found, processing again");
+    }
+}